@@ -0,0 +1,34 @@
+use sea_orm::entity::prelude::*;
+
+// a moodle user on a moodle site. everything synced belongs to one of these, as moodle's ids
+// are only unique within a site
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "account")]
+pub struct Model {
+	#[sea_orm(primary_key)]
+	pub id: i32,
+	pub host: String,
+	// the user's id on the site
+	pub user_id: i32,
+	pub user_name: Option<String>,
+	// cleared when signing out while keeping what was synced to read offline. it's only ever
+	// written to the database, which is itself encrypted when encryption is enabled
+	pub token: Option<String>,
+	// set once moodle turns the token down, until the user signs in again
+	pub reauth_required: bool,
+	pub created_at: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+	#[sea_orm(has_many = "super::course::Entity")]
+	Course,
+}
+
+impl Related<super::course::Entity> for Entity {
+	fn to() -> RelationDef {
+		Relation::Course.def()
+	}
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -7,21 +7,34 @@ use specta::Type;
 #[specta(rename = "ContentBlob", rename_all = "camelCase")]
 #[serde(rename_all = "camelCase")]
 pub struct Model {
+	#[sea_orm(primary_key, auto_increment = false)]
+	pub account_id: i32,
 	#[sea_orm(primary_key)]
 	pub name: String,
 	#[sea_orm(primary_key, auto_increment = false)]
 	pub module_id: i32,
+	// the folder moodle keeps the file in, i.e. "/" or "/<chapter id>/" for books
+	#[sea_orm(primary_key, auto_increment = false)]
+	pub file_path: String,
 	pub updated_at: i64,
 	pub mime_type: String,
 	pub path: String,
+	// sha-256 of the file, blobs with the same content share the same file. blobs without
+	// one are missing or broken and get downloaded again on the next sync
+	pub hash: Option<String>,
+	// in bytes, checked along with the hash when verifying storage
+	pub size: Option<i64>,
+	// when the file was removed to stay under the storage cap, it's downloaded again once
+	// the module is opened after this
+	pub evicted_at: Option<i64>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
 	#[sea_orm(
 		belongs_to = "super::section_module::Entity",
-		from = "Column::ModuleId",
-		to = "super::section_module::Column::Id"
+		from = "(Column::AccountId, Column::ModuleId)",
+		to = "(super::section_module::Column::AccountId, super::section_module::Column::Id)"
 	)]
 	SectionModule,
 }
@@ -7,20 +7,36 @@ use specta::Type;
 #[specta(rename = "Course", rename_all = "camelCase")]
 #[serde(rename_all = "camelCase")]
 pub struct Model {
+	#[sea_orm(primary_key, auto_increment = false)]
+	pub account_id: i32,
 	#[sea_orm(primary_key, auto_increment = false)]
 	pub id: i32,
 	pub name: String,
 	pub module_count: i32,
 	pub colour: Option<String>,
 	pub icon: Option<String>,
+	// pinned courses are kept whole when storage is over its cap
+	pub pinned: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+	#[sea_orm(
+		belongs_to = "super::account::Entity",
+		from = "Column::AccountId",
+		to = "super::account::Column::Id"
+	)]
+	Account,
 	#[sea_orm(has_many = "super::course_section::Entity")]
 	CourseSection,
 }
 
+impl Related<super::account::Entity> for Entity {
+	fn to() -> RelationDef {
+		Relation::Account.def()
+	}
+}
+
 impl Related<super::course_section::Entity> for Entity {
 	fn to() -> RelationDef {
 		Relation::CourseSection.def()
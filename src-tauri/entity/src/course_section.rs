@@ -7,24 +7,28 @@ use specta::Type;
 #[specta(rename = "CourseSection")]
 #[serde(rename_all = "camelCase")]
 pub struct Model {
+	#[sea_orm(primary_key, auto_increment = false)]
+	pub account_id: i32,
 	#[sea_orm(primary_key, auto_increment = false)]
 	pub id: i32,
 	pub course_id: i32,
 	pub name: String,
+	// where moodle lists the section within its course
+	pub rank: i32,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
 	#[sea_orm(
 		belongs_to = "super::course::Entity",
-		from = "Column::CourseId",
-		to = "super::course::Column::Id"
+		from = "(Column::AccountId, Column::CourseId)",
+		to = "(super::course::Column::AccountId, super::course::Column::Id)"
 	)]
 	Course,
 	#[sea_orm(
 		has_many = "super::section_module::Entity",
-		from = "Column::Id",
-		to = "super::section_module::Column::SectionId"
+		from = "(Column::AccountId, Column::Id)",
+		to = "(super::section_module::Column::AccountId, super::section_module::Column::SectionId)"
 	)]
 	SectionModule,
 }
@@ -1,11 +1,17 @@
+pub mod account;
 pub mod content_blob;
 pub mod course;
 pub mod course_section;
 pub mod module_content;
+pub mod module_content_version;
 pub mod section_module;
+pub mod sync_log;
 
+pub use account::Entity as Account;
 pub use content_blob::Entity as ContentBlob;
 pub use course::Entity as Course;
 pub use course_section::Entity as CourseSection;
 pub use module_content::Entity as ModuleContent;
+pub use module_content_version::Entity as ModuleContentVersion;
 pub use section_module::Entity as SectionModule;
+pub use sync_log::Entity as SyncLog;
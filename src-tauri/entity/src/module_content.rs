@@ -7,12 +7,15 @@ use specta::Type;
 #[specta(rename = "ModuleContent", rename_all = "camelCase")]
 #[serde(rename_all = "camelCase")]
 pub struct Model {
+	#[sea_orm(primary_key, auto_increment = false)]
+	pub account_id: i32,
 	#[sea_orm(primary_key, auto_increment = false)]
 	pub id: i32,
 	#[sea_orm(primary_key, auto_increment = false)]
 	pub module_id: i32,
 	pub updated_at: i64,
 	pub rank: i32,
+	#[sea_orm(column_type = "Text")]
 	pub content: String,
 }
 
@@ -20,8 +23,8 @@ pub struct Model {
 pub enum Relation {
 	#[sea_orm(
 		belongs_to = "super::section_module::Entity",
-		from = "Column::ModuleId",
-		to = "super::section_module::Column::Id"
+		from = "(Column::AccountId, Column::ModuleId)",
+		to = "(super::section_module::Column::AccountId, super::section_module::Column::Id)"
 	)]
 	SectionModule,
 }
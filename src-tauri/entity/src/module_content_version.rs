@@ -0,0 +1,40 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+// every distinct version of a module's content block, the latest matching what's in
+// module_content. these are kept after the module content is overwritten so edits can be
+// compared
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Type, Serialize, Deserialize)]
+#[sea_orm(table_name = "module_content_version")]
+#[specta(rename = "ModuleContentVersion", rename_all = "camelCase")]
+#[serde(rename_all = "camelCase")]
+pub struct Model {
+	#[sea_orm(primary_key)]
+	pub id: i32,
+	pub account_id: i32,
+	pub module_id: i32,
+	// the id of the module_content block this is a version of
+	pub content_id: i32,
+	#[sea_orm(column_type = "Text")]
+	pub content: String,
+	pub created_at: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+	#[sea_orm(
+		belongs_to = "super::section_module::Entity",
+		from = "(Column::AccountId, Column::ModuleId)",
+		to = "(super::section_module::Column::AccountId, super::section_module::Column::Id)"
+	)]
+	SectionModule,
+}
+
+impl Related<super::section_module::Entity> for Entity {
+	fn to() -> RelationDef {
+		Relation::SectionModule.def()
+	}
+}
+
+impl ActiveModelBehavior for ActiveModel {}
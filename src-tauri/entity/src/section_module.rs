@@ -33,6 +33,8 @@ pub enum SectionModuleType {
 #[specta(rename = "SectionModule", rename_all = "camelCase")]
 #[serde(rename_all = "camelCase")]
 pub struct Model {
+	#[sea_orm(primary_key, auto_increment = false)]
+	pub account_id: i32,
 	#[sea_orm(primary_key, auto_increment = false)]
 	pub id: i32,
 	pub section_id: i32,
@@ -43,26 +45,32 @@ pub struct Model {
 	#[specta(type = Vec<String>)]
 	pub mime_types: Option<serde_json::Value>,
 	pub module_type: SectionModuleType,
+	// never evicted when storage is over its cap
+	pub pinned: bool,
+	// least recently opened modules have their blobs evicted first
+	pub last_opened_at: Option<i64>,
+	// where moodle lists the module within its section
+	pub rank: i32,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
 	#[sea_orm(
 		belongs_to = "super::course_section::Entity",
-		from = "Column::SectionId",
-		to = "super::course_section::Column::Id"
+		from = "(Column::AccountId, Column::SectionId)",
+		to = "(super::course_section::Column::AccountId, super::course_section::Column::Id)"
 	)]
 	Section,
 	#[sea_orm(
 		has_many = "super::module_content::Entity",
-		from = "Column::Id",
-		to = "super::module_content::Column::ModuleId"
+		from = "(Column::AccountId, Column::Id)",
+		to = "(super::module_content::Column::AccountId, super::module_content::Column::ModuleId)"
 	)]
 	ModuleContent,
 	#[sea_orm(
 		has_many = "super::content_blob::Entity",
-		from = "Column::Id",
-		to = "super::content_blob::Column::ModuleId"
+		from = "(Column::AccountId, Column::Id)",
+		to = "(super::content_blob::Column::AccountId, super::content_blob::Column::ModuleId)"
 	)]
 	ContentBlob,
 }
@@ -0,0 +1,24 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Type, Serialize, Deserialize)]
+#[sea_orm(table_name = "sync_log")]
+#[specta(rename = "SyncLog", rename_all = "camelCase")]
+#[serde(rename_all = "camelCase")]
+pub struct Model {
+	#[sea_orm(primary_key, auto_increment = false)]
+	pub id: String,
+	pub last_success_at: Option<i64>,
+	pub last_error_at: Option<i64>,
+	pub last_error_code: Option<String>,
+	pub last_error_message: Option<String>,
+	// duration of the most recent attempt, successful or not
+	pub duration_ms: i64,
+	pub attempt_count: i32,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
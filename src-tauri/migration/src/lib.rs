@@ -1,12 +1,34 @@
 pub use sea_orm_migration::prelude::*;
 
 mod m20250716_094625_create_tables;
+mod m20261018_101500_create_sync_log;
+mod m20261018_113000_content_addressed_blobs;
+mod m20261018_120000_content_blob_file_path;
+mod m20261018_130000_storage_eviction;
+mod m20261018_140000_content_blob_size;
+mod m20261018_150000_create_module_content_version;
+mod m20261018_160000_schema_v2;
+mod m20261018_170000_accounts;
+mod m20261018_180000_content_blob_names;
+mod m20261018_190000_content_blob_sync_logs;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
 	fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-		vec![Box::new(m20250716_094625_create_tables::Migration)]
+		vec![
+			Box::new(m20250716_094625_create_tables::Migration),
+			Box::new(m20261018_101500_create_sync_log::Migration),
+			Box::new(m20261018_113000_content_addressed_blobs::Migration),
+			Box::new(m20261018_120000_content_blob_file_path::Migration),
+			Box::new(m20261018_130000_storage_eviction::Migration),
+			Box::new(m20261018_140000_content_blob_size::Migration),
+			Box::new(m20261018_150000_create_module_content_version::Migration),
+			Box::new(m20261018_160000_schema_v2::Migration),
+			Box::new(m20261018_170000_accounts::Migration),
+			Box::new(m20261018_180000_content_blob_names::Migration),
+			Box::new(m20261018_190000_content_blob_sync_logs::Migration),
+		]
 	}
 }
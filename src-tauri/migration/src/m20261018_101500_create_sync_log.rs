@@ -0,0 +1,58 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum SyncLog {
+	Table,
+	Id,
+	LastSuccessAt,
+	LastErrorAt,
+	LastErrorCode,
+	LastErrorMessage,
+	DurationMs,
+	AttemptCount,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+	async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+		manager
+			.create_table(
+				Table::create()
+					.table(SyncLog::Table)
+					.if_not_exists()
+					.col(
+						ColumnDef::new(SyncLog::Id)
+							.string()
+							.not_null()
+							.primary_key(),
+					)
+					.col(ColumnDef::new(SyncLog::LastSuccessAt).integer().null())
+					.col(ColumnDef::new(SyncLog::LastErrorAt).integer().null())
+					.col(ColumnDef::new(SyncLog::LastErrorCode).string().null())
+					.col(ColumnDef::new(SyncLog::LastErrorMessage).text().null())
+					.col(
+						ColumnDef::new(SyncLog::DurationMs)
+							.integer()
+							.not_null()
+							.default(0),
+					)
+					.col(
+						ColumnDef::new(SyncLog::AttemptCount)
+							.integer()
+							.not_null()
+							.default(0),
+					)
+					.to_owned(),
+			)
+			.await
+	}
+
+	async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+		manager
+			.drop_table(Table::drop().table(SyncLog::Table).to_owned())
+			.await
+	}
+}
@@ -0,0 +1,210 @@
+use std::{
+	collections::HashSet,
+	fs, io,
+	path::{Path, PathBuf},
+};
+
+use sea_orm_migration::{prelude::*, sea_orm::ConnectionTrait};
+use sha2::{Digest, Sha256};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum ContentBlob {
+	Table,
+	Name,
+	ModuleId,
+	Path,
+	Hash,
+}
+
+const HASH_INDEX: &str = "idx_content_blob_hash";
+
+fn io_err(path: &Path, e: io::Error) -> DbErr {
+	DbErr::Custom(format!(
+		"Failed to migrate content blob {}: {}",
+		path.display(),
+		e
+	))
+}
+
+// content_blobs/<dir>/<file_name> -> content_blobs, the same for either layout
+fn blobs_dir(path: &Path) -> Option<&Path> {
+	path.parent().and_then(|module_dir| module_dir.parent())
+}
+
+// keep in sync with blob_store::blob_path
+fn blob_path(blobs_dir: &Path, hash: &str, file_name: &str) -> PathBuf {
+	let extension = Path::new(file_name)
+		.extension()
+		.map(|ext| {
+			ext
+				.to_string_lossy()
+				.chars()
+				.filter(|c| c.is_ascii_alphanumeric())
+				.collect::<String>()
+				.to_lowercase()
+		})
+		.unwrap_or_default();
+	let file_name = if extension.is_empty() {
+		hash.to_string()
+	} else {
+		format!("{hash}.{extension}")
+	};
+
+	blobs_dir.join(&hash[..2]).join(file_name)
+}
+
+fn hash_file(path: &Path) -> io::Result<String> {
+	let mut file = fs::File::open(path)?;
+	let mut hasher = Sha256::new();
+	io::copy(&mut file, &mut hasher)?;
+	Ok(format!("{:x}", hasher.finalize()))
+}
+
+async fn blob_rows(manager: &SchemaManager<'_>) -> Result<Vec<(String, i32, String)>, DbErr> {
+	let db = manager.get_connection();
+	let select = Query::select()
+		.columns([ContentBlob::Name, ContentBlob::ModuleId, ContentBlob::Path])
+		.from(ContentBlob::Table)
+		.to_owned();
+
+	db.query_all(manager.get_database_backend().build(&select))
+		.await?
+		.into_iter()
+		.map(|row| {
+			Ok((
+				row.try_get("", "name")?,
+				row.try_get("", "module_id")?,
+				row.try_get("", "path")?,
+			))
+		})
+		.collect()
+}
+
+async fn update_blob_row(
+	manager: &SchemaManager<'_>,
+	name: &str,
+	module_id: i32,
+	path: &Path,
+	hash: Option<String>,
+) -> Result<(), DbErr> {
+	let update = Query::update()
+		.table(ContentBlob::Table)
+		.values([
+			(ContentBlob::Path, path.to_string_lossy().to_string().into()),
+			(ContentBlob::Hash, hash.into()),
+		])
+		.and_where(Expr::col(ContentBlob::Name).eq(name))
+		.and_where(Expr::col(ContentBlob::ModuleId).eq(module_id))
+		.to_owned();
+
+	manager.exec_stmt(update).await
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+	async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+		manager
+			.alter_table(
+				Table::alter()
+					.table(ContentBlob::Table)
+					.add_column(ColumnDef::new(ContentBlob::Hash).string().null())
+					.to_owned(),
+			)
+			.await?;
+
+		manager
+			.create_index(
+				Index::create()
+					.name(HASH_INDEX)
+					.table(ContentBlob::Table)
+					.col(ContentBlob::Hash)
+					.to_owned(),
+			)
+			.await?;
+
+		// move every stored file into content_blobs/<hash prefix>/<hash>, files with the same
+		// content collapsing into one. rows whose file has gone missing are left without a
+		// hash, which has them downloaded again on the next sync
+		let mut module_dirs = HashSet::new();
+		for (name, module_id, path) in blob_rows(manager).await? {
+			let old_path = PathBuf::from(&path);
+			let Some(blobs_dir) = blobs_dir(&old_path) else {
+				continue;
+			};
+
+			if !old_path.exists() {
+				continue;
+			}
+
+			let hash = hash_file(&old_path).map_err(|e| io_err(&old_path, e))?;
+			let new_path = blob_path(blobs_dir, &hash, &name);
+			if new_path.exists() {
+				fs::remove_file(&old_path).map_err(|e| io_err(&old_path, e))?;
+			} else {
+				fs::create_dir_all(new_path.parent().unwrap()).map_err(|e| io_err(&new_path, e))?;
+				fs::rename(&old_path, &new_path).map_err(|e| io_err(&old_path, e))?;
+			}
+
+			if let Some(module_dir) = old_path.parent() {
+				module_dirs.insert(module_dir.to_path_buf());
+			}
+
+			update_blob_row(manager, &name, module_id, &new_path, Some(hash)).await?;
+		}
+
+		// only removes the old module directories once they're empty
+		for module_dir in module_dirs {
+			fs::remove_dir(module_dir).ok();
+		}
+
+		Ok(())
+	}
+
+	async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+		// every row gets its own copy again, the shared files are removed afterwards
+		let mut shared_files = HashSet::new();
+		for (name, module_id, path) in blob_rows(manager).await? {
+			let shared_path = PathBuf::from(&path);
+			let Some(blobs_dir) = blobs_dir(&shared_path) else {
+				continue;
+			};
+
+			let old_path = blobs_dir.join(module_id.to_string()).join(&name);
+			if shared_path.exists() {
+				fs::create_dir_all(old_path.parent().unwrap()).map_err(|e| io_err(&old_path, e))?;
+				fs::copy(&shared_path, &old_path).map_err(|e| io_err(&shared_path, e))?;
+				shared_files.insert(shared_path);
+			}
+
+			update_blob_row(manager, &name, module_id, &old_path, None).await?;
+		}
+
+		for shared_file in shared_files {
+			fs::remove_file(&shared_file).ok();
+			if let Some(prefix_dir) = shared_file.parent() {
+				fs::remove_dir(prefix_dir).ok();
+			}
+		}
+
+		manager
+			.drop_index(
+				Index::drop()
+					.name(HASH_INDEX)
+					.table(ContentBlob::Table)
+					.to_owned(),
+			)
+			.await?;
+
+		manager
+			.alter_table(
+				Table::alter()
+					.table(ContentBlob::Table)
+					.drop_column(ContentBlob::Hash)
+					.to_owned(),
+			)
+			.await
+	}
+}
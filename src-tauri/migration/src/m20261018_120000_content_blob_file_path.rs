@@ -0,0 +1,128 @@
+use sea_orm_migration::{prelude::*, sea_orm::ConnectionTrait};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum ContentBlob {
+	Table,
+	Name,
+	ModuleId,
+	FilePath,
+	UpdatedAt,
+	MimeType,
+	Path,
+	Hash,
+}
+
+#[derive(DeriveIden)]
+enum ContentBlobNext {
+	Table,
+}
+
+#[derive(DeriveIden)]
+enum SectionModule {
+	Table,
+	Id,
+}
+
+const HASH_INDEX: &str = "idx_content_blob_hash";
+
+// sqlite can't change a table's primary key, so the table is rebuilt with the new key and
+// the existing rows copied over
+async fn rebuild_table(
+	manager: &SchemaManager<'_>,
+	with_file_path: bool,
+	copy_rows: &str,
+) -> Result<(), DbErr> {
+	let mut table = Table::create();
+	table
+		.table(ContentBlobNext::Table)
+		.col(ColumnDef::new(ContentBlob::Name).string().not_null())
+		.col(ColumnDef::new(ContentBlob::ModuleId).integer().not_null());
+
+	let mut primary_key = Index::create();
+	primary_key
+		.col(ContentBlob::ModuleId)
+		.col(ContentBlob::Name);
+	if with_file_path {
+		table.col(
+			ColumnDef::new(ContentBlob::FilePath)
+				.string()
+				.not_null()
+				.default("/"),
+		);
+		primary_key.col(ContentBlob::FilePath);
+	}
+
+	manager
+		.create_table(
+			table
+				.primary_key(&mut primary_key)
+				.col(ColumnDef::new(ContentBlob::UpdatedAt).integer().null())
+				.col(ColumnDef::new(ContentBlob::MimeType).string().not_null())
+				.col(ColumnDef::new(ContentBlob::Path).string().not_null())
+				.col(ColumnDef::new(ContentBlob::Hash).string().null())
+				.foreign_key(
+					ForeignKey::create()
+						.name("fk_content_blob_module_id")
+						.from(ContentBlobNext::Table, ContentBlob::ModuleId)
+						.to(SectionModule::Table, SectionModule::Id)
+						.on_delete(ForeignKeyAction::Cascade),
+				)
+				.to_owned(),
+		)
+		.await?;
+
+	manager
+		.get_connection()
+		.execute_unprepared(copy_rows)
+		.await?;
+	manager
+		.drop_table(Table::drop().table(ContentBlob::Table).to_owned())
+		.await?;
+	manager
+		.rename_table(
+			Table::rename()
+				.table(ContentBlobNext::Table, ContentBlob::Table)
+				.to_owned(),
+		)
+		.await?;
+
+	// indexes go with the old table
+	manager
+		.create_index(
+			Index::create()
+				.name(HASH_INDEX)
+				.table(ContentBlob::Table)
+				.col(ContentBlob::Hash)
+				.to_owned(),
+		)
+		.await
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+	async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+		// we didn't keep track of where files were, but pages and resources keep theirs in the
+		// root folder anyway. anything else is replaced once its module syncs again
+		rebuild_table(
+			manager,
+			true,
+			"INSERT INTO content_blob_next (name, module_id, file_path, updated_at, mime_type, path, hash)
+			SELECT name, module_id, '/', updated_at, mime_type, path, hash FROM content_blob",
+		)
+		.await
+	}
+
+	async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+		// files with the same name in different folders collapse into one row again
+		rebuild_table(
+			manager,
+			false,
+			"INSERT OR REPLACE INTO content_blob_next (name, module_id, updated_at, mime_type, path, hash)
+			SELECT name, module_id, updated_at, mime_type, path, hash FROM content_blob",
+		)
+		.await
+	}
+}
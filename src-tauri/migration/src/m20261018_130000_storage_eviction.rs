@@ -0,0 +1,86 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum Course {
+	Table,
+	Pinned,
+}
+
+#[derive(DeriveIden)]
+enum SectionModule {
+	Table,
+	Pinned,
+	LastOpenedAt,
+}
+
+#[derive(DeriveIden)]
+enum ContentBlob {
+	Table,
+	EvictedAt,
+}
+
+// sqlite only takes one column per alter statement
+fn add_column(table: impl IntoIden + 'static, column: &mut ColumnDef) -> TableAlterStatement {
+	Table::alter().table(table).add_column(column).to_owned()
+}
+
+fn drop_column(table: impl IntoIden + 'static, column: impl IntoIden) -> TableAlterStatement {
+	Table::alter().table(table).drop_column(column).to_owned()
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+	async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+		manager
+			.alter_table(add_column(
+				Course::Table,
+				ColumnDef::new(Course::Pinned)
+					.boolean()
+					.not_null()
+					.default(false),
+			))
+			.await?;
+		manager
+			.alter_table(add_column(
+				SectionModule::Table,
+				ColumnDef::new(SectionModule::Pinned)
+					.boolean()
+					.not_null()
+					.default(false),
+			))
+			.await?;
+		manager
+			.alter_table(add_column(
+				SectionModule::Table,
+				ColumnDef::new(SectionModule::LastOpenedAt).integer().null(),
+			))
+			.await?;
+		manager
+			.alter_table(add_column(
+				ContentBlob::Table,
+				ColumnDef::new(ContentBlob::EvictedAt).integer().null(),
+			))
+			.await
+	}
+
+	async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+		manager
+			.alter_table(drop_column(ContentBlob::Table, ContentBlob::EvictedAt))
+			.await?;
+		manager
+			.alter_table(drop_column(
+				SectionModule::Table,
+				SectionModule::LastOpenedAt,
+			))
+			.await?;
+		manager
+			.alter_table(drop_column(SectionModule::Table, SectionModule::Pinned))
+			.await?;
+		manager
+			.alter_table(drop_column(Course::Table, Course::Pinned))
+			.await
+	}
+}
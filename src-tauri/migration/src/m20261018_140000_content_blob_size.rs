@@ -0,0 +1,36 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum ContentBlob {
+	Table,
+	Size,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+	async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+		// left empty for existing rows, verifying storage fills it in once the file checks out
+		manager
+			.alter_table(
+				Table::alter()
+					.table(ContentBlob::Table)
+					.add_column(ColumnDef::new(ContentBlob::Size).integer().null())
+					.to_owned(),
+			)
+			.await
+	}
+
+	async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+		manager
+			.alter_table(
+				Table::alter()
+					.table(ContentBlob::Table)
+					.drop_column(ContentBlob::Size)
+					.to_owned(),
+			)
+			.await
+	}
+}
@@ -0,0 +1,100 @@
+use sea_orm_migration::{prelude::*, sea_orm::ConnectionTrait};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum ModuleContentVersion {
+	Table,
+	Id,
+	ModuleId,
+	ContentId,
+	Content,
+	CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum SectionModule {
+	Table,
+	Id,
+}
+
+const MODULE_INDEX: &str = "idx_module_content_version_module_id";
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+	async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+		manager
+			.create_table(
+				Table::create()
+					.table(ModuleContentVersion::Table)
+					.if_not_exists()
+					.col(
+						ColumnDef::new(ModuleContentVersion::Id)
+							.integer()
+							.not_null()
+							.auto_increment()
+							.primary_key(),
+					)
+					.col(
+						ColumnDef::new(ModuleContentVersion::ModuleId)
+							.integer()
+							.not_null(),
+					)
+					.col(
+						ColumnDef::new(ModuleContentVersion::ContentId)
+							.integer()
+							.not_null(),
+					)
+					.col(
+						ColumnDef::new(ModuleContentVersion::Content)
+							.text()
+							.not_null(),
+					)
+					.col(
+						ColumnDef::new(ModuleContentVersion::CreatedAt)
+							.integer()
+							.not_null(),
+					)
+					.foreign_key(
+						ForeignKey::create()
+							.name("fk_module_content_version_section_module_id")
+							.from(ModuleContentVersion::Table, ModuleContentVersion::ModuleId)
+							.to(SectionModule::Table, SectionModule::Id)
+							.on_delete(ForeignKeyAction::Cascade),
+					)
+					.to_owned(),
+			)
+			.await?;
+
+		manager
+			.create_index(
+				Index::create()
+					.name(MODULE_INDEX)
+					.table(ModuleContentVersion::Table)
+					.col(ModuleContentVersion::ModuleId)
+					.col(ModuleContentVersion::ContentId)
+					.to_owned(),
+			)
+			.await?;
+
+		// what's stored now becomes the first version, so the next edit has something to be
+		// compared against. resource modules only store the path of their file, so are left out
+		manager
+			.get_connection()
+			.execute_unprepared(
+				"INSERT INTO module_content_version (module_id, content_id, content, created_at)
+				SELECT module_id, id, content, COALESCE(updated_at, 0) FROM module_content
+				WHERE module_id IN (SELECT id FROM section_module WHERE module_type != 3)",
+			)
+			.await?;
+
+		Ok(())
+	}
+
+	async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+		manager
+			.drop_table(Table::drop().table(ModuleContentVersion::Table).to_owned())
+			.await
+	}
+}
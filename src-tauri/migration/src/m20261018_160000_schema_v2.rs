@@ -0,0 +1,375 @@
+use sea_orm_migration::{prelude::*, sea_orm::ConnectionTrait};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum Course {
+	Table,
+	Id,
+}
+
+#[derive(DeriveIden)]
+enum CourseSection {
+	Table,
+	Id,
+	CourseId,
+	Name,
+	Rank,
+}
+
+#[derive(DeriveIden)]
+enum SectionModule {
+	Table,
+	Id,
+	SectionId,
+	Name,
+	UpdatedAt,
+	ModuleType,
+	MimeTypes,
+	Pinned,
+	LastOpenedAt,
+	Rank,
+}
+
+#[derive(DeriveIden)]
+enum ModuleContent {
+	Table,
+	Id,
+	ModuleId,
+	UpdatedAt,
+	Rank,
+	Content,
+}
+
+#[derive(DeriveIden)]
+enum ContentBlob {
+	Table,
+	Name,
+	ModuleId,
+	FilePath,
+	UpdatedAt,
+	MimeType,
+	Path,
+	Hash,
+	EvictedAt,
+	Size,
+}
+
+#[derive(DeriveIden)]
+enum ModuleContentVersion {
+	Table,
+	Id,
+	ModuleId,
+	ContentId,
+	Content,
+	CreatedAt,
+}
+
+// parents first, with the columns both versions of the schema have in common
+const TABLES: [(&str, &str); 5] = [
+	("course_section", "id, course_id, name"),
+	(
+		"section_module",
+		"id, section_id, name, updated_at, module_type, mime_types, pinned, last_opened_at",
+	),
+	("module_content", "id, module_id, updated_at, rank, content"),
+	(
+		"content_blob",
+		"name, module_id, file_path, updated_at, mime_type, path, hash, evicted_at, size",
+	),
+	(
+		"module_content_version",
+		"id, module_id, content_id, content, created_at",
+	),
+];
+
+fn course_section_table(v2: bool) -> TableCreateStatement {
+	let mut table = Table::create();
+	table
+		.table(CourseSection::Table)
+		.col(
+			ColumnDef::new(CourseSection::Id)
+				.integer()
+				.not_null()
+				.primary_key(),
+		)
+		.col(ColumnDef::new(CourseSection::CourseId).integer().not_null())
+		.col(ColumnDef::new(CourseSection::Name).string().not_null())
+		.foreign_key(
+			ForeignKey::create()
+				.name("fk_course_section_course_id")
+				.from(CourseSection::Table, CourseSection::CourseId)
+				.to(Course::Table, Course::Id)
+				.on_delete(ForeignKeyAction::Cascade),
+		);
+
+	// where moodle lists the section within its course
+	if v2 {
+		table.col(
+			ColumnDef::new(CourseSection::Rank)
+				.integer()
+				.not_null()
+				.default(0),
+		);
+	}
+
+	table.to_owned()
+}
+
+fn section_module_table(v2: bool) -> TableCreateStatement {
+	let mut table = Table::create();
+	table
+		.table(SectionModule::Table)
+		.col(
+			ColumnDef::new(SectionModule::Id)
+				.integer()
+				.not_null()
+				.primary_key(),
+		)
+		.col(
+			ColumnDef::new(SectionModule::SectionId)
+				.integer()
+				.not_null(),
+		)
+		.col(ColumnDef::new(SectionModule::Name).string().not_null())
+		.col(ColumnDef::new(SectionModule::UpdatedAt).integer().null())
+		.col(
+			ColumnDef::new(SectionModule::ModuleType)
+				.integer()
+				.not_null(),
+		)
+		.col(ColumnDef::new(SectionModule::MimeTypes).text().null())
+		.col(
+			ColumnDef::new(SectionModule::Pinned)
+				.boolean()
+				.not_null()
+				.default(false),
+		)
+		.col(ColumnDef::new(SectionModule::LastOpenedAt).integer().null())
+		.foreign_key(
+			ForeignKey::create()
+				.name("fk_course_section_item_section_id")
+				.from(SectionModule::Table, SectionModule::SectionId)
+				// this used to name SectionModule::Id, which only worked out because both are "id"
+				.to(CourseSection::Table, CourseSection::Id)
+				.on_delete(ForeignKeyAction::Cascade),
+		);
+
+	// where moodle lists the module within its section
+	if v2 {
+		table.col(
+			ColumnDef::new(SectionModule::Rank)
+				.integer()
+				.not_null()
+				.default(0),
+		);
+	}
+
+	table.to_owned()
+}
+
+fn module_content_table(v2: bool) -> TableCreateStatement {
+	let mut content = ColumnDef::new(ModuleContent::Content);
+	if v2 {
+		content.text();
+	} else {
+		content.string();
+	}
+
+	Table::create()
+		.table(ModuleContent::Table)
+		.col(ColumnDef::new(ModuleContent::Id).integer().not_null())
+		.col(ColumnDef::new(ModuleContent::ModuleId).integer().not_null())
+		.primary_key(
+			Index::create()
+				.col(ModuleContent::Id)
+				.col(ModuleContent::ModuleId),
+		)
+		.col(ColumnDef::new(ModuleContent::UpdatedAt).integer().null())
+		.col(ColumnDef::new(ModuleContent::Rank).integer().not_null())
+		.col(content.not_null())
+		.foreign_key(
+			ForeignKey::create()
+				.name("fk_module_content_section_module_id")
+				.from(ModuleContent::Table, ModuleContent::ModuleId)
+				.to(SectionModule::Table, SectionModule::Id)
+				.on_delete(ForeignKeyAction::Cascade),
+		)
+		.to_owned()
+}
+
+fn content_blob_table() -> TableCreateStatement {
+	Table::create()
+		.table(ContentBlob::Table)
+		.col(ColumnDef::new(ContentBlob::Name).string().not_null())
+		.col(ColumnDef::new(ContentBlob::ModuleId).integer().not_null())
+		.col(
+			ColumnDef::new(ContentBlob::FilePath)
+				.string()
+				.not_null()
+				.default("/"),
+		)
+		.primary_key(
+			Index::create()
+				.col(ContentBlob::ModuleId)
+				.col(ContentBlob::Name)
+				.col(ContentBlob::FilePath),
+		)
+		.col(ColumnDef::new(ContentBlob::UpdatedAt).integer().null())
+		.col(ColumnDef::new(ContentBlob::MimeType).string().not_null())
+		.col(ColumnDef::new(ContentBlob::Path).string().not_null())
+		.col(ColumnDef::new(ContentBlob::Hash).string().null())
+		.col(ColumnDef::new(ContentBlob::EvictedAt).integer().null())
+		.col(ColumnDef::new(ContentBlob::Size).integer().null())
+		.foreign_key(
+			ForeignKey::create()
+				.name("fk_content_blob_module_id")
+				.from(ContentBlob::Table, ContentBlob::ModuleId)
+				.to(SectionModule::Table, SectionModule::Id)
+				.on_delete(ForeignKeyAction::Cascade),
+		)
+		.to_owned()
+}
+
+fn module_content_version_table() -> TableCreateStatement {
+	Table::create()
+		.table(ModuleContentVersion::Table)
+		.col(
+			ColumnDef::new(ModuleContentVersion::Id)
+				.integer()
+				.not_null()
+				.auto_increment()
+				.primary_key(),
+		)
+		.col(
+			ColumnDef::new(ModuleContentVersion::ModuleId)
+				.integer()
+				.not_null(),
+		)
+		.col(
+			ColumnDef::new(ModuleContentVersion::ContentId)
+				.integer()
+				.not_null(),
+		)
+		.col(
+			ColumnDef::new(ModuleContentVersion::Content)
+				.text()
+				.not_null(),
+		)
+		.col(
+			ColumnDef::new(ModuleContentVersion::CreatedAt)
+				.integer()
+				.not_null(),
+		)
+		.foreign_key(
+			ForeignKey::create()
+				.name("fk_module_content_version_section_module_id")
+				.from(ModuleContentVersion::Table, ModuleContentVersion::ModuleId)
+				.to(SectionModule::Table, SectionModule::Id)
+				.on_delete(ForeignKeyAction::Cascade),
+		)
+		.to_owned()
+}
+
+fn indexes(v2: bool) -> Vec<IndexCreateStatement> {
+	let mut indexes = vec![
+		Index::create()
+			.name("idx_content_blob_hash")
+			.table(ContentBlob::Table)
+			.col(ContentBlob::Hash)
+			.to_owned(),
+		Index::create()
+			.name("idx_module_content_version_module_id")
+			.table(ModuleContentVersion::Table)
+			.col(ModuleContentVersion::ModuleId)
+			.col(ModuleContentVersion::ContentId)
+			.to_owned(),
+	];
+
+	// content_blob's primary key already starts with module_id
+	if v2 {
+		indexes.extend([
+			Index::create()
+				.name("idx_course_section_course_id")
+				.table(CourseSection::Table)
+				.col(CourseSection::CourseId)
+				.to_owned(),
+			Index::create()
+				.name("idx_section_module_section_id")
+				.table(SectionModule::Table)
+				.col(SectionModule::SectionId)
+				.to_owned(),
+			Index::create()
+				.name("idx_module_content_module_id")
+				.table(ModuleContent::Table)
+				.col(ModuleContent::ModuleId)
+				.to_owned(),
+		]);
+	}
+
+	indexes
+}
+
+// sqlite can't change constraints or column types in place, so every table below course is
+// rebuilt and the existing rows copied over. sqlx enforces foreign keys, so the old tables
+// are renamed out of the way first: sqlite points their foreign keys at each other's new
+// names, which keeps dropping them afterwards from cascading into the rebuilt tables
+async fn rebuild_tables(manager: &SchemaManager<'_>, v2: bool) -> Result<(), DbErr> {
+	let db = manager.get_connection();
+	for (table, _) in TABLES {
+		manager
+			.rename_table(
+				Table::rename()
+					.table(Alias::new(table), Alias::new(format!("{table}_old")))
+					.to_owned(),
+			)
+			.await?;
+	}
+
+	for table in [
+		course_section_table(v2),
+		section_module_table(v2),
+		module_content_table(v2),
+		content_blob_table(),
+		module_content_version_table(),
+	] {
+		manager.create_table(table).await?;
+	}
+
+	for (table, columns) in TABLES {
+		db.execute_unprepared(&format!(
+			"INSERT INTO {table} ({columns}) SELECT {columns} FROM {table}_old"
+		))
+		.await?;
+	}
+
+	// children first, and the indexes only once the old ones have gone with their tables
+	for (table, _) in TABLES.iter().rev() {
+		manager
+			.drop_table(
+				Table::drop()
+					.table(Alias::new(format!("{table}_old")))
+					.to_owned(),
+			)
+			.await?;
+	}
+
+	for index in indexes(v2) {
+		manager.create_index(index).await?;
+	}
+
+	Ok(())
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+	async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+		// existing rows are ranked 0 and keep their current order until their course syncs
+		rebuild_tables(manager, true).await
+	}
+
+	async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+		rebuild_tables(manager, false).await
+	}
+}
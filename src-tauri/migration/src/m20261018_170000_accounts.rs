@@ -0,0 +1,602 @@
+use sea_orm_migration::{prelude::*, sea_orm::ConnectionTrait};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum Account {
+	Table,
+	Id,
+	Host,
+	UserId,
+	UserName,
+	Token,
+	ReauthRequired,
+	CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Course {
+	Table,
+	AccountId,
+	Id,
+	Name,
+	ModuleCount,
+	Colour,
+	Icon,
+	Pinned,
+}
+
+#[derive(DeriveIden)]
+enum CourseSection {
+	Table,
+	AccountId,
+	Id,
+	CourseId,
+	Name,
+	Rank,
+}
+
+#[derive(DeriveIden)]
+enum SectionModule {
+	Table,
+	AccountId,
+	Id,
+	SectionId,
+	Name,
+	UpdatedAt,
+	ModuleType,
+	MimeTypes,
+	Pinned,
+	LastOpenedAt,
+	Rank,
+}
+
+#[derive(DeriveIden)]
+enum ModuleContent {
+	Table,
+	AccountId,
+	Id,
+	ModuleId,
+	UpdatedAt,
+	Rank,
+	Content,
+}
+
+#[derive(DeriveIden)]
+enum ContentBlob {
+	Table,
+	AccountId,
+	Name,
+	ModuleId,
+	FilePath,
+	UpdatedAt,
+	MimeType,
+	Path,
+	Hash,
+	EvictedAt,
+	Size,
+}
+
+#[derive(DeriveIden)]
+enum ModuleContentVersion {
+	Table,
+	AccountId,
+	Id,
+	ModuleId,
+	ContentId,
+	Content,
+	CreatedAt,
+}
+
+// parents first, with the columns both versions of the schema have in common
+const TABLES: [(&str, &str); 6] = [
+	("course", "id, name, module_count, colour, icon, pinned"),
+	("course_section", "id, course_id, name, rank"),
+	(
+		"section_module",
+		"id, section_id, name, updated_at, module_type, mime_types, pinned, last_opened_at, rank",
+	),
+	("module_content", "id, module_id, updated_at, rank, content"),
+	(
+		"content_blob",
+		"name, module_id, file_path, updated_at, mime_type, path, hash, evicted_at, size",
+	),
+	(
+		"module_content_version",
+		"id, module_id, content_id, content, created_at",
+	),
+];
+
+// whatever was synced before accounts existed belongs to this one. its host and user are
+// filled in from the store on the next start, which the migration can't get at
+const LEGACY_ACCOUNT_ID: i32 = 1;
+
+fn account_table() -> TableCreateStatement {
+	Table::create()
+		.table(Account::Table)
+		.col(
+			ColumnDef::new(Account::Id)
+				.integer()
+				.not_null()
+				.auto_increment()
+				.primary_key(),
+		)
+		.col(ColumnDef::new(Account::Host).string().not_null())
+		.col(ColumnDef::new(Account::UserId).integer().not_null())
+		.col(ColumnDef::new(Account::UserName).string().null())
+		.col(ColumnDef::new(Account::Token).string().null())
+		.col(
+			ColumnDef::new(Account::ReauthRequired)
+				.boolean()
+				.not_null()
+				.default(false),
+		)
+		.col(ColumnDef::new(Account::CreatedAt).integer().not_null())
+		.to_owned()
+}
+
+fn course_table(namespaced: bool) -> TableCreateStatement {
+	let mut table = Table::create();
+	table.table(Course::Table);
+	if namespaced {
+		table
+			.col(ColumnDef::new(Course::AccountId).integer().not_null())
+			.col(ColumnDef::new(Course::Id).integer().not_null())
+			.primary_key(Index::create().col(Course::AccountId).col(Course::Id))
+			.foreign_key(
+				ForeignKey::create()
+					.name("fk_course_account_id")
+					.from(Course::Table, Course::AccountId)
+					.to(Account::Table, Account::Id)
+					.on_delete(ForeignKeyAction::Cascade),
+			);
+	} else {
+		table.col(
+			ColumnDef::new(Course::Id)
+				.integer()
+				.not_null()
+				.primary_key(),
+		);
+	}
+
+	table
+		.col(ColumnDef::new(Course::Name).string().not_null())
+		.col(ColumnDef::new(Course::ModuleCount).integer().not_null())
+		.col(ColumnDef::new(Course::Colour).string().null())
+		.col(ColumnDef::new(Course::Icon).string().null())
+		.col(
+			ColumnDef::new(Course::Pinned)
+				.boolean()
+				.not_null()
+				.default(false),
+		)
+		.to_owned()
+}
+
+fn course_section_table(namespaced: bool) -> TableCreateStatement {
+	let mut table = Table::create();
+	table.table(CourseSection::Table);
+	if namespaced {
+		table
+			.col(
+				ColumnDef::new(CourseSection::AccountId)
+					.integer()
+					.not_null(),
+			)
+			.col(ColumnDef::new(CourseSection::Id).integer().not_null())
+			.primary_key(
+				Index::create()
+					.col(CourseSection::AccountId)
+					.col(CourseSection::Id),
+			)
+			.foreign_key(
+				ForeignKey::create()
+					.name("fk_course_section_course_id")
+					.from(
+						CourseSection::Table,
+						(CourseSection::AccountId, CourseSection::CourseId),
+					)
+					.to(Course::Table, (Course::AccountId, Course::Id))
+					.on_delete(ForeignKeyAction::Cascade),
+			);
+	} else {
+		table
+			.col(
+				ColumnDef::new(CourseSection::Id)
+					.integer()
+					.not_null()
+					.primary_key(),
+			)
+			.foreign_key(
+				ForeignKey::create()
+					.name("fk_course_section_course_id")
+					.from(CourseSection::Table, CourseSection::CourseId)
+					.to(Course::Table, Course::Id)
+					.on_delete(ForeignKeyAction::Cascade),
+			);
+	}
+
+	table
+		.col(ColumnDef::new(CourseSection::CourseId).integer().not_null())
+		.col(ColumnDef::new(CourseSection::Name).string().not_null())
+		.col(
+			ColumnDef::new(CourseSection::Rank)
+				.integer()
+				.not_null()
+				.default(0),
+		)
+		.to_owned()
+}
+
+fn section_module_table(namespaced: bool) -> TableCreateStatement {
+	let mut table = Table::create();
+	table.table(SectionModule::Table);
+	if namespaced {
+		table
+			.col(
+				ColumnDef::new(SectionModule::AccountId)
+					.integer()
+					.not_null(),
+			)
+			.col(ColumnDef::new(SectionModule::Id).integer().not_null())
+			.primary_key(
+				Index::create()
+					.col(SectionModule::AccountId)
+					.col(SectionModule::Id),
+			)
+			.foreign_key(
+				ForeignKey::create()
+					.name("fk_course_section_item_section_id")
+					.from(
+						SectionModule::Table,
+						(SectionModule::AccountId, SectionModule::SectionId),
+					)
+					.to(
+						CourseSection::Table,
+						(CourseSection::AccountId, CourseSection::Id),
+					)
+					.on_delete(ForeignKeyAction::Cascade),
+			);
+	} else {
+		table
+			.col(
+				ColumnDef::new(SectionModule::Id)
+					.integer()
+					.not_null()
+					.primary_key(),
+			)
+			.foreign_key(
+				ForeignKey::create()
+					.name("fk_course_section_item_section_id")
+					.from(SectionModule::Table, SectionModule::SectionId)
+					.to(CourseSection::Table, CourseSection::Id)
+					.on_delete(ForeignKeyAction::Cascade),
+			);
+	}
+
+	table
+		.col(
+			ColumnDef::new(SectionModule::SectionId)
+				.integer()
+				.not_null(),
+		)
+		.col(ColumnDef::new(SectionModule::Name).string().not_null())
+		.col(ColumnDef::new(SectionModule::UpdatedAt).integer().null())
+		.col(
+			ColumnDef::new(SectionModule::ModuleType)
+				.integer()
+				.not_null(),
+		)
+		.col(ColumnDef::new(SectionModule::MimeTypes).text().null())
+		.col(
+			ColumnDef::new(SectionModule::Pinned)
+				.boolean()
+				.not_null()
+				.default(false),
+		)
+		.col(ColumnDef::new(SectionModule::LastOpenedAt).integer().null())
+		.col(
+			ColumnDef::new(SectionModule::Rank)
+				.integer()
+				.not_null()
+				.default(0),
+		)
+		.to_owned()
+}
+
+// module content, blobs and versions all point at their module the same way
+fn module_foreign_key(
+	namespaced: bool,
+	name: &str,
+	table: impl IntoIden + 'static,
+	account_id: impl IntoIden + 'static,
+	module_id: impl IntoIden + 'static,
+) -> ForeignKeyCreateStatement {
+	let mut foreign_key = ForeignKey::create();
+	foreign_key.name(name).on_delete(ForeignKeyAction::Cascade);
+	if namespaced {
+		foreign_key.from(table, (account_id, module_id)).to(
+			SectionModule::Table,
+			(SectionModule::AccountId, SectionModule::Id),
+		);
+	} else {
+		foreign_key
+			.from(table, module_id)
+			.to(SectionModule::Table, SectionModule::Id);
+	}
+
+	foreign_key.to_owned()
+}
+
+fn module_content_table(namespaced: bool) -> TableCreateStatement {
+	let mut table = Table::create();
+	table.table(ModuleContent::Table);
+	let mut primary_key = Index::create();
+	if namespaced {
+		table.col(
+			ColumnDef::new(ModuleContent::AccountId)
+				.integer()
+				.not_null(),
+		);
+		primary_key.col(ModuleContent::AccountId);
+	}
+
+	table
+		.col(ColumnDef::new(ModuleContent::Id).integer().not_null())
+		.col(ColumnDef::new(ModuleContent::ModuleId).integer().not_null())
+		.primary_key(
+			primary_key
+				.col(ModuleContent::Id)
+				.col(ModuleContent::ModuleId),
+		)
+		.col(ColumnDef::new(ModuleContent::UpdatedAt).integer().null())
+		.col(ColumnDef::new(ModuleContent::Rank).integer().not_null())
+		.col(ColumnDef::new(ModuleContent::Content).text().not_null())
+		.foreign_key(&mut module_foreign_key(
+			namespaced,
+			"fk_module_content_section_module_id",
+			ModuleContent::Table,
+			ModuleContent::AccountId,
+			ModuleContent::ModuleId,
+		))
+		.to_owned()
+}
+
+fn content_blob_table(namespaced: bool) -> TableCreateStatement {
+	let mut table = Table::create();
+	table.table(ContentBlob::Table);
+	let mut primary_key = Index::create();
+	if namespaced {
+		table.col(ColumnDef::new(ContentBlob::AccountId).integer().not_null());
+		primary_key.col(ContentBlob::AccountId);
+	}
+
+	table
+		.col(ColumnDef::new(ContentBlob::Name).string().not_null())
+		.col(ColumnDef::new(ContentBlob::ModuleId).integer().not_null())
+		.col(
+			ColumnDef::new(ContentBlob::FilePath)
+				.string()
+				.not_null()
+				.default("/"),
+		)
+		.primary_key(
+			primary_key
+				.col(ContentBlob::ModuleId)
+				.col(ContentBlob::Name)
+				.col(ContentBlob::FilePath),
+		)
+		.col(ColumnDef::new(ContentBlob::UpdatedAt).integer().null())
+		.col(ColumnDef::new(ContentBlob::MimeType).string().not_null())
+		.col(ColumnDef::new(ContentBlob::Path).string().not_null())
+		.col(ColumnDef::new(ContentBlob::Hash).string().null())
+		.col(ColumnDef::new(ContentBlob::EvictedAt).integer().null())
+		.col(ColumnDef::new(ContentBlob::Size).integer().null())
+		.foreign_key(&mut module_foreign_key(
+			namespaced,
+			"fk_content_blob_module_id",
+			ContentBlob::Table,
+			ContentBlob::AccountId,
+			ContentBlob::ModuleId,
+		))
+		.to_owned()
+}
+
+fn module_content_version_table(namespaced: bool) -> TableCreateStatement {
+	let mut table = Table::create();
+	table.table(ModuleContentVersion::Table).col(
+		ColumnDef::new(ModuleContentVersion::Id)
+			.integer()
+			.not_null()
+			.auto_increment()
+			.primary_key(),
+	);
+	if namespaced {
+		table.col(
+			ColumnDef::new(ModuleContentVersion::AccountId)
+				.integer()
+				.not_null(),
+		);
+	}
+
+	table
+		.col(
+			ColumnDef::new(ModuleContentVersion::ModuleId)
+				.integer()
+				.not_null(),
+		)
+		.col(
+			ColumnDef::new(ModuleContentVersion::ContentId)
+				.integer()
+				.not_null(),
+		)
+		.col(
+			ColumnDef::new(ModuleContentVersion::Content)
+				.text()
+				.not_null(),
+		)
+		.col(
+			ColumnDef::new(ModuleContentVersion::CreatedAt)
+				.integer()
+				.not_null(),
+		)
+		.foreign_key(&mut module_foreign_key(
+			namespaced,
+			"fk_module_content_version_section_module_id",
+			ModuleContentVersion::Table,
+			ModuleContentVersion::AccountId,
+			ModuleContentVersion::ModuleId,
+		))
+		.to_owned()
+}
+
+fn indexes(namespaced: bool) -> Vec<IndexCreateStatement> {
+	let mut course_section = Index::create();
+	let mut section_module = Index::create();
+	let mut module_content = Index::create();
+	let mut module_content_version = Index::create();
+	if namespaced {
+		course_section.col(CourseSection::AccountId);
+		section_module.col(SectionModule::AccountId);
+		module_content.col(ModuleContent::AccountId);
+		module_content_version.col(ModuleContentVersion::AccountId);
+	}
+
+	// content_blob's primary key already starts with (the account and) module_id
+	vec![
+		Index::create()
+			.name("idx_content_blob_hash")
+			.table(ContentBlob::Table)
+			.col(ContentBlob::Hash)
+			.to_owned(),
+		module_content_version
+			.name("idx_module_content_version_module_id")
+			.table(ModuleContentVersion::Table)
+			.col(ModuleContentVersion::ModuleId)
+			.col(ModuleContentVersion::ContentId)
+			.to_owned(),
+		course_section
+			.name("idx_course_section_course_id")
+			.table(CourseSection::Table)
+			.col(CourseSection::CourseId)
+			.to_owned(),
+		section_module
+			.name("idx_section_module_section_id")
+			.table(SectionModule::Table)
+			.col(SectionModule::SectionId)
+			.to_owned(),
+		module_content
+			.name("idx_module_content_module_id")
+			.table(ModuleContent::Table)
+			.col(ModuleContent::ModuleId)
+			.to_owned(),
+	]
+}
+
+// the same rebuild as schema_v2, this time including course. rows are copied into the
+// legacy account on the way up, and only the first account's rows are kept on the way down
+// as the ids of different sites would collide
+async fn rebuild_tables(manager: &SchemaManager<'_>, namespaced: bool) -> Result<(), DbErr> {
+	let db = manager.get_connection();
+	for (table, _) in TABLES {
+		manager
+			.rename_table(
+				Table::rename()
+					.table(Alias::new(table), Alias::new(format!("{table}_old")))
+					.to_owned(),
+			)
+			.await?;
+	}
+
+	for table in [
+		course_table(namespaced),
+		course_section_table(namespaced),
+		section_module_table(namespaced),
+		module_content_table(namespaced),
+		content_blob_table(namespaced),
+		module_content_version_table(namespaced),
+	] {
+		manager.create_table(table).await?;
+	}
+
+	for (table, columns) in TABLES {
+		let statement = if namespaced {
+			format!(
+				"INSERT INTO {table} (account_id, {columns}) SELECT {LEGACY_ACCOUNT_ID}, {columns} FROM {table}_old"
+			)
+		} else {
+			format!(
+				"INSERT INTO {table} ({columns}) SELECT {columns} FROM {table}_old WHERE account_id = (SELECT MIN(id) FROM account)"
+			)
+		};
+		db.execute_unprepared(&statement).await?;
+	}
+
+	// children first, and the indexes only once the old ones have gone with their tables
+	for (table, _) in TABLES.iter().rev() {
+		manager
+			.drop_table(
+				Table::drop()
+					.table(Alias::new(format!("{table}_old")))
+					.to_owned(),
+			)
+			.await?;
+	}
+
+	for index in indexes(namespaced) {
+		manager.create_index(index).await?;
+	}
+
+	Ok(())
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+	async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+		let db = manager.get_connection();
+		manager.create_table(account_table()).await?;
+		manager
+			.create_index(
+				Index::create()
+					.name("idx_account_host_user_id")
+					.table(Account::Table)
+					.col(Account::Host)
+					.col(Account::UserId)
+					.unique()
+					.to_owned(),
+			)
+			.await?;
+
+		// only when there's something for it to own, otherwise the first sign in gets an
+		// account of its own
+		db.execute_unprepared(&format!(
+			"INSERT INTO account (id, host, user_id, reauth_required, created_at) \
+			SELECT {LEGACY_ACCOUNT_ID}, '', 0, 0, strftime('%s', 'now') WHERE EXISTS (SELECT 1 FROM course)"
+		))
+		.await?;
+		rebuild_tables(manager, true).await?;
+
+		// sync ids are namespaced by account the same way, i.e. sync_task_get_course_2 becomes
+		// sync_task_account_1_get_course_2. without a legacy account they'd have nothing to
+		// throttle
+		db.execute_unprepared(&format!(
+			"UPDATE sync_log SET id = 'sync_task_account_{LEGACY_ACCOUNT_ID}_' || substr(id, length('sync_task_') + 1) \
+			WHERE EXISTS (SELECT 1 FROM account)"
+		))
+		.await?;
+		db.execute_unprepared("DELETE FROM sync_log WHERE id NOT LIKE 'sync_task_account_%'")
+			.await?;
+		Ok(())
+	}
+
+	async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+		let db = manager.get_connection();
+		rebuild_tables(manager, false).await?;
+		// forgetting when things last synced only means they're synced again
+		db.execute_unprepared("DELETE FROM sync_log").await?;
+		manager
+			.drop_table(Table::drop().table(Account::Table).to_owned())
+			.await
+	}
+}
@@ -0,0 +1,58 @@
+use std::path::Path;
+
+use sea_orm_migration::{prelude::*, sea_orm::ConnectionTrait};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum ContentBlob {
+	Table,
+	Name,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+	async fn up(&self, _manager: &SchemaManager) -> Result<(), DbErr> {
+		// nothing changes going up, names are only ever joined onto a path when going back
+		// down to blobs stored per module
+		Ok(())
+	}
+
+	async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+		// runs before the content addressed blobs migration is undone, which copies each blob
+		// back to <module id>/<name>. rows whose name isn't just a file name (i.e. "../file")
+		// could point that outside the module's directory, so they're dropped first
+		let db = manager.get_connection();
+		let select = Query::select()
+			.column(ContentBlob::Name)
+			.from(ContentBlob::Table)
+			.to_owned();
+		let names = db
+			.query_all(manager.get_database_backend().build(&select))
+			.await?
+			.into_iter()
+			.map(|row| row.try_get::<String>("", "name"))
+			.collect::<Result<Vec<_>, _>>()?;
+
+		let unsafe_names = names
+			.into_iter()
+			.filter(|name| {
+				Path::new(name)
+					.file_name()
+					.is_none_or(|file_name| file_name != name.as_str())
+			})
+			.collect::<Vec<_>>();
+		if unsafe_names.is_empty() {
+			return Ok(());
+		}
+
+		let delete = Query::delete()
+			.from_table(ContentBlob::Table)
+			.and_where(Expr::col(ContentBlob::Name).is_in(unsafe_names))
+			.to_owned();
+		db.execute(manager.get_database_backend().build(&delete))
+			.await
+			.map(|_| ())
+	}
+}
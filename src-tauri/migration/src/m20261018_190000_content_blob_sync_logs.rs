@@ -0,0 +1,31 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum SyncLog {
+	Table,
+	Id,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+	async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+		// get_content_blobs used to go through a sync task that never synced anything, which
+		// still logged a run per module
+		manager
+			.exec_stmt(
+				Query::delete()
+					.from_table(SyncLog::Table)
+					.and_where(Expr::col(SyncLog::Id).like("%get_content_blobs_%"))
+					.to_owned(),
+			)
+			.await
+	}
+
+	async fn down(&self, _manager: &SchemaManager) -> Result<(), DbErr> {
+		// nothing to restore, the rows never held anything worth keeping
+		Ok(())
+	}
+}
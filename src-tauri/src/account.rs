@@ -0,0 +1,386 @@
+use anyhow::Context;
+use sea_orm::{
+	ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect,
+	sea_query,
+};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use sqlx::types::chrono::Utc;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_store::StoreExt;
+
+use entity::account::Model as Account;
+
+use crate::{
+	auth::auth_keys,
+	blob_store,
+	database::DatabaseState,
+	encryption,
+	scheduler::SchedulerState,
+	sync_task::{self, SyncError},
+};
+
+// moodle's ids are only unique within a site, so everything synced is stored under the
+// account it was synced for. one account is active at a time, which is the one commands
+// read from, while background syncs go through every signed in account
+
+// the migration gives anything synced before there were accounts to this one, its host and
+// user are filled in from the store on the next start
+const LEGACY_ACCOUNT_ID: i32 = 1;
+
+// what the ui gets to see of an account, the token stays put
+#[derive(Serialize, Deserialize, Type, Debug, Clone)]
+pub struct AccountInfo {
+	pub id: i32,
+	pub host: String,
+	pub user_name: Option<String>,
+	// signed out accounts keep whatever was synced to read offline
+	pub signed_in: bool,
+	pub reauth_required: bool,
+	pub active: bool,
+}
+
+// what a sync needs to talk to moodle as an account
+pub struct Credentials {
+	pub host: String,
+	pub token: String,
+	pub user_id: i32,
+}
+
+/// the account commands act on
+pub fn active_id(app: &AppHandle) -> Option<i32> {
+	app
+		.store("store.json")
+		.ok()
+		.and_then(|store| store.get(auth_keys::ACCOUNT_ID))
+		.and_then(|id| id.as_i64())
+		.map(|id| id as i32)
+}
+
+pub(crate) fn require_active(app: &AppHandle) -> Result<i32, String> {
+	active_id(app).ok_or_else(|| "No account has been signed in to".to_string())
+}
+
+fn set_active(app: &AppHandle, account_id: Option<i32>) -> anyhow::Result<()> {
+	let store = app.store("store.json")?;
+	match account_id {
+		Some(account_id) => store.set(auth_keys::ACCOUNT_ID, account_id),
+		None => {
+			store.delete(auth_keys::ACCOUNT_ID);
+		}
+	}
+
+	store.save()?;
+	Ok(())
+}
+
+pub async fn find(app: &AppHandle, account_id: i32) -> anyhow::Result<Option<Account>> {
+	let state = app.state::<DatabaseState>();
+	entity::Account::find_by_id(account_id)
+		.one(&state.0)
+		.await
+		.with_context(|| format!("Failed to query account {}", account_id))
+}
+
+pub async fn active(app: &AppHandle) -> anyhow::Result<Option<Account>> {
+	match active_id(app) {
+		Some(account_id) => find(app, account_id).await,
+		None => Ok(None),
+	}
+}
+
+/// whether the active account has a token, even one moodle has since turned down
+pub async fn is_signed_in(app: &AppHandle) -> bool {
+	active(app)
+		.await
+		.unwrap_or_else(|e| {
+			log::error!("Failed to query active account: {:#}", e);
+			None
+		})
+		.is_some_and(|account| account.token.is_some())
+}
+
+/// the host and token to sync the account with. signed out accounts, and those whose token
+/// moodle turned down, don't sync at all
+pub async fn credentials(app: &AppHandle, account_id: i32) -> Result<Credentials, SyncError> {
+	let account = find(app, account_id)
+		.await?
+		.ok_or_else(SyncError::signed_out)?;
+	if account.reauth_required {
+		return Err(SyncError::reauth_required());
+	}
+
+	Ok(Credentials {
+		token: account.token.ok_or_else(SyncError::signed_out)?,
+		host: account.host,
+		user_id: account.user_id,
+	})
+}
+
+async fn signed_in_ids(app: &AppHandle, syncable: bool) -> anyhow::Result<Vec<i32>> {
+	let state = app.state::<DatabaseState>();
+	let mut query = entity::Account::find()
+		.select_only()
+		.column(entity::account::Column::Id)
+		.filter(entity::account::Column::Token.is_not_null());
+	if syncable {
+		query = query.filter(entity::account::Column::ReauthRequired.eq(false));
+	}
+
+	query
+		.order_by_asc(entity::account::Column::Id)
+		.into_tuple::<i32>()
+		.all(&state.0)
+		.await
+		.with_context(|| "Failed to query signed in accounts")
+}
+
+/// accounts with a token moodle still accepts, which the scheduler goes through
+pub async fn syncable_ids(app: &AppHandle) -> anyhow::Result<Vec<i32>> {
+	signed_in_ids(app, true).await
+}
+
+// adds the account, or signs in to it again when we already know the user on this host
+async fn upsert(
+	app: &AppHandle,
+	account: entity::account::ActiveModel,
+	host: &str,
+	user_id: i32,
+) -> anyhow::Result<i32> {
+	let state = app.state::<DatabaseState>();
+	entity::Account::insert(account)
+		.on_conflict(
+			sea_query::OnConflict::columns([
+				entity::account::Column::Host,
+				entity::account::Column::UserId,
+			])
+			.update_columns([
+				entity::account::Column::Token,
+				entity::account::Column::ReauthRequired,
+			])
+			.to_owned(),
+		)
+		.exec_without_returning(&state.0)
+		.await
+		.with_context(|| format!("Failed to store account for {}", host))?;
+
+	// the row that was updated rather than inserted doesn't come back as the last insert
+	entity::Account::find()
+		.select_only()
+		.column(entity::account::Column::Id)
+		.filter(entity::account::Column::Host.eq(host))
+		.filter(entity::account::Column::UserId.eq(user_id))
+		.into_tuple::<i32>()
+		.one(&state.0)
+		.await
+		.with_context(|| format!("Failed to query account for {}", host))?
+		.with_context(|| format!("Account for {} was not stored", host))
+}
+
+/// signs in to the user's account on the host, which is added if it's new and becomes the
+/// active account. resolves to the account's id
+pub(crate) async fn sign_in(
+	app: &AppHandle,
+	host: &str,
+	user_id: i32,
+	token: &str,
+) -> anyhow::Result<i32> {
+	let account = entity::account::ActiveModel {
+		id: ActiveValue::NotSet,
+		host: ActiveValue::Set(host.to_string()),
+		user_id: ActiveValue::Set(user_id),
+		user_name: ActiveValue::NotSet,
+		token: ActiveValue::Set(Some(token.to_string())),
+		reauth_required: ActiveValue::Set(false),
+		created_at: ActiveValue::Set(Utc::now().timestamp()),
+	};
+	let account_id = upsert(app, account, host, user_id).await?;
+	set_active(app, Some(account_id))?;
+
+	// a new token can sync straight away, rather than waiting on the next scheduled round
+	app.state::<SchedulerState>().wake.notify_one();
+	Ok(account_id)
+}
+
+/// forgets the account's token, whatever was synced for it is kept to read offline
+pub(crate) async fn sign_out(app: &AppHandle, account_id: i32) -> anyhow::Result<()> {
+	let state = app.state::<DatabaseState>();
+	entity::Account::update_many()
+		.col_expr(
+			entity::account::Column::Token,
+			sea_query::Expr::value(None::<String>),
+		)
+		.col_expr(
+			entity::account::Column::ReauthRequired,
+			sea_query::Expr::value(false),
+		)
+		.filter(entity::account::Column::Id.eq(account_id))
+		.exec(&state.0)
+		.await
+		.with_context(|| format!("Failed to sign out of account {}", account_id))?;
+
+	Ok(())
+}
+
+/// removes the account along with everything synced for it
+pub(crate) async fn remove(app: &AppHandle, account_id: i32) -> anyhow::Result<()> {
+	let state = app.state::<DatabaseState>();
+	// everything below the account goes with it
+	entity::Account::delete_by_id(account_id)
+		.exec(&state.0)
+		.await
+		.with_context(|| format!("Failed to remove account {}", account_id))?;
+	entity::SyncLog::delete_many()
+		.filter(sync_task::account_sync_logs(account_id))
+		.exec(&state.0)
+		.await
+		.with_context(|| format!("Failed to remove sync logs of account {}", account_id))?;
+
+	blob_store::remove_account(app, account_id)
+		.await
+		.with_context(|| format!("Failed to remove content blobs of account {}", account_id))
+}
+
+/// makes the next signed in account the active one once the active account has been signed
+/// out of. resolves to whether there was one to switch to
+pub(crate) async fn switch_to_next(app: &AppHandle, wipe_data: bool) -> anyhow::Result<bool> {
+	let next = signed_in_ids(app, false).await?.into_iter().next();
+	match next {
+		Some(account_id) => set_active(app, Some(account_id))?,
+		// the account signed out of stays active, so what was kept of it can still be browsed
+		None if wipe_data => set_active(app, None)?,
+		None => {}
+	}
+
+	Ok(next.is_some())
+}
+
+/// takes over the single account the store kept from before there were accounts, along with
+/// everything the migration gave the legacy account. blobs stored back then are moved into
+/// the account's own directory
+pub(crate) async fn adopt_legacy(app: &AppHandle) -> anyhow::Result<()> {
+	let store = app.store("store.json")?;
+	let state = app.state::<DatabaseState>();
+	let get_string = |key: &str| {
+		store
+			.get(key)
+			.and_then(|value| value.as_str().map(str::to_string))
+	};
+	let host = get_string(auth_keys::MOODLE_HOST);
+	let user_id = get_string(auth_keys::USER_ID).and_then(|user_id| user_id.parse::<i32>().ok());
+	let legacy = entity::Account::find_by_id(LEGACY_ACCOUNT_ID)
+		.one(&state.0)
+		.await
+		.with_context(|| "Failed to query legacy account")?
+		.filter(|account| account.host.is_empty());
+
+	if let (Some(host), Some(user_id)) = (host, user_id) {
+		let token = get_string(auth_keys::WS_TOKEN)
+			.map(|token| encryption::reveal(app, &token))
+			.transpose()?;
+		let account = entity::account::ActiveModel {
+			id: ActiveValue::NotSet,
+			host: ActiveValue::Set(host.clone()),
+			user_id: ActiveValue::Set(user_id),
+			user_name: ActiveValue::Set(get_string(auth_keys::USER_NAME)),
+			token: ActiveValue::Set(token),
+			reauth_required: ActiveValue::Set(
+				store
+					.get(auth_keys::REAUTH_REQUIRED)
+					.and_then(|value| value.as_bool())
+					.unwrap_or(false),
+			),
+			created_at: ActiveValue::Set(Utc::now().timestamp()),
+		};
+		let account_id = match legacy {
+			Some(_) => {
+				entity::account::ActiveModel {
+					id: ActiveValue::Unchanged(LEGACY_ACCOUNT_ID),
+					..account
+				}
+				.update(&state.0)
+				.await
+				.with_context(|| "Failed to update legacy account")?
+				.id
+			}
+			None => upsert(app, account, &host, user_id).await?,
+		};
+
+		for key in [
+			auth_keys::MOODLE_HOST,
+			auth_keys::WS_TOKEN,
+			auth_keys::USER_ID,
+			auth_keys::USER_NAME,
+			auth_keys::REAUTH_REQUIRED,
+		] {
+			store.delete(key);
+		}
+		set_active(app, Some(account_id))?;
+		log::info!("Adopted the signed in account for {}", host);
+	} else if legacy.is_some() && active_id(app).is_none() {
+		// signed out before there were accounts, what was kept is still there to browse
+		set_active(app, Some(LEGACY_ACCOUNT_ID))?;
+	}
+
+	// these could have been moved before a crash kept the paths from being updated, so this
+	// goes ahead whether there was anything to adopt or not
+	blob_store::adopt_legacy(app, LEGACY_ACCOUNT_ID)
+		.await
+		.with_context(|| "Failed to move legacy content blobs")
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn list_accounts(app: AppHandle) -> Result<Vec<AccountInfo>, String> {
+	let state = app.state::<DatabaseState>();
+	let active_id = active_id(&app);
+	let accounts = entity::Account::find()
+		.order_by_asc(entity::account::Column::Id)
+		.all(&state.0)
+		.await
+		.map_err(|e| e.to_string())?;
+
+	Ok(
+		accounts
+			.into_iter()
+			.map(|account| AccountInfo {
+				active: active_id == Some(account.id),
+				id: account.id,
+				host: account.host,
+				user_name: account.user_name,
+				signed_in: account.token.is_some(),
+				reauth_required: account.reauth_required,
+			})
+			.collect(),
+	)
+}
+
+/// makes the account the one the ui shows, the main window starts over with its courses.
+/// syncs of the previous account carry on in the background
+#[tauri::command]
+#[specta::specta]
+pub async fn switch_account(app: AppHandle, account_id: i32) -> Result<(), String> {
+	if find(&app, account_id)
+		.await
+		.map_err(|e| e.to_string())?
+		.is_none()
+	{
+		return Err(format!("Account with id {} not found", account_id));
+	}
+
+	set_active(&app, Some(account_id)).map_err(|e| e.to_string())?;
+	navigate_main(&app, "/home")
+}
+
+// reloads the main window at the given path, so nothing from another account lingers
+pub(crate) fn navigate_main(app: &AppHandle, path: &str) -> Result<(), String> {
+	let Some(main_window) = app.get_webview_window("main") else {
+		return Ok(());
+	};
+
+	let mut url = main_window.url().map_err(|e| e.to_string())?;
+	url.set_path(path);
+	url.set_query(None);
+	url.set_fragment(None);
+	main_window.navigate(url).map_err(|e| e.to_string())
+}
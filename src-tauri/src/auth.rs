@@ -1,6 +1,6 @@
-use anyhow::Context;
 use base64::Engine;
 use rand::Rng;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, sea_query::Expr};
 use serde::{Deserialize, Serialize};
 use specta::Type;
 use tauri::{AppHandle, Manager, Url, WebviewWindowBuilder};
@@ -9,14 +9,25 @@ use tauri_plugin_store::StoreExt;
 use tauri_specta::Event;
 use tokio::sync::Mutex;
 
-use crate::request::rest::{self, RestUser};
+use crate::account;
+use crate::connectivity;
+use crate::database::DatabaseState;
+use crate::request::rest::{
+	self, RestAjaxResponse, RestQrLoginTokens, RestSiteInfo, RestTokenResponse, RestUser,
+};
+use crate::sync_task;
 
 pub mod auth_keys {
+	pub const PASSPORT: &str = "passport";
+	// the account the ui is showing, see account.rs
+	pub const ACCOUNT_ID: &str = "account_id";
+	// the single account was kept in the store before there were accounts, these are only
+	// read to move it over
 	pub const MOODLE_HOST: &str = "moodle_host";
 	pub const WS_TOKEN: &str = "ws_token";
-	pub const PASSPORT: &str = "passport";
 	pub const USER_ID: &str = "user_id";
 	pub const USER_NAME: &str = "user_name";
+	pub const REAUTH_REQUIRED: &str = "reauth_required";
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
@@ -33,16 +44,6 @@ impl Default for AuthStatus {
 	}
 }
 
-#[derive(Default, Deserialize)]
-struct RestSiteInfo {
-	// #[serde(rename = "sitename")]
-	// pub site_name: String,
-	#[serde(rename = "userid")]
-	pub user_id: u32,
-	// #[serde(rename = "fullname")]
-	// pub full_name: String,
-}
-
 // auth state represents the current status of the auth process, i.e whether
 // the user is currently being authenticated or not, this used for handling
 // window close events to abort authentication
@@ -54,6 +55,55 @@ pub struct AuthState {
 #[derive(Serialize, Deserialize, Type, Debug, Clone, Event)]
 pub struct MoodleAuthEvent(pub AuthStatus);
 
+// emitted when moodle stops accepting the stored token (revoked, expired, or the user
+// lost access), along with the host to sign in to again
+#[derive(Serialize, Deserialize, Type, Debug, Clone, Event)]
+pub struct ReauthRequiredEvent {
+	pub host: Option<String>,
+}
+
+/// marks the account's token as no longer accepted, its syncs stop until the user signs in
+/// again. nothing stored locally is touched, so everything synced so far can still be read
+pub async fn require_reauth(app: &AppHandle, account_id: i32) {
+	let state = app.state::<DatabaseState>();
+	// only the first rejection is reported, the syncs that were already running when it
+	// happened would otherwise each report it again
+	let flagged = entity::Account::update_many()
+		.col_expr(entity::account::Column::ReauthRequired, Expr::value(true))
+		.filter(entity::account::Column::Id.eq(account_id))
+		.filter(entity::account::Column::ReauthRequired.eq(false))
+		.filter(entity::account::Column::Token.is_not_null())
+		.exec(&state.0)
+		.await;
+	match flagged {
+		Ok(result) if result.rows_affected > 0 => {}
+		Ok(_) => return,
+		Err(e) => {
+			log::error!("Failed to flag account {} for reauth: {}", account_id, e);
+			return;
+		}
+	}
+
+	log::warn!(
+		"Moodle no longer accepts the token of account {}, re-authentication required",
+		account_id
+	);
+	// other accounts show as needing to sign in again in the account list instead
+	if account::active_id(app) != Some(account_id) {
+		return;
+	}
+
+	ReauthRequiredEvent {
+		host: account::find(app, account_id)
+			.await
+			.ok()
+			.flatten()
+			.map(|account| account.host),
+	}
+	.emit(app)
+	.ok();
+}
+
 #[tauri::command]
 #[specta::specta]
 pub async fn open_login_window(app: AppHandle, host: &str) -> Result<(), String> {
@@ -141,40 +191,31 @@ pub async fn open_login_window(app: AppHandle, host: &str) -> Result<(), String>
 			}
 
 			let host = host.clone();
+			let account_app = app_handle.clone();
 			tauri::async_runtime::block_on(async move {
-				// one time request to get site info, could move this to rest.rs later
-				let site_info_response = reqwest::Client::new()
-					.get(&format!("{}/webservice/rest/server.php", host))
-					.query(&[
-						("moodlewsrestformat", "json"),
-						("wsfunction", "core_webservice_get_site_info"),
-						("wstoken", token_parts[1]),
-						("moodlewssettinglang", "en"),
-						("moodlewssettingfileurl", "true"),
-						("moodlewssettingfilter", "true"),
-					])
-					.send()
-					.await;
-
-				if site_info_response.is_err() {
-					println!(
-						"Failed to fetch site info: {}",
-						site_info_response.err().unwrap()
-					);
-					auth_state.auth_status = AuthStatus::Failed;
-					MoodleAuthEvent(auth_state.auth_status.clone())
-						.emit(&window)
-						.unwrap();
-					window.close().unwrap();
-					return false;
-				}
-
-				let response_body = site_info_response.unwrap().text().await;
-				if response_body.is_err() {
-					println!(
-						"Failed to read response body: {}",
-						response_body.err().unwrap()
-					);
+				let site_info = match fetch_site_info(&host, token_parts[1]).await {
+					Ok(site_info) => site_info,
+					Err(e) => {
+						log::error!("Failed to fetch site info: {}", e);
+						auth_state.auth_status = AuthStatus::Failed;
+						MoodleAuthEvent(auth_state.auth_status.clone())
+							.emit(&window)
+							.unwrap();
+						window.close().unwrap();
+						return false;
+					}
+				};
+
+				// signing in to an account we already know keeps everything synced for it
+				let signed_in = account::sign_in(
+					&account_app,
+					&host,
+					site_info.user_id as i32,
+					token_parts[1],
+				)
+				.await;
+				if let Err(e) = signed_in {
+					log::error!("Failed to store account: {:#}", e);
 					auth_state.auth_status = AuthStatus::Failed;
 					MoodleAuthEvent(auth_state.auth_status.clone())
 						.emit(&window)
@@ -183,18 +224,6 @@ pub async fn open_login_window(app: AppHandle, host: &str) -> Result<(), String>
 					return false;
 				}
 
-				let site_info: RestSiteInfo = serde_json::from_str(&response_body.unwrap())
-					.map_err(|e| {
-						println!("Failed to parse site info: {}", e);
-						"Failed to parse site info".to_string()
-					})
-					.unwrap();
-
-				// todo: store user data in separate table with data like enrolled courses
-				// probably means we also have to encrypt course data
-				store.set(auth_keys::USER_ID, site_info.user_id.to_string());
-				store.set(auth_keys::MOODLE_HOST, host);
-				store.set(auth_keys::WS_TOKEN, token_parts[1]);
 				store.set(auth_keys::PASSPORT, passport);
 
 				auth_state.auth_status = AuthStatus::Success;
@@ -218,37 +247,277 @@ pub async fn open_login_window(app: AppHandle, host: &str) -> Result<(), String>
 	Ok(())
 }
 
+/// signs in with a username and password through login/token.php instead of the login window,
+/// for sites where the browser flow gets in the way (captchas, custom themes, etc.)
 #[tauri::command]
 #[specta::specta]
-pub async fn get_user_name(app: AppHandle) -> Result<String, String> {
-	let store = app.store("store.json").map_err(|e| e.to_string())?;
-	if let Some(name) = store.get(auth_keys::USER_NAME) {
-		if let Some(name) = name.as_str() {
-			return Ok(name.to_string());
+pub async fn login_with_credentials(
+	app: AppHandle,
+	host: &str,
+	username: &str,
+	password: &str,
+) -> Result<(), String> {
+	if host.is_empty() {
+		return Err("invalid domain".to_string());
+	}
+
+	if username.is_empty() || password.is_empty() {
+		return Err("username and password are required".to_string());
+	}
+
+	let auth_state = app.state::<Mutex<AuthState>>();
+	let mut auth_state = auth_state.lock().await;
+	auth_state.auth_status = AuthStatus::Pending;
+
+	let signed_in = async {
+		let client = reqwest::Client::new();
+		let request =
+			rest::get_token_request(&client, host, username, password).map_err(|e| e.to_string())?;
+		let response = client
+			.execute(request)
+			.await
+			.map_err(|_| "invalid domain".to_string())?;
+		if response.status().is_success() == false {
+			return Err(format!("failed to request token: {}", response.status()));
 		}
+
+		let body = response.text().await.map_err(|e| e.to_string())?;
+		let token_response: RestTokenResponse =
+			serde_json::from_str(&body).map_err(|_| "invalid moodle instance".to_string())?;
+		let token = match token_response {
+			RestTokenResponse {
+				token: Some(token), ..
+			} => token,
+			// e.g. invalidlogin, or enablewsdescription when the site doesn't allow it
+			RestTokenResponse {
+				error, error_code, ..
+			} => {
+				return Err(
+					error
+						.or(error_code)
+						.unwrap_or_else(|| "no token returned".to_string()),
+				);
+			}
+		};
+
+		sign_in_with_token(&app, host, &token, None).await
 	}
+	.await;
 
-	let user_id: String = serde_json::from_value(
-		store
-			.get(auth_keys::USER_ID)
-			.with_context(|| "user id not found")
-			.map_err(|e| e.to_string())?
-			.clone(),
-	)
-	.map_err(|e| e.to_string())?;
-	let user_id = user_id.parse::<u32>().map_err(|e| e.to_string())?;
+	finish_login(&app, &mut auth_state, signed_in)
+}
 
-	let client = reqwest::Client::new();
-	let token = store
-		.get(auth_keys::WS_TOKEN)
-		.with_context(|| "ws token not found")
+// what a qr code to sign in with is given as
+#[derive(Serialize, Deserialize, Type, Debug, Clone)]
+pub enum QrLoginSource {
+	// the text the code holds, i.e. read with another scanner
+	Payload(String),
+	// the path to a picture of the code, i.e. a screenshot of the profile page
+	Image(String),
+}
+
+// what a qr code for signing in to the app holds, in the form
+// <urlscheme>://<site url>?qrlogin=<key>&userid=<user id>
+struct QrLogin {
+	host: String,
+	key: String,
+	user_id: u32,
+}
+
+/// signs in with the qr code moodle shows on the user's profile for the mobile app, without
+/// going through the login window. the code is only valid for a few minutes
+#[tauri::command]
+#[specta::specta]
+pub async fn login_with_qr(app: AppHandle, source: QrLoginSource) -> Result<(), String> {
+	let payload = match source {
+		QrLoginSource::Payload(payload) => payload,
+		QrLoginSource::Image(path) => {
+			tauri::async_runtime::spawn_blocking(move || decode_qr_image(&path))
+				.await
+				.map_err(|e| e.to_string())??
+		}
+	};
+	let qr_login = parse_qr_payload(&payload)?;
+
+	let auth_state = app.state::<Mutex<AuthState>>();
+	let mut auth_state = auth_state.lock().await;
+	auth_state.auth_status = AuthStatus::Pending;
+
+	let signed_in = async {
+		let client = reqwest::Client::new();
+		let request = rest::get_tokens_for_qr_login_request(
+			&client,
+			&qr_login.host,
+			&qr_login.key,
+			qr_login.user_id,
+		)
 		.map_err(|e| e.to_string())?;
-	let token = token.as_str().unwrap();
+		let response = client
+			.execute(request)
+			.await
+			.map_err(|_| "invalid domain".to_string())?;
+		if response.status().is_success() == false {
+			return Err(format!("failed to request token: {}", response.status()));
+		}
+
+		let body = response.text().await.map_err(|e| e.to_string())?;
+		let response = serde_json::from_str::<Vec<RestAjaxResponse>>(&body)
+			.map_err(|_| "invalid moodle instance".to_string())?
+			.into_iter()
+			.next()
+			.ok_or_else(|| "no token returned".to_string())?;
+		// e.g. the code expired, or was shown on another network
+		if response.error {
+			return Err(
+				response
+					.exception
+					.map(|exception| exception.message)
+					.unwrap_or_else(|| "no token returned".to_string()),
+			);
+		}
+
+		let tokens: RestQrLoginTokens = response
+			.data
+			.ok_or_else(|| "no token returned".to_string())
+			.and_then(|data| serde_json::from_value(data).map_err(|e| e.to_string()))?;
+		sign_in_with_token(&app, &qr_login.host, &tokens.token, Some(qr_login.user_id)).await
+	}
+	.await;
+
+	finish_login(&app, &mut auth_state, signed_in)
+}
+
+fn decode_qr_image(path: &str) -> Result<String, String> {
+	let image = image::open(path)
+		.map_err(|e| format!("failed to open image: {}", e))?
+		.to_luma8();
+	let mut prepared = rqrr::PreparedImage::prepare_from_greyscale(
+		image.width() as usize,
+		image.height() as usize,
+		|x, y| image.get_pixel(x as u32, y as u32).0[0],
+	);
+
+	prepared
+		.detect_grids()
+		.into_iter()
+		.find_map(|grid| grid.decode().ok())
+		.map(|(_, content)| content)
+		.ok_or_else(|| "no qr code found in image".to_string())
+}
+
+fn parse_qr_payload(payload: &str) -> Result<QrLogin, String> {
+	// the scheme is whatever the site has set for the mobile app, the site url follows it
+	let site_url = payload
+		.trim()
+		.split_once("://")
+		.map(|(_, site_url)| site_url)
+		.filter(|site_url| site_url.contains("://"))
+		.unwrap_or(payload.trim());
+	let url = Url::parse(site_url).map_err(|_| "invalid qr code".to_string())?;
+	let query = url
+		.query_pairs()
+		.collect::<std::collections::HashMap<_, _>>();
+	// codes that only hold the site url are for filling in the host, not for signing in
+	let key = query
+		.get("qrlogin")
+		.filter(|key| key.is_empty() == false)
+		.ok_or_else(|| "qr code isn't for signing in, use the one from your profile".to_string())?;
+	let user_id = query
+		.get("userid")
+		.and_then(|user_id| user_id.parse::<u32>().ok())
+		.ok_or_else(|| "invalid qr code".to_string())?;
+
+	Ok(QrLogin {
+		host: format!(
+			"{}{}",
+			url.origin().ascii_serialization(),
+			url.path().trim_end_matches('/')
+		),
+		key: key.to_string(),
+		user_id,
+	})
+}
+
+// the token is only kept once the site accepts it, and it belongs to who it was issued for
+async fn sign_in_with_token(
+	app: &AppHandle,
+	host: &str,
+	token: &str,
+	expected_user_id: Option<u32>,
+) -> Result<i32, String> {
+	let site_info = fetch_site_info(host, token).await?;
+	if expected_user_id.is_some_and(|user_id| user_id != site_info.user_id) {
+		return Err("token was issued for another user".to_string());
+	}
+
+	account::sign_in(app, host, site_info.user_id as i32, token)
+		.await
+		.map_err(|e| format!("{:#}", e))
+}
+
+// the ui handles every sign in flow the same way once it's done, failures are returned to
+// the command instead
+fn finish_login(
+	app: &AppHandle,
+	auth_state: &mut AuthState,
+	signed_in: Result<i32, String>,
+) -> Result<(), String> {
+	auth_state.auth_status = match &signed_in {
+		Ok(_) => AuthStatus::Success,
+		Err(_) => AuthStatus::Failed,
+	};
+	if signed_in.is_ok() {
+		MoodleAuthEvent(auth_state.auth_status.clone())
+			.emit(app)
+			.ok();
+	}
+
+	signed_in.map(|_| ())
+}
+
+// checks the token against the site, and finds out who it belongs to
+async fn fetch_site_info(host: &str, token: &str) -> Result<RestSiteInfo, String> {
+	let client = reqwest::Client::new();
+	let request = rest::get_site_info_request(&client, host, token).map_err(|e| e.to_string())?;
+	let response = client.execute(request).await.map_err(|e| e.to_string())?;
+	if response.status().is_success() == false {
+		return Err(format!("failed to fetch site info: {}", response.status()));
+	}
+
+	let body = response.text().await.map_err(|e| e.to_string())?;
+	if body.contains("errorcode") {
+		let error_body: rest::RestErrorBody = serde_json::from_str(&body).map_err(|e| e.to_string())?;
+		return Err(error_body.message);
+	}
+
+	serde_json::from_str(&body).map_err(|e| format!("failed to parse site info: {}", e))
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn get_user_name(app: AppHandle) -> Result<String, String> {
+	let account_id = account::require_active(&app)?;
+	let active = account::find(&app, account_id)
+		.await
+		.map_err(|e| e.to_string())?
+		.ok_or_else(|| format!("Account with id {} not found", account_id))?;
+	if let Some(name) = active.user_name {
+		return Ok(name);
+	}
+
+	if !connectivity::is_online(&app) {
+		return Err("can't fetch user info while offline".to_string());
+	}
+
+	let credentials = account::credentials(&app, account_id)
+		.await
+		.map_err(|e| e.message)?;
+	let client = reqwest::Client::new();
 	let request = rest::get_users_by_id(
 		&client,
-		store.get(auth_keys::MOODLE_HOST).unwrap().as_str().unwrap(),
-		token,
-		vec![user_id],
+		&credentials.host,
+		&credentials.token,
+		vec![credentials.user_id as u32],
 	)
 	.map_err(|e| e.to_string())?;
 
@@ -259,12 +528,26 @@ pub async fn get_user_name(app: AppHandle) -> Result<String, String> {
 
 	let body = response.text().await.map_err(|e| e.to_string())?;
 	if body.contains("errorcode") {
+		if let Ok(error_body) = serde_json::from_str::<rest::RestErrorBody>(&body)
+			&& sync_task::is_auth_error_code(&error_body.error_code)
+		{
+			require_reauth(&app, account_id).await;
+		}
 		return Err(format!("error fetching user info: {}", body));
 	}
 
 	let users: Vec<RestUser> = serde_json::from_str(&body).map_err(|e| e.to_string())?;
 	let user_name = users[0].full_name.clone();
-	store.set(auth_keys::USER_NAME, user_name.clone());
+	let state = app.state::<DatabaseState>();
+	entity::Account::update_many()
+		.col_expr(
+			entity::account::Column::UserName,
+			Expr::value(user_name.clone()),
+		)
+		.filter(entity::account::Column::Id.eq(account_id))
+		.exec(&state.0)
+		.await
+		.map_err(|e| e.to_string())?;
 
 	Ok(user_name)
 }
@@ -272,15 +555,117 @@ pub async fn get_user_name(app: AppHandle) -> Result<String, String> {
 #[tauri::command]
 #[specta::specta]
 pub async fn get_host(app: AppHandle) -> Result<String, String> {
-	let store = app.store("store.json").map_err(|e| e.to_string())?;
-	let host = serde_json::from_value(
-		store
-			.get(auth_keys::MOODLE_HOST)
-			.with_context(|| "host not found")
+	account::active(&app)
+		.await
+		.map_err(|e| e.to_string())?
+		.map(|account| account.host)
+		// the legacy account's host is only known once it's been signed in to again
+		.filter(|host| host.is_empty() == false)
+		.ok_or_else(|| "host not found".to_string())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn get_reauth_required(app: AppHandle) -> Result<bool, String> {
+	Ok(
+		account::active(&app)
+			.await
 			.map_err(|e| e.to_string())?
-			.clone(),
+			.is_some_and(|account| account.reauth_required),
 	)
-	.map_err(|e| e.to_string())?;
+}
+
+/// opens the login window for the host we're already signed in to, everything stored locally
+/// is kept and syncs pick up again once it succeeds
+#[tauri::command]
+#[specta::specta]
+pub async fn reauthenticate(app: AppHandle) -> Result<(), String> {
+	let host = get_host(app.clone()).await?;
+	open_login_window(app, &host).await
+}
+
+/// signs out of the active account. everything synced for it is either wiped along with its
+/// blobs, or kept as an offline library that can still be read but is no longer synced. the
+/// main window carries on with another signed in account if there is one, or goes back to
+/// setup otherwise
+#[tauri::command]
+#[specta::specta]
+pub async fn logout(app: AppHandle, wipe_data: bool) -> Result<(), String> {
+	let account_id = account::require_active(&app)?;
+	// nothing should still be syncing with the token once it's gone, or writing under what's
+	// about to be wiped
+	sync_task::cancel_account(&app, account_id).await;
+	if wipe_data {
+		account::remove(&app, account_id).await
+	} else {
+		account::sign_out(&app, account_id).await
+	}
+	.map_err(|e| format!("{:#}", e))?;
+
+	let store = app.store("store.json").map_err(|e| e.to_string())?;
+	store.delete(auth_keys::PASSPORT);
+	store.save().map_err(|e| e.to_string())?;
+
+	*app.state::<Mutex<AuthState>>().lock().await = AuthState::default();
+	if let Some(login_window) = app.get_webview_window("login") {
+		login_window.close().ok();
+	}
 
-	Ok(host)
+	let switched = account::switch_to_next(&app, wipe_data)
+		.await
+		.map_err(|e| format!("{:#}", e))?;
+	account::navigate_main(&app, if switched { "/home" } else { "/" })?;
+
+	log::info!(
+		"Signed out of account {}{}",
+		account_id,
+		if wipe_data {
+			" and wiped its stored data"
+		} else {
+			""
+		}
+	);
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_app_scheme_payload() {
+		let login =
+			parse_qr_payload("moodlemobile://https://moodle.example.com?qrlogin=abc123&userid=5")
+				.unwrap();
+		assert_eq!(login.host, "https://moodle.example.com");
+		assert_eq!(login.key, "abc123");
+		assert_eq!(login.user_id, 5);
+	}
+
+	#[test]
+	fn parses_plain_site_url() {
+		let login = parse_qr_payload(" https://moodle.example.com/?qrlogin=abc123&userid=5\n").unwrap();
+		assert_eq!(login.host, "https://moodle.example.com");
+		assert_eq!(login.key, "abc123");
+	}
+
+	#[test]
+	fn keeps_site_path() {
+		let login =
+			parse_qr_payload("moodlemobile://https://example.com/moodle/?qrlogin=abc123&userid=5")
+				.unwrap();
+		assert_eq!(login.host, "https://example.com/moodle");
+	}
+
+	#[test]
+	fn rejects_site_only_code() {
+		assert!(parse_qr_payload("moodlemobile://https://moodle.example.com").is_err());
+		assert!(parse_qr_payload("https://moodle.example.com?qrlogin=&userid=5").is_err());
+	}
+
+	#[test]
+	fn rejects_missing_user_id() {
+		assert!(parse_qr_payload("https://moodle.example.com?qrlogin=abc123").is_err());
+		assert!(parse_qr_payload("https://moodle.example.com?qrlogin=abc123&userid=me").is_err());
+	}
 }
@@ -0,0 +1,689 @@
+use std::{
+	collections::HashSet,
+	ops::Not,
+	path::{Path, PathBuf},
+	time::Duration,
+};
+
+use anyhow::Context;
+use sea_orm::{
+	ColumnTrait, DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter, QuerySelect,
+	TransactionTrait, sea_query::Expr,
+};
+use sha2::{Digest, Sha256};
+use tauri::{
+	AppHandle, Manager,
+	http::{
+		Request, Response, StatusCode,
+		header::{
+			ACCEPT_RANGES, ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_EXPOSE_HEADERS, CONTENT_LENGTH,
+			CONTENT_RANGE, CONTENT_TYPE, ORIGIN, RANGE, VARY,
+		},
+	},
+};
+use tauri_plugin_http::reqwest;
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+	database::DatabaseState,
+	encryption::{self, Key},
+	sync_task::{SyncContext, SyncError, SyncProgress},
+};
+
+// blobs are stored by the hash of their content as
+// content_blobs/account_<id>/<hash prefix>/<hash>.<ext>, so the same file shared between
+// modules (or courses) of an account is only stored once. a file is referenced by every
+// content_blob row pointing at its path, these references are counted from the rows
+// themselves rather than stored so they can't drift
+
+// each account's blobs are kept in their own directory, so removing an account is just
+// removing its directory
+const ACCOUNT_DIR_PREFIX: &str = "account_";
+// in progress downloads, kept between attempts so they can be resumed
+const PARTIAL_DIR: &str = "partial";
+// progress is reported at most once per this many bytes downloaded
+const PROGRESS_INTERVAL: u64 = 1024 * 1024;
+// partial downloads left alone for this long are assumed abandoned when pruning
+const PARTIAL_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+// blobs are served to the webview through this protocol rather than the asset protocol, so
+// sealed blobs can be opened on the way out
+pub const PROTOCOL: &str = "content-blob";
+// ranges are served at most this much at a time, media elements ask for the rest as they
+// get to it
+const MAX_RANGE_LEN: u64 = 4 * 1024 * 1024;
+
+pub fn blobs_root(app_handle: &AppHandle) -> PathBuf {
+	app_handle
+		.path()
+		.app_local_data_dir()
+		.expect("failed to get app data dir")
+		.join("content_blobs")
+}
+
+pub fn blobs_dir(app_handle: &AppHandle, account_id: i32) -> PathBuf {
+	blobs_root(app_handle).join(format!("{}{}", ACCOUNT_DIR_PREFIX, account_id))
+}
+
+// the directories of every account that has blobs stored
+fn account_dirs(blobs_root: &Path) -> Vec<PathBuf> {
+	let Ok(entries) = std::fs::read_dir(blobs_root) else {
+		return Vec::new();
+	};
+
+	entries
+		.flatten()
+		.filter(|entry| {
+			entry
+				.file_name()
+				.to_string_lossy()
+				.starts_with(ACCOUNT_DIR_PREFIX)
+		})
+		.map(|entry| entry.path())
+		.filter(|path| path.is_dir())
+		.collect()
+}
+
+// the extension is kept so the blob protocol can still tell what kind of file it's serving
+fn blob_path(blobs_dir: &Path, hash: &str, file_name: &str) -> PathBuf {
+	let extension = Path::new(file_name)
+		.extension()
+		.map(|ext| {
+			ext
+				.to_string_lossy()
+				.chars()
+				.filter(|c| c.is_ascii_alphanumeric())
+				.collect::<String>()
+				.to_lowercase()
+		})
+		.unwrap_or_default();
+	let file_name = if extension.is_empty() {
+		hash.to_string()
+	} else {
+		format!("{hash}.{extension}")
+	};
+
+	blobs_dir.join(&hash[..2]).join(file_name)
+}
+
+pub struct DownloadedBlob {
+	pub hash: String,
+	pub path: PathBuf,
+	pub size: u64,
+}
+
+/// streams the blob at `url` into a temp file, then moves it into the store. a download that
+/// was interrupted (cancelled, lost connection, crashed) resumes where it left off when the
+/// server supports range requests. `version` should change whenever the file does, so a
+/// partial download is never resumed onto a newer file
+pub async fn download_blob(
+	ctx: &SyncContext,
+	progress: &mut SyncProgress,
+	client: &reqwest::Client,
+	url: &str,
+	file_name: &str,
+	version: u64,
+) -> Result<DownloadedBlob, SyncError> {
+	let blobs_dir = blobs_dir(&ctx.app_handle, ctx.account_id);
+	let partial_dir = blobs_dir.join(PARTIAL_DIR);
+	tokio::fs::create_dir_all(&partial_dir)
+		.await
+		.with_context(|| format!("Failed to create directory {}", partial_dir.display()))?;
+
+	// the token in the url isn't part of what makes the file, so it's left out of the key
+	let key = format!("{}:{}", url.split('?').next().unwrap_or(url), version);
+	let temp_path = partial_dir.join(format!("{:x}.part", Sha256::digest(key.as_bytes())));
+	let resume_from = tokio::fs::metadata(&temp_path)
+		.await
+		.map(|metadata| metadata.len())
+		.unwrap_or(0);
+
+	let mut request = client.get(url);
+	if resume_from > 0 {
+		request = request.header(RANGE, format!("bytes={resume_from}-"));
+	}
+
+	let mut response = request
+		.send()
+		.await
+		.with_context(|| format!("Failed to fetch content blob {}", file_name))?;
+	// whatever we had doesn't line up with the file anymore, so start over
+	if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+		tokio::fs::remove_file(&temp_path).await.ok();
+		response = client
+			.get(url)
+			.send()
+			.await
+			.with_context(|| format!("Failed to fetch content blob {}", file_name))?;
+	}
+
+	let mut response = response
+		.error_for_status()
+		.with_context(|| format!("Failed to fetch content blob {}", file_name))?;
+	// servers that ignore the range send the whole file again
+	let resumed = response.status() == StatusCode::PARTIAL_CONTENT;
+	let expected_size = response.content_length().map(|length| {
+		if resumed {
+			resume_from + length
+		} else {
+			length
+		}
+	});
+
+	let mut file = tokio::fs::OpenOptions::new()
+		.create(true)
+		.write(true)
+		.append(resumed)
+		.truncate(resumed.not())
+		.open(&temp_path)
+		.await
+		.with_context(|| format!("Failed to open {}", temp_path.display()))?;
+
+	let mut unreported = 0;
+	while let Some(chunk) = response
+		.chunk()
+		.await
+		.with_context(|| format!("Failed to read content blob {}", file_name))?
+	{
+		// what's been written so far is kept, so cancelling doesn't lose the download
+		ctx.check_cancelled()?;
+		file
+			.write_all(&chunk)
+			.await
+			.with_context(|| format!("Failed to write to {}", temp_path.display()))?;
+
+		progress.bytes_downloaded += chunk.len() as u64;
+		unreported += chunk.len() as u64;
+		if unreported >= PROGRESS_INTERVAL {
+			ctx.report(progress).await;
+			unreported = 0;
+		}
+	}
+
+	file
+		.sync_all()
+		.await
+		.with_context(|| format!("Failed to flush {}", temp_path.display()))?;
+	drop(file);
+
+	let size = tokio::fs::metadata(&temp_path)
+		.await
+		.with_context(|| format!("Failed to read metadata of {}", temp_path.display()))?
+		.len();
+	if let Some(expected_size) = expected_size
+		&& size != expected_size
+	{
+		return Err(SyncError::network(format!(
+			"Content blob {} ended early ({} of {} bytes)",
+			file_name, size, expected_size
+		)));
+	}
+
+	let hash_path = temp_path.clone();
+	let hash = tokio::task::spawn_blocking(move || hash_file(None, &hash_path))
+		.await
+		.with_context(|| "Failed to join hashing task")?
+		.with_context(|| format!("Failed to hash {}", temp_path.display()))?;
+
+	let path = blob_path(&blobs_dir, &hash, file_name);
+	if tokio::fs::try_exists(&path).await.unwrap_or(false) {
+		// same content is already stored by another module of the account
+		tokio::fs::remove_file(&temp_path).await.ok();
+		return Ok(DownloadedBlob { hash, path, size });
+	}
+
+	let parent = path.parent().unwrap();
+	tokio::fs::create_dir_all(parent).await.with_context(|| {
+		format!(
+			"Failed to create content blob directory {}",
+			parent.display()
+		)
+	})?;
+	match encryption::key(&ctx.app_handle) {
+		// the partial download is plain, but it's removed as soon as it's sealed
+		Some(key) => {
+			let sealed_path = temp_path.with_extension("sealed");
+			let (seal_from, seal_to) = (temp_path.clone(), sealed_path.clone());
+			tokio::task::spawn_blocking(move || encryption::seal_file(&key, &seal_from, &seal_to))
+				.await
+				.with_context(|| "Failed to join sealing task")?
+				.with_context(|| format!("Failed to seal {}", temp_path.display()))?;
+			tokio::fs::rename(&sealed_path, &path)
+				.await
+				.with_context(|| format!("Failed to move content blob to {}", path.display()))?;
+			tokio::fs::remove_file(&temp_path).await.ok();
+		}
+		None => tokio::fs::rename(&temp_path, &path)
+			.await
+			.with_context(|| format!("Failed to move content blob to {}", path.display()))?,
+	}
+
+	Ok(DownloadedBlob { hash, path, size })
+}
+
+/// hash of the content of the file, sealed files need the key
+pub fn hash_file(key: Option<&Key>, path: &Path) -> std::io::Result<String> {
+	let mut file = encryption::open_file(key, path)?;
+	let mut hasher = Sha256::new();
+	std::io::copy(&mut file, &mut hasher)?;
+	Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// removes the files at the given paths that are no longer referenced by any content blob,
+/// this should be called once whatever dropped the references has been committed. resolves
+/// to how many files were removed and how many bytes that freed
+pub async fn release_unreferenced(db: &DatabaseConnection, paths: Vec<String>) -> (u32, u64) {
+	let mut released = (0, 0);
+	for path in paths.into_iter().collect::<HashSet<_>>() {
+		let references = entity::ContentBlob::find()
+			.filter(entity::content_blob::Column::Path.eq(&path))
+			.count(db)
+			.await;
+
+		match references {
+			Ok(0) => {
+				log::debug!("Removing unreferenced content blob {}", path);
+				let size = tokio::fs::metadata(&path)
+					.await
+					.map(|m| m.len())
+					.unwrap_or(0);
+				match tokio::fs::remove_file(&path).await {
+					Ok(()) => {
+						released.0 += 1;
+						released.1 += size;
+					}
+					// someone (or something) got to it first
+					Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+					Err(e) => log::warn!("Failed to remove content blob {}: {}", path, e),
+				}
+			}
+			Ok(_) => {}
+			Err(e) => log::error!("Failed to count references to content blob {}: {}", path, e),
+		}
+	}
+
+	released
+}
+
+/// removes files in the blob store that no content blob points at, i.e. left behind by a crash
+/// between committing and releasing, and partial downloads that haven't been resumed in a
+/// while. resolves to how many files were removed and how many bytes that freed
+pub async fn remove_untracked(app_handle: &AppHandle, referenced: HashSet<String>) -> (u32, u64) {
+	let blobs_root = blobs_root(app_handle);
+	tokio::task::spawn_blocking(move || {
+		let mut removed = (0, 0);
+		let dirs = account_dirs(&blobs_root)
+			.into_iter()
+			.flat_map(|account_dir| {
+				std::fs::read_dir(account_dir)
+					.into_iter()
+					.flatten()
+					.flatten()
+			});
+		for dir in dirs.map(|entry| entry.path()) {
+			if dir.is_dir().not() {
+				continue;
+			}
+
+			let is_partial = dir.file_name().is_some_and(|name| name == PARTIAL_DIR);
+			for file in std::fs::read_dir(&dir).into_iter().flatten().flatten() {
+				let path = file.path();
+				let Ok(metadata) = file.metadata() else {
+					continue;
+				};
+
+				let untracked = if is_partial {
+					metadata
+						.modified()
+						.ok()
+						.and_then(|modified| modified.elapsed().ok())
+						.is_some_and(|age| age > PARTIAL_MAX_AGE)
+				} else {
+					metadata.is_file() && referenced.contains(path.to_string_lossy().as_ref()).not()
+				};
+
+				if untracked.not() {
+					continue;
+				}
+
+				log::debug!("Removing untracked content blob {}", path.display());
+				match std::fs::remove_file(&path) {
+					Ok(()) => {
+						removed.0 += 1;
+						removed.1 += metadata.len();
+					}
+					Err(e) => log::warn!("Failed to remove {}: {}", path.display(), e),
+				}
+			}
+
+			// only goes through once the directory is empty
+			std::fs::remove_dir(&dir).ok();
+		}
+
+		removed
+	})
+	.await
+	.unwrap_or_else(|e| {
+		log::error!("Failed to join blob store cleanup task: {}", e);
+		(0, 0)
+	})
+}
+
+/// seals every stored blob with `key`, or opens them again when `seal` is false. partial
+/// downloads are dropped either way, they'd otherwise be resumed as they were. resolves to
+/// how many files were converted
+pub async fn convert_all(app_handle: &AppHandle, key: Key, seal: bool) -> anyhow::Result<u32> {
+	let blobs_root = blobs_root(app_handle);
+	tokio::task::spawn_blocking(move || {
+		let mut converted = 0;
+		let dirs = account_dirs(&blobs_root)
+			.into_iter()
+			.flat_map(|account_dir| {
+				std::fs::read_dir(account_dir)
+					.into_iter()
+					.flatten()
+					.flatten()
+			});
+		for dir in dirs.map(|entry| entry.path()) {
+			if dir.is_dir().not() {
+				continue;
+			}
+
+			if dir.file_name().is_some_and(|name| name == PARTIAL_DIR) {
+				std::fs::remove_dir_all(&dir)
+					.with_context(|| format!("Failed to remove {}", dir.display()))?;
+				continue;
+			}
+
+			for file in std::fs::read_dir(&dir).into_iter().flatten().flatten() {
+				let path = file.path();
+				if encryption::is_staging(&path) {
+					std::fs::remove_file(&path)
+						.with_context(|| format!("Failed to remove {}", path.display()))?;
+					continue;
+				}
+
+				let result = if seal {
+					encryption::seal_in_place(&key, &path)
+				} else {
+					encryption::open_in_place(&key, &path)
+				};
+
+				if result.with_context(|| format!("Failed to convert {}", path.display()))? {
+					converted += 1;
+				}
+			}
+		}
+
+		Ok(converted)
+	})
+	.await?
+}
+
+/// removes every blob stored for the account, its rows are expected to be gone already
+pub async fn remove_account(app_handle: &AppHandle, account_id: i32) -> std::io::Result<()> {
+	match tokio::fs::remove_dir_all(blobs_dir(app_handle, account_id)).await {
+		Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+		_ => Ok(()),
+	}
+}
+
+/// moves blobs stored before each account had its own directory into the account's, along
+/// with the paths of the rows pointing at them. either half is picked up again on the next
+/// start if the other didn't get to finish
+pub async fn adopt_legacy(app_handle: &AppHandle, account_id: i32) -> anyhow::Result<()> {
+	let blobs_root = blobs_root(app_handle);
+	let blobs_dir = blobs_dir(app_handle, account_id);
+	let state = app_handle.state::<DatabaseState>();
+	let db = &state.0;
+	let paths = entity::ContentBlob::find()
+		.select_only()
+		.column(entity::content_blob::Column::Path)
+		.distinct()
+		.into_tuple::<String>()
+		.all(db)
+		.await
+		.with_context(|| "Failed to query content blob paths")?;
+
+	let legacy_paths = paths
+		.into_iter()
+		.filter_map(|path| {
+			let relative = Path::new(&path).strip_prefix(&blobs_root).ok()?;
+			let legacy = relative.components().next().is_some_and(|dir| {
+				dir
+					.as_os_str()
+					.to_string_lossy()
+					.starts_with(ACCOUNT_DIR_PREFIX)
+					.not()
+			});
+			legacy.then(|| (path.clone(), blobs_dir.join(relative)))
+		})
+		.collect::<Vec<_>>();
+
+	if legacy_paths.is_empty().not() {
+		let txn = db
+			.begin()
+			.await
+			.with_context(|| "Failed to begin transaction")?;
+		for (path, new_path) in &legacy_paths {
+			entity::ContentBlob::update_many()
+				.col_expr(
+					entity::content_blob::Column::Path,
+					Expr::value(new_path.to_string_lossy().to_string()),
+				)
+				.filter(entity::content_blob::Column::Path.eq(path))
+				.exec(&txn)
+				.await
+				.with_context(|| format!("Failed to update path of content blob {}", path))?;
+		}
+
+		txn
+			.commit()
+			.await
+			.with_context(|| "Failed to commit transaction")?;
+		log::info!(
+			"Moved {} legacy content blobs to {}",
+			legacy_paths.len(),
+			blobs_dir.display()
+		);
+	}
+
+	tokio::task::spawn_blocking(move || -> anyhow::Result<()> {
+		let Ok(entries) = std::fs::read_dir(&blobs_root) else {
+			return Ok(());
+		};
+
+		for entry in entries.flatten() {
+			if entry
+				.file_name()
+				.to_string_lossy()
+				.starts_with(ACCOUNT_DIR_PREFIX)
+			{
+				continue;
+			}
+
+			// a directory the account already has, i.e. a move interrupted part way through,
+			// is merged file by file
+			let from = entry.path();
+			let to = blobs_dir.join(entry.file_name());
+			std::fs::create_dir_all(&blobs_dir)
+				.with_context(|| format!("Failed to create directory {}", blobs_dir.display()))?;
+			if to.exists().not() {
+				std::fs::rename(&from, &to)
+					.with_context(|| format!("Failed to move {}", from.display()))?;
+				continue;
+			}
+
+			for file in std::fs::read_dir(&from).into_iter().flatten().flatten() {
+				let to = to.join(file.file_name());
+				std::fs::rename(file.path(), &to)
+					.with_context(|| format!("Failed to move {}", file.path().display()))?;
+			}
+			std::fs::remove_dir(&from).ok();
+		}
+
+		Ok(())
+	})
+	.await?
+}
+
+/// answers a request on the blob protocol, the path is the (url encoded) path of the blob
+/// the same way `convertFileSrc` builds it for the asset protocol
+pub fn respond(app_handle: &AppHandle, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+	let path = urlencoding::decode(request.uri().path().trim_start_matches('/'))
+		.map(|path| PathBuf::from(path.into_owned()));
+	// nothing outside the blob store is served, however the path was put together
+	let path = match path.map(|path| path.canonicalize()) {
+		Ok(Ok(path)) => path,
+		_ => return status_response(StatusCode::NOT_FOUND),
+	};
+	let within_store = blobs_root(app_handle)
+		.canonicalize()
+		.is_ok_and(|blobs_dir| path.starts_with(blobs_dir));
+	if within_store.not() {
+		return status_response(StatusCode::FORBIDDEN);
+	}
+
+	let len = match encryption::content_len(&path) {
+		Ok(len) => len,
+		Err(e) => {
+			log::warn!("Failed to serve content blob {}: {}", path.display(), e);
+			return status_response(StatusCode::INTERNAL_SERVER_ERROR);
+		}
+	};
+
+	let mut response = Response::builder()
+		.header(CONTENT_TYPE, mime_type(&path))
+		.header(ACCEPT_RANGES, "bytes")
+		.header(VARY, "Origin");
+	if let Some(origin) = allowed_origin(app_handle, request) {
+		response = response.header(ACCESS_CONTROL_ALLOW_ORIGIN, origin).header(
+			ACCESS_CONTROL_EXPOSE_HEADERS,
+			"Accept-Ranges, Content-Length, Content-Range",
+		);
+	}
+
+	let range = request
+		.headers()
+		.get(RANGE)
+		.and_then(|range| range.to_str().ok())
+		.map_or(RequestedRange::Whole, |range| parse_range(range, len));
+	let (start, end) = match range {
+		RequestedRange::Whole => (0, len),
+		RequestedRange::Part(start, end) => {
+			let end = end.min(start + MAX_RANGE_LEN);
+			response = response.status(StatusCode::PARTIAL_CONTENT).header(
+				CONTENT_RANGE,
+				format!("bytes {}-{}/{}", start, end - 1, len),
+			);
+			(start, end)
+		}
+		RequestedRange::Unsatisfiable => {
+			return response
+				.status(StatusCode::RANGE_NOT_SATISFIABLE)
+				.header(CONTENT_RANGE, format!("bytes */{}", len))
+				.body(Vec::new())
+				.unwrap();
+		}
+	};
+
+	// only the chunks covering the range are opened for sealed blobs
+	let key = encryption::key(app_handle);
+	match encryption::read_range(key.as_ref(), &path, start, end - start) {
+		Ok(body) => response
+			.header(CONTENT_LENGTH, body.len())
+			.body(body)
+			.unwrap(),
+		Err(e) => {
+			log::warn!("Failed to serve content blob {}: {}", path.display(), e);
+			status_response(StatusCode::INTERNAL_SERVER_ERROR)
+		}
+	}
+}
+
+fn status_response(status: StatusCode) -> Response<Vec<u8>> {
+	Response::builder().status(status).body(Vec::new()).unwrap()
+}
+
+// the ui fetches pdfs rather than embedding them, which is a cross origin request from the
+// main window. nothing else is let in
+fn allowed_origin(app_handle: &AppHandle, request: &Request<Vec<u8>>) -> Option<String> {
+	let origin = request.headers().get(ORIGIN)?.to_str().ok()?;
+	let window_url = app_handle.get_webview_window("main")?.url().ok()?;
+	(window_url.origin().ascii_serialization() == origin).then(|| origin.to_string())
+}
+
+enum RequestedRange {
+	Whole,
+	// from the start up to (but not including) the end
+	Part(u64, u64),
+	Unsatisfiable,
+}
+
+// only single ranges are served, anything else gets the whole blob as is allowed for
+// headers that can't be made sense of
+fn parse_range(range: &str, len: u64) -> RequestedRange {
+	let Some((start, end)) = range
+		.strip_prefix("bytes=")
+		.filter(|range| range.contains(',').not())
+		.and_then(|range| range.trim().split_once('-'))
+	else {
+		return RequestedRange::Whole;
+	};
+
+	// bytes=-<n> asks for the last n bytes
+	if start.is_empty() {
+		return match end.parse::<u64>() {
+			Ok(0) => RequestedRange::Unsatisfiable,
+			Ok(_) if len == 0 => RequestedRange::Unsatisfiable,
+			Ok(suffix) => RequestedRange::Part(len.saturating_sub(suffix), len),
+			Err(_) => RequestedRange::Whole,
+		};
+	}
+
+	let Ok(start) = start.parse::<u64>() else {
+		return RequestedRange::Whole;
+	};
+	let end = if end.is_empty() {
+		len
+	} else {
+		match end.parse::<u64>() {
+			Ok(end) if end >= start => (end + 1).min(len),
+			_ => return RequestedRange::Whole,
+		}
+	};
+
+	if start >= len {
+		return RequestedRange::Unsatisfiable;
+	}
+
+	RequestedRange::Part(start, end)
+}
+
+// blobs keep their extension for just this
+fn mime_type(path: &Path) -> &'static str {
+	let extension = path
+		.extension()
+		.map(|ext| ext.to_string_lossy().to_string())
+		.unwrap_or_default();
+	match extension.as_str() {
+		"pdf" => "application/pdf",
+		"png" => "image/png",
+		"jpg" | "jpeg" => "image/jpeg",
+		"gif" => "image/gif",
+		"webp" => "image/webp",
+		"svg" => "image/svg+xml",
+		"bmp" => "image/bmp",
+		"ico" => "image/x-icon",
+		"mp4" => "video/mp4",
+		"webm" => "video/webm",
+		"mp3" => "audio/mpeg",
+		"ogg" => "audio/ogg",
+		"wav" => "audio/wav",
+		"html" | "htm" => "text/html",
+		"css" => "text/css",
+		"js" => "text/javascript",
+		"json" => "application/json",
+		"txt" => "text/plain",
+		_ => "application/octet-stream",
+	}
+}
@@ -0,0 +1,129 @@
+use std::{
+	ops::Not,
+	sync::atomic::{AtomicBool, Ordering},
+	time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_http::reqwest;
+use tauri_specta::Event;
+
+use crate::{account, scheduler::SchedulerState, sync_task};
+
+// probe less often while things are working, we'll hear about it from failed syncs anyway
+const ONLINE_PROBE_INTERVAL: Duration = Duration::from_secs(60);
+const OFFLINE_PROBE_INTERVAL: Duration = Duration::from_secs(10);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// whether the moodle host is reachable. while offline syncs aren't attempted, commands
+// only serve what's stored and the syncs they would have started are deferred until
+// the host is reachable again
+pub struct ConnectivityState {
+	online: AtomicBool,
+}
+
+impl Default for ConnectivityState {
+	fn default() -> Self {
+		// assume we're online until a probe says otherwise
+		Self {
+			online: AtomicBool::new(true),
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize, Type, Debug, Clone, Event)]
+pub struct ConnectivityEvent {
+	pub online: bool,
+}
+
+pub fn start(app: AppHandle) {
+	tauri::async_runtime::spawn(async move {
+		loop {
+			check(&app).await;
+
+			let interval = if is_online(&app) {
+				ONLINE_PROBE_INTERVAL
+			} else {
+				OFFLINE_PROBE_INTERVAL
+			};
+
+			tokio::time::sleep(interval).await;
+		}
+	});
+}
+
+pub fn is_online(app: &AppHandle) -> bool {
+	app
+		.state::<ConnectivityState>()
+		.online
+		.load(Ordering::Relaxed)
+}
+
+/// probes the host and updates the connectivity state, resolving to whether it's reachable
+pub async fn check(app: &AppHandle) -> bool {
+	let online = probe(app).await;
+	set_online(app, online).await;
+	online
+}
+
+async fn probe(app: &AppHandle) -> bool {
+	// the host of the account the ui is showing, other accounts' syncs just fail with a
+	// network error if theirs happens to be down
+	let host = account::active(app)
+		.await
+		.unwrap_or_else(|e| {
+			log::error!("Failed to query active account: {:#}", e);
+			None
+		})
+		.map(|account| account.host)
+		.filter(|host| host.is_empty().not());
+
+	// nothing to probe before logging in
+	let Some(host) = host else {
+		return true;
+	};
+
+	let client = match reqwest::Client::builder().timeout(PROBE_TIMEOUT).build() {
+		Ok(client) => client,
+		Err(e) => {
+			log::error!("Failed to build connectivity probe client: {}", e);
+			return true;
+		}
+	};
+
+	// any response at all means the host is reachable, we don't care what it says
+	client.head(host).send().await.is_ok()
+}
+
+async fn set_online(app: &AppHandle, online: bool) {
+	let state = app.state::<ConnectivityState>();
+	if state.online.swap(online, Ordering::Relaxed) == online {
+		return;
+	}
+
+	if online.not() {
+		log::warn!("Moodle host is unreachable, serving stored content only");
+		ConnectivityEvent { online }.emit(app).ok();
+		return;
+	}
+
+	log::info!("Moodle host is reachable again, resuming deferred syncs");
+	ConnectivityEvent { online }.emit(app).ok();
+	sync_task::resume_deferred(app).await;
+	app.state::<SchedulerState>().wake.notify_one();
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn get_connectivity(app: AppHandle) -> Result<bool, String> {
+	Ok(is_online(&app))
+}
+
+/// probes the host now rather than waiting on the monitor, i.e. a "retry" button
+#[tauri::command]
+#[specta::specta]
+pub async fn check_connectivity(app: AppHandle) -> Result<bool, String> {
+	Ok(check(&app).await)
+}
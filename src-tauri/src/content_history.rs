@@ -0,0 +1,211 @@
+use std::{ops::Not, sync::OnceLock};
+
+use regex::Regex;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect};
+use serde::{Deserialize, Serialize};
+use similar::{Algorithm, ChangeTag, TextDiff};
+use specta::Type;
+use tauri::{AppHandle, Manager};
+
+use entity::module_content_version::Model as ModuleContentVersion;
+
+use crate::{account, database::DatabaseState};
+
+static HIDDEN_RE: OnceLock<Regex> = OnceLock::new();
+static BREAK_RE: OnceLock<Regex> = OnceLock::new();
+static TAG_RE: OnceLock<Regex> = OnceLock::new();
+
+// versions are listed without their content, which is only needed to diff them
+#[derive(Serialize, Deserialize, Type, Debug, Clone)]
+pub struct ModuleContentVersionInfo {
+	pub id: i32,
+	pub content_id: i32,
+	pub created_at: i64,
+	pub size: u64,
+}
+
+#[derive(Serialize, Deserialize, Type, Debug, Clone, Copy, PartialEq)]
+pub enum DiffFormat {
+	// a unified diff, line by line
+	Text,
+	// the newer text with removed words in <del> and added words in <ins>
+	Html,
+}
+
+/// versions of every content block in the module, newest first
+#[tauri::command]
+#[specta::specta]
+pub async fn list_module_versions(
+	app: AppHandle,
+	module_id: i32,
+) -> Result<Vec<ModuleContentVersionInfo>, String> {
+	let account_id = account::require_active(&app)?;
+	let state = app.state::<DatabaseState>();
+	let versions = entity::ModuleContentVersion::find()
+		.select_only()
+		.column(entity::module_content_version::Column::Id)
+		.column(entity::module_content_version::Column::ContentId)
+		.column(entity::module_content_version::Column::CreatedAt)
+		.column_as(
+			sea_orm::sea_query::Expr::cust("LENGTH(CAST(content AS BLOB))"),
+			"size",
+		)
+		.filter(entity::module_content_version::Column::AccountId.eq(account_id))
+		.filter(entity::module_content_version::Column::ModuleId.eq(module_id))
+		.order_by_desc(entity::module_content_version::Column::CreatedAt)
+		.order_by_desc(entity::module_content_version::Column::Id)
+		.into_tuple::<(i32, i32, i64, i64)>()
+		.all(&state.0)
+		.await
+		.map_err(|e| e.to_string())?;
+
+	Ok(
+		versions
+			.into_iter()
+			.map(
+				|(id, content_id, created_at, size)| ModuleContentVersionInfo {
+					id,
+					content_id,
+					created_at,
+					size: size as u64,
+				},
+			)
+			.collect(),
+	)
+}
+
+/// compares the readable text of two versions, markup changes alone don't show up
+#[tauri::command]
+#[specta::specta]
+pub async fn diff_module_versions(
+	app: AppHandle,
+	from_version_id: i32,
+	to_version_id: i32,
+	format: DiffFormat,
+) -> Result<String, String> {
+	let from = find_version(&app, from_version_id).await?;
+	let to = find_version(&app, to_version_id).await?;
+
+	tokio::task::spawn_blocking(move || {
+		let old = readable_text(&from.content);
+		let new = readable_text(&to.content);
+		match format {
+			DiffFormat::Text => text_diff(&from, &old, &to, &new),
+			DiffFormat::Html => html_diff(&old, &new),
+		}
+	})
+	.await
+	.map_err(|e| e.to_string())
+}
+
+// version ids are unique across accounts, but another account's versions aren't ours to show
+async fn find_version(app: &AppHandle, version_id: i32) -> Result<ModuleContentVersion, String> {
+	let account_id = account::require_active(app)?;
+	let state = app.state::<DatabaseState>();
+	entity::ModuleContentVersion::find_by_id(version_id)
+		.one(&state.0)
+		.await
+		.map_err(|e| e.to_string())?
+		.filter(|version| version.account_id == account_id)
+		.ok_or_else(|| format!("Module content version with id {} not found", version_id))
+}
+
+// one line per block of text, without any markup
+fn readable_text(html: &str) -> String {
+	let hidden_re = HIDDEN_RE
+		.get_or_init(|| Regex::new(r"(?is)<script[^>]*>.*?</script>|<style[^>]*>.*?</style>").unwrap());
+	let break_re = BREAK_RE.get_or_init(|| {
+		Regex::new(r"(?i)<br\s*/?>|</(p|div|h[1-6]|li|tr|table|ul|ol|blockquote|pre)>").unwrap()
+	});
+	let tag_re = TAG_RE.get_or_init(|| Regex::new(r"<[^>]*>").unwrap());
+
+	let text = hidden_re.replace_all(html, "");
+	let text = break_re.replace_all(&text, "\n");
+	let text = tag_re.replace_all(&text, "");
+	let text = html_escape::decode_html_entities(&text);
+
+	text
+		.lines()
+		.map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+		.filter(|line| line.is_empty().not())
+		.collect::<Vec<_>>()
+		.join("\n")
+}
+
+fn text_diff(
+	from: &ModuleContentVersion,
+	old: &str,
+	to: &ModuleContentVersion,
+	new: &str,
+) -> String {
+	TextDiff::from_lines(old, new)
+		.unified_diff()
+		.context_radius(3)
+		.header(
+			&format!("version {} ({})", from.id, from.created_at),
+			&format!("version {} ({})", to.id, to.created_at),
+		)
+		.to_string()
+}
+
+fn html_diff(old: &str, new: &str) -> String {
+	let diff = TextDiff::configure()
+		.algorithm(Algorithm::Patience)
+		.diff_words(old, new);
+
+	// consecutive changes of the same kind are wrapped together
+	let mut html = String::new();
+	let mut run = (ChangeTag::Equal, String::new());
+	for change in diff.iter_all_changes() {
+		if change.tag() != run.0 {
+			push_run(&mut html, run.0, &run.1);
+			run = (change.tag(), String::new());
+		}
+		run.1.push_str(change.value());
+	}
+	push_run(&mut html, run.0, &run.1);
+
+	html
+}
+
+fn push_run(html: &mut String, tag: ChangeTag, text: &str) {
+	if text.is_empty() {
+		return;
+	}
+
+	let escaped = html_escape::encode_text(text).replace('\n', "<br>");
+	match tag {
+		ChangeTag::Equal => html.push_str(&escaped),
+		ChangeTag::Delete => html.push_str(&format!("<del>{}</del>", escaped)),
+		ChangeTag::Insert => html.push_str(&format!("<ins>{}</ins>", escaped)),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn readable_text_strips_markup() {
+		let html = "<style>p { color: red; }</style><p>Hello&nbsp;<b>world</b></p>\
+			<script>alert(1)</script><ul><li>first   item</li><li>second</li></ul>";
+		assert_eq!(readable_text(html), "Hello world\nfirst item\nsecond");
+	}
+
+	#[test]
+	fn readable_text_breaks_on_br() {
+		assert_eq!(readable_text("one<br>two<BR />three"), "one\ntwo\nthree");
+	}
+
+	#[test]
+	fn html_diff_marks_changed_words() {
+		let diff = html_diff("the quick fox", "the slow fox");
+		assert_eq!(diff, "the <del>quick</del><ins>slow</ins> fox");
+	}
+
+	#[test]
+	fn html_diff_escapes_text() {
+		let diff = html_diff("a <b>\nc", "a <b>\nc");
+		assert_eq!(diff, "a &lt;b&gt;<br>c");
+	}
+}
@@ -1,12 +1,35 @@
 use std::{
 	env::{self, set_var},
-	fs::{create_dir_all, remove_file},
+	fs::{File, create_dir_all},
+	io::Read,
+	ops::Not,
+	path::{Path, PathBuf},
+	sync::atomic::Ordering,
 	time::Duration,
 };
 
+use anyhow::Context;
 use migration::{Migrator, MigratorTrait};
-use sea_orm::{ConnectOptions, DatabaseConnection};
+use sea_orm::{
+	ConnectOptions, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait, TransactionTrait,
+};
+use sqlx::sqlite::{SqliteJournalMode, SqliteSynchronous};
 use tauri::{AppHandle, Manager};
+use tauri_specta::Event;
+
+use crate::{
+	blob_store,
+	encryption::Key,
+	scheduler::SchedulerState,
+	sync_task::{self, ContentUpdatedEvent},
+};
+
+// sqlite only ever has one writer, more connections just means more concurrent readers
+const MAX_CONNECTIONS: u32 = 8;
+const BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+const DATABASE_FILE: &str = "journey.db";
+// every plain sqlite database starts with this, sqlcipher encrypts it along with the rest
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
 
 pub struct Database {
 	pub connection: DatabaseConnection,
@@ -16,7 +39,8 @@ pub struct Database {
 pub struct DatabaseState(pub DatabaseConnection);
 
 impl Database {
-	pub async fn new(app_handle: &AppHandle) -> Result<Self, String> {
+	/// opens the database, encrypted with `key` when there is one
+	pub async fn new(app_handle: &AppHandle, key: Option<&Key>) -> Result<Self, String> {
 		let app_dir = app_handle
 			.path()
 			.app_data_dir()
@@ -24,7 +48,10 @@ impl Database {
 
 		// CHANGEEGE
 		create_dir_all(&app_dir).map_err(|e| format!("Failed to create app data directory: {}", e))?;
-		let db_path = app_dir.join("journey.db");
+		let db_path = app_dir.join(DATABASE_FILE);
+		install_export(&db_path, key.is_some())
+			.map_err(|e| format!("Failed to swap in exported database: {}", e))?;
+		let key_literal = key.map(|key| key.sqlcipher_literal());
 		unsafe {
 			set_var(
 				"DATABASE_URL",
@@ -32,19 +59,36 @@ impl Database {
 			)
 		};
 
-		// true for dev command or build --debug, false otherwise
-		// https://tauri.app/reference/environment-variables/
-		if env::var("TAURI_ENV_DEBUG").is_ok() {
-			remove_file(&db_path).ok();
-		}
-
 		let mut options = ConnectOptions::new(&env::var("DATABASE_URL").unwrap());
 		options
+			.max_connections(MAX_CONNECTIONS)
+			.min_connections(1)
 			.connect_timeout(Duration::from_secs(8))
-			.acquire_timeout(Duration::from_secs(8))
-			.idle_timeout(Duration::from_secs(8))
-			.max_lifetime(Duration::from_secs(8))
-			.sqlx_logging(true);
+			// a connection can wait on another's write for up to the busy timeout, so this
+			// leaves some room on top of it
+			.acquire_timeout(BUSY_TIMEOUT * 2)
+			// connections are cheap to keep around, but reopening them now and then returns
+			// memory sqlite holds on to
+			.idle_timeout(Duration::from_secs(60 * 10))
+			.max_lifetime(Duration::from_secs(60 * 60))
+			.sqlx_logging(true)
+			.map_sqlx_sqlite_opts(move |options| {
+				// sqlx makes sure the key is the first thing sent on a new connection
+				let options = match &key_literal {
+					Some(key_literal) => options.pragma("key", key_literal.to_string()),
+					None => options,
+				};
+				options
+					// the cascades in the schema depend on this
+					.foreign_keys(true)
+					// readers don't block the writer (or each other), so the ui can keep reading
+					// while background syncs write
+					.journal_mode(SqliteJournalMode::Wal)
+					// plenty safe with wal, only the last commits can be lost on power loss
+					.synchronous(SqliteSynchronous::Normal)
+					// wait on the lock rather than fail with "database is locked" straight away
+					.busy_timeout(BUSY_TIMEOUT)
+			});
 
 		let db = sea_orm::Database::connect(options)
 			.await
@@ -56,3 +100,167 @@ impl Database {
 		Ok(Self { connection: db })
 	}
 }
+
+// a copy of the database exported when encryption is turned on or off. it's swapped in on
+// the next start, as the pool can't let go of the file while the app is running
+fn export_path(db_path: &Path, encrypted: bool) -> PathBuf {
+	let suffix = if encrypted { "encrypted" } else { "plain" };
+	db_path.with_file_name(format!("{}.{}-export", DATABASE_FILE, suffix))
+}
+
+// an export that doesn't match whether there's a key was left behind by a switch that
+// didn't finish, so it's the database as it is that's kept
+fn install_export(db_path: &Path, encrypted: bool) -> std::io::Result<()> {
+	let stale = export_path(db_path, encrypted.not());
+	if stale.exists() {
+		std::fs::remove_file(&stale)?;
+	}
+
+	let export = export_path(db_path, encrypted);
+	if export.exists().not() {
+		return Ok(());
+	}
+
+	// whatever was in the wal of the old database made it into the export
+	for suffix in ["-wal", "-shm"] {
+		let mut path = db_path.as_os_str().to_owned();
+		path.push(suffix);
+		std::fs::remove_file(path).ok();
+	}
+
+	log::info!("Swapping in exported database {}", export.display());
+	std::fs::rename(&export, db_path)
+}
+
+fn database_path(app_handle: &AppHandle) -> PathBuf {
+	app_handle
+		.path()
+		.app_data_dir()
+		.expect("failed to get app data dir")
+		.join(DATABASE_FILE)
+}
+
+/// whether the database is encrypted once any export waiting to be swapped in is, without
+/// opening it
+pub fn is_encrypted_on_disk(app_handle: &AppHandle) -> std::io::Result<bool> {
+	let db_path = database_path(app_handle);
+	if export_path(&db_path, true).exists() {
+		return Ok(true);
+	}
+
+	let mut header = [0; SQLITE_HEADER.len()];
+	match File::open(&db_path).and_then(|mut file| file.read_exact(&mut header)) {
+		Ok(()) => Ok(&header != SQLITE_HEADER),
+		// nothing has been written to it yet
+		Err(e)
+			if matches!(
+				e.kind(),
+				std::io::ErrorKind::NotFound | std::io::ErrorKind::UnexpectedEof
+			) =>
+		{
+			Ok(false)
+		}
+		Err(e) => Err(e),
+	}
+}
+
+/// removes an export that won't be swapped in after all
+pub fn discard_export(app_handle: &AppHandle, encrypted: bool) -> std::io::Result<()> {
+	match std::fs::remove_file(export_path(&database_path(app_handle), encrypted)) {
+		Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+		_ => Ok(()),
+	}
+}
+
+/// copies the whole database into an export encrypted with `key`, or a plain one without,
+/// which replaces the database on the next start
+pub async fn export(app_handle: &AppHandle, key: Option<&Key>) -> anyhow::Result<()> {
+	let db_path = database_path(app_handle);
+	let export = export_path(&db_path, key.is_some());
+	// it's written under another name first, so a half written export is never swapped in
+	let mut partial = export.as_os_str().to_owned();
+	partial.push(".partial");
+	let partial = PathBuf::from(partial);
+	std::fs::remove_file(&partial).ok();
+
+	let key_literal = key
+		.map(|key| key.sqlcipher_literal().to_string())
+		.unwrap_or_else(|| "''".to_string());
+	// all in one go, attached databases only exist on the connection that attached them
+	let state = app_handle.state::<DatabaseState>();
+	state
+		.0
+		.execute_unprepared(&format!(
+			"ATTACH DATABASE '{}' AS export KEY {}; SELECT sqlcipher_export('export'); DETACH DATABASE export;",
+			partial.to_string_lossy().replace('\'', "''"),
+			key_literal
+		))
+		.await
+		.with_context(|| "Failed to export database")?;
+
+	std::fs::rename(&partial, &export)
+		.with_context(|| format!("Failed to move database export to {}", export.display()))?;
+	Ok(())
+}
+
+/// removes everything stored along with every stored blob. accounts are kept under the same
+/// ids (which the active account and blob directories go by), so whatever the ui asks for
+/// next is synced again from scratch
+#[tauri::command]
+#[specta::specta]
+pub async fn reset_database(app: AppHandle) -> Result<(), String> {
+	// a sync still running would write rows and blobs back under what's removed, and the
+	// scheduler would start more straight away
+	let scheduler = app.state::<SchedulerState>();
+	let was_paused = scheduler.paused.swap(true, Ordering::Relaxed);
+	sync_task::cancel_all(&app).await;
+	let result = reset(&app).await;
+	scheduler.paused.store(was_paused, Ordering::Relaxed);
+	result
+}
+
+async fn reset(app: &AppHandle) -> Result<(), String> {
+	let state = app.state::<DatabaseState>();
+	// child tables first, in one transaction. Migrator::fresh turns foreign_keys off and on
+	// with statements that can each land on another pooled connection, which could leave one
+	// of them without the cascades the schema depends on
+	let txn = state
+		.0
+		.begin()
+		.await
+		.map_err(|e| format!("Failed to reset database: {}", e))?;
+	let deleted: Result<(), DbErr> = async {
+		entity::ModuleContentVersion::delete_many()
+			.exec(&txn)
+			.await?;
+		entity::ContentBlob::delete_many().exec(&txn).await?;
+		entity::ModuleContent::delete_many().exec(&txn).await?;
+		entity::SectionModule::delete_many().exec(&txn).await?;
+		entity::CourseSection::delete_many().exec(&txn).await?;
+		entity::Course::delete_many().exec(&txn).await?;
+		entity::SyncLog::delete_many().exec(&txn).await?;
+		Ok(())
+	}
+	.await;
+	deleted.map_err(|e| format!("Failed to reset database: {}", e))?;
+	txn
+		.commit()
+		.await
+		.map_err(|e| format!("Failed to reset database: {}", e))?;
+
+	let blobs_root = blob_store::blobs_root(app);
+	if let Err(e) = tokio::fs::remove_dir_all(&blobs_root).await
+		&& e.kind() != std::io::ErrorKind::NotFound
+	{
+		return Err(format!("Failed to remove content blobs: {}", e));
+	}
+
+	log::info!("Database has been reset");
+	ContentUpdatedEvent {
+		course_id: None,
+		module_id: None,
+	}
+	.emit(app)
+	.ok();
+	Ok(())
+}
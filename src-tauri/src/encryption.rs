@@ -0,0 +1,817 @@
+use std::{
+	fs::File,
+	io::{Read, Seek, SeekFrom, Write},
+	ops::Not,
+	path::{Path, PathBuf},
+	sync::{Arc, RwLock},
+};
+
+use anyhow::{Context, anyhow, bail};
+use argon2::Argon2;
+use base64::Engine;
+use chacha20poly1305::{
+	ChaCha20Poly1305, KeyInit, Nonce,
+	aead::{
+		Aead,
+		generic_array::GenericArray,
+		stream::{NewStream, StreamBE32, StreamPrimitive},
+	},
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use specta::Type;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_store::StoreExt;
+use zeroize::Zeroizing;
+
+use crate::{account, blob_store, database, scheduler, sync_task};
+
+// at-rest encryption is opt-in. once enabled the database is encrypted with sqlcipher, which
+// covers the accounts' tokens, and content blobs are sealed in chunks with
+// chacha20-poly1305. the key never touches the disk unless it comes from a key file, so nothing
+// that needs the database starts until it's been unlocked
+
+pub mod encryption_keys {
+	pub const KEY_SOURCE: &str = "encryption_key_source";
+	pub const SALT: &str = "encryption_salt";
+	pub const KEY_FILE: &str = "encryption_key_file";
+	// a known value sealed with the key, to tell a wrong passphrase from a broken database
+	pub const VERIFIER: &str = "encryption_verifier";
+	// set while encryption is being turned on or off, so a switch the app didn't get to
+	// finish is picked up on the next start
+	pub const PENDING: &str = "encryption_pending";
+}
+
+const KEY_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+// key files shorter than this don't carry enough entropy to be worth much
+const MIN_KEY_FILE_LEN: u64 = 32;
+const VERIFIER_TEXT: &str = "journey";
+const SEALED_SECRET_PREFIX: &str = "sealed:";
+
+// sealed blobs start with the magic and the stream nonce, followed by the content in
+// chunks that are each sealed on their own (see the stream construction in the aead crate)
+const BLOB_MAGIC: &[u8; 8] = b"JRNBLOB1";
+const STREAM_NONCE_LEN: usize = NONCE_LEN - 5;
+const BLOB_HEADER_LEN: u64 = (BLOB_MAGIC.len() + STREAM_NONCE_LEN) as u64;
+const CHUNK_LEN: usize = 64 * 1024;
+const STAGING_SUFFIX: &str = ".staging";
+
+#[derive(Clone)]
+pub struct Key(Arc<Zeroizing<[u8; KEY_LEN]>>);
+
+impl Key {
+	fn cipher(&self) -> ChaCha20Poly1305 {
+		ChaCha20Poly1305::new(chacha20poly1305::Key::from_slice(self.0.as_slice()))
+	}
+
+	// sqlcipher takes a raw key as a blob literal, which skips its own key derivation
+	pub fn sqlcipher_literal(&self) -> Zeroizing<String> {
+		let hex = self
+			.0
+			.iter()
+			.map(|b| format!("{b:02x}"))
+			.collect::<String>();
+		Zeroizing::new(format!("\"x'{hex}'\""))
+	}
+}
+
+// holds the key once unlocked, none while locked or when encryption isn't enabled
+#[derive(Default)]
+pub struct EncryptionState(RwLock<Option<Key>>);
+
+#[derive(Serialize, Deserialize, Type, Debug, Clone, Copy, PartialEq)]
+pub enum KeySourceKind {
+	Passphrase,
+	KeyFile,
+}
+
+#[derive(Serialize, Deserialize, Type, Clone)]
+pub enum KeySource {
+	Passphrase(String),
+	// a key file at the given path, generated when there's nothing there yet. any file will
+	// do as long as it doesn't change. it has to be given when enabling, when unlocking
+	// none reads it from where it was when enabled
+	KeyFile(Option<String>),
+}
+
+impl KeySource {
+	fn kind(&self) -> KeySourceKind {
+		match self {
+			KeySource::Passphrase(_) => KeySourceKind::Passphrase,
+			KeySource::KeyFile(_) => KeySourceKind::KeyFile,
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum PendingSwitch {
+	Enable,
+	Disable,
+}
+
+#[derive(Serialize, Deserialize, Type, Debug, Clone)]
+pub struct EncryptionStatus {
+	// none when encryption isn't enabled
+	pub key_source: Option<KeySourceKind>,
+	pub locked: bool,
+	// whether this build has sqlcipher to encrypt the database with
+	pub available: bool,
+}
+
+pub fn key(app: &AppHandle) -> Option<Key> {
+	app.state::<EncryptionState>().0.read().unwrap().clone()
+}
+
+fn key_source(app: &AppHandle) -> Option<KeySourceKind> {
+	let store = app.store("store.json").ok()?;
+	store
+		.get(encryption_keys::KEY_SOURCE)
+		.and_then(|value| serde_json::from_value(value).ok())
+}
+
+pub fn is_locked(app: &AppHandle) -> bool {
+	key_source(app).is_some() && key(app).is_none()
+}
+
+fn pending_switch(app: &AppHandle) -> Option<PendingSwitch> {
+	let store = app.store("store.json").ok()?;
+	store
+		.get(encryption_keys::PENDING)
+		.and_then(|value| serde_json::from_value(value).ok())
+}
+
+fn clear_store_keys(app: &AppHandle) -> anyhow::Result<()> {
+	let store = app.store("store.json")?;
+	for store_key in [
+		encryption_keys::KEY_SOURCE,
+		encryption_keys::SALT,
+		encryption_keys::KEY_FILE,
+		encryption_keys::VERIFIER,
+		encryption_keys::PENDING,
+	] {
+		store.delete(store_key);
+	}
+	store.save()?;
+	Ok(())
+}
+
+// the blobs are only sealed once the encrypted export is in place, so an enable that stopped
+// before then left nothing but the store keys behind and is undone. anything further along is
+// finished once unlocked, see finish_pending
+fn recover_pending(app: &AppHandle) -> anyhow::Result<()> {
+	if pending_switch(app) != Some(PendingSwitch::Enable) || database::is_encrypted_on_disk(app)? {
+		return Ok(());
+	}
+
+	log::warn!("Encryption was still being enabled when the app stopped, rolling it back");
+	clear_store_keys(app)
+}
+
+/// finishes a switch the app didn't get to finish, once the database is open. the database
+/// that was opened is encrypted either way (an unfinished disable keeps the encrypted one), so
+/// any blobs that aren't sealed yet, or were opened again already, are sealed
+pub async fn finish_pending(app: &AppHandle) -> anyhow::Result<()> {
+	let Some(pending) = pending_switch(app) else {
+		return Ok(());
+	};
+
+	let key = key(app).with_context(|| "Encryption is locked")?;
+	let sealed = blob_store::convert_all(app, key, true).await?;
+	log::info!(
+		"Finished interrupted encryption switch ({:?}), sealed {} content blobs",
+		pending,
+		sealed
+	);
+
+	let store = app.store("store.json")?;
+	store.delete(encryption_keys::PENDING);
+	store.save()?;
+	Ok(())
+}
+
+/// unlocks with the key file at startup when that's where the key comes from, so only a
+/// passphrase (or a key file that's gone missing) needs the unlock step. resolves to
+/// whether anything still needs unlocking
+pub fn unlock_on_startup(app: &AppHandle) -> bool {
+	if let Err(e) = recover_pending(app) {
+		log::error!("Failed to recover interrupted encryption switch: {:#}", e);
+	}
+
+	if key_source(app) != Some(KeySourceKind::KeyFile) {
+		return is_locked(app);
+	}
+
+	match unlock_with(app, KeySource::KeyFile(None)) {
+		Ok(_) => false,
+		Err(e) => {
+			log::warn!("Failed to unlock with key file: {:#}", e);
+			true
+		}
+	}
+}
+
+// resolves to false when something else unlocked it first
+fn unlock_with(app: &AppHandle, source: KeySource) -> anyhow::Result<bool> {
+	let store = app.store("store.json")?;
+	let Some(kind) = key_source(app) else {
+		bail!("Encryption isn't enabled");
+	};
+	if kind != source.kind() {
+		bail!("Encryption is unlocked with a {:?}", kind);
+	}
+
+	let salt = store
+		.get(encryption_keys::SALT)
+		.and_then(|salt| salt.as_str().map(str::to_string))
+		.map(|salt| base64::prelude::BASE64_STANDARD.decode(salt))
+		.transpose()
+		.with_context(|| "Failed to decode salt")?
+		.unwrap_or_default();
+	// a key file given to unlock just overrides where it's read from this time, i.e. a
+	// removable drive mounted somewhere else
+	let key_file = match &source {
+		KeySource::KeyFile(Some(path)) => Some(PathBuf::from(path)),
+		KeySource::KeyFile(None) => store
+			.get(encryption_keys::KEY_FILE)
+			.and_then(|path| path.as_str().map(PathBuf::from)),
+		KeySource::Passphrase(_) => None,
+	};
+
+	let key = derive_key(&source, &salt, key_file.as_deref())?;
+	let verifier = store
+		.get(encryption_keys::VERIFIER)
+		.and_then(|verifier| verifier.as_str().map(str::to_string))
+		.with_context(|| "Encryption verifier not found")?;
+	match open_secret(&key, &verifier) {
+		Ok(text) if text == VERIFIER_TEXT => {}
+		_ => bail!("Wrong passphrase or key file"),
+	}
+
+	let state = app.state::<EncryptionState>();
+	let mut current = state.0.write().unwrap();
+	if current.is_some() {
+		return Ok(false);
+	}
+
+	*current = Some(key);
+	Ok(true)
+}
+
+fn derive_key(source: &KeySource, salt: &[u8], key_file: Option<&Path>) -> anyhow::Result<Key> {
+	let mut key = Zeroizing::new([0; KEY_LEN]);
+	match source {
+		KeySource::Passphrase(passphrase) => {
+			if passphrase.is_empty() {
+				bail!("Passphrase can't be empty");
+			}
+
+			Argon2::default()
+				.hash_password_into(passphrase.as_bytes(), salt, &mut key[..])
+				.map_err(|e| anyhow!("Failed to derive key from passphrase: {}", e))?;
+		}
+		KeySource::KeyFile(_) => {
+			let path = key_file.with_context(|| "Key file not set")?;
+			let mut file =
+				File::open(path).with_context(|| format!("Failed to open key file {}", path.display()))?;
+			if file.metadata()?.len() < MIN_KEY_FILE_LEN {
+				bail!("Key file has to be at least {} bytes", MIN_KEY_FILE_LEN);
+			}
+
+			let mut hasher = Sha256::new();
+			std::io::copy(&mut file, &mut hasher)
+				.with_context(|| format!("Failed to read key file {}", path.display()))?;
+			key.copy_from_slice(&hasher.finalize());
+		}
+	}
+
+	Ok(Key(Arc::new(key)))
+}
+
+// only readable by the current user where the platform lets us say so
+fn create_key_file(path: &Path) -> anyhow::Result<()> {
+	let mut options = std::fs::OpenOptions::new();
+	options.write(true).create_new(true);
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::OpenOptionsExt;
+		options.mode(0o600);
+	}
+
+	let mut bytes = Zeroizing::new([0; KEY_LEN]);
+	rand::rng().fill(&mut bytes[..]);
+	let mut file = options
+		.open(path)
+		.with_context(|| format!("Failed to create key file {}", path.display()))?;
+	file.write_all(&bytes[..])?;
+	file.sync_all()?;
+	Ok(())
+}
+
+// a key kept next to what it encrypts is copied along with it, so it has to live elsewhere,
+// i.e. on a removable drive
+fn is_in_app_data(app: &AppHandle, path: &Path) -> bool {
+	let app_data_dir = app
+		.path()
+		.app_data_dir()
+		.expect("failed to get app data dir");
+	let app_data_dir = app_data_dir.canonicalize().unwrap_or(app_data_dir);
+	// the file itself might not exist yet
+	let path = path
+		.parent()
+		.and_then(|parent| parent.canonicalize().ok())
+		.map(|parent| parent.join(path.file_name().unwrap_or_default()))
+		.unwrap_or_else(|| path.to_path_buf());
+	path.starts_with(app_data_dir)
+}
+
+pub fn seal_secret(key: &Key, secret: &str) -> String {
+	let mut nonce = [0; NONCE_LEN];
+	rand::rng().fill(&mut nonce);
+	let sealed = key
+		.cipher()
+		.encrypt(Nonce::from_slice(&nonce), secret.as_bytes())
+		.expect("failed to seal secret");
+
+	let mut bytes = nonce.to_vec();
+	bytes.extend(sealed);
+	format!(
+		"{}{}",
+		SEALED_SECRET_PREFIX,
+		base64::prelude::BASE64_STANDARD.encode(bytes)
+	)
+}
+
+pub fn open_secret(key: &Key, sealed: &str) -> anyhow::Result<String> {
+	let bytes = base64::prelude::BASE64_STANDARD
+		.decode(sealed.trim_start_matches(SEALED_SECRET_PREFIX))
+		.with_context(|| "Failed to decode sealed secret")?;
+	if bytes.len() < NONCE_LEN {
+		bail!("Sealed secret is too short");
+	}
+
+	let (nonce, sealed) = bytes.split_at(NONCE_LEN);
+	let secret = key
+		.cipher()
+		.decrypt(Nonce::from_slice(nonce), sealed)
+		.map_err(|_| anyhow!("Failed to open sealed secret"))?;
+	String::from_utf8(secret).with_context(|| "Sealed secret isn't valid utf-8")
+}
+
+/// the plain value of a secret from the store, whether it was sealed or not. tokens were
+/// sealed in the store before they moved into the database
+pub fn reveal(app: &AppHandle, value: &str) -> anyhow::Result<String> {
+	if value.starts_with(SEALED_SECRET_PREFIX).not() {
+		return Ok(value.to_string());
+	}
+
+	let key = key(app).with_context(|| "Encryption is locked")?;
+	open_secret(&key, value)
+}
+
+fn stream(key: &Key, nonce: &[u8]) -> StreamBE32<ChaCha20Poly1305> {
+	StreamBE32::from_aead(key.cipher(), GenericArray::from_slice(nonce))
+}
+
+fn chunk_count(plain_len: u64) -> u64 {
+	plain_len.div_ceil(CHUNK_LEN as u64).max(1)
+}
+
+fn invalid_data(message: &str) -> std::io::Error {
+	std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string())
+}
+
+pub fn is_sealed(path: &Path) -> std::io::Result<bool> {
+	let mut magic = [0; BLOB_MAGIC.len()];
+	let mut file = File::open(path)?;
+	match file.read_exact(&mut magic) {
+		Ok(()) => Ok(&magic == BLOB_MAGIC),
+		Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+		Err(e) => Err(e),
+	}
+}
+
+/// size of the content stored at `path`, without the overhead of sealing it
+pub fn content_len(path: &Path) -> std::io::Result<u64> {
+	let len = std::fs::metadata(path)?.len();
+	if is_sealed(path)?.not() {
+		return Ok(len);
+	}
+
+	let body = len.saturating_sub(BLOB_HEADER_LEN);
+	let chunks = body.div_ceil((CHUNK_LEN + TAG_LEN) as u64);
+	Ok(body.saturating_sub(chunks * TAG_LEN as u64))
+}
+
+/// writes `src` sealed with `key` to `dst`
+pub fn seal_file(key: &Key, src: &Path, dst: &Path) -> std::io::Result<()> {
+	let mut input = File::open(src)?;
+	let mut output = File::create(dst)?;
+	let mut nonce = [0; STREAM_NONCE_LEN];
+	rand::rng().fill(&mut nonce);
+	output.write_all(BLOB_MAGIC)?;
+	output.write_all(&nonce)?;
+
+	let stream = stream(key, &nonce);
+	let mut remaining = input.metadata()?.len();
+	let chunks = chunk_count(remaining);
+	let mut chunk = vec![0; CHUNK_LEN];
+	for position in 0..chunks {
+		let len = remaining.min(CHUNK_LEN as u64) as usize;
+		input.read_exact(&mut chunk[..len])?;
+		remaining -= len as u64;
+
+		let sealed = stream
+			.encrypt(position as u32, position + 1 == chunks, &chunk[..len])
+			.map_err(|_| invalid_data("Failed to seal chunk"))?;
+		output.write_all(&sealed)?;
+	}
+
+	output.sync_all()
+}
+
+/// reads the content of a sealed blob, checking each chunk as it goes
+pub struct SealedReader {
+	file: File,
+	stream: StreamBE32<ChaCha20Poly1305>,
+	position: u64,
+	chunks: u64,
+	remaining: u64,
+	buffer: Vec<u8>,
+	offset: usize,
+}
+
+impl SealedReader {
+	pub fn open(key: &Key, path: &Path) -> std::io::Result<Self> {
+		let mut file = File::open(path)?;
+		let mut header = [0; BLOB_HEADER_LEN as usize];
+		file.read_exact(&mut header)?;
+		if header.starts_with(BLOB_MAGIC).not() {
+			return Err(invalid_data("Content blob isn't sealed"));
+		}
+
+		let remaining = file.metadata()?.len() - BLOB_HEADER_LEN;
+		Ok(Self {
+			stream: stream(key, &header[BLOB_MAGIC.len()..]),
+			position: 0,
+			chunks: remaining.div_ceil((CHUNK_LEN + TAG_LEN) as u64),
+			remaining,
+			buffer: Vec::new(),
+			offset: 0,
+			file,
+		})
+	}
+}
+
+impl Read for SealedReader {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		if self.offset == self.buffer.len() {
+			if self.position == self.chunks {
+				return Ok(0);
+			}
+
+			let len = self.remaining.min((CHUNK_LEN + TAG_LEN) as u64) as usize;
+			let mut sealed = vec![0; len];
+			self.file.read_exact(&mut sealed)?;
+			self.remaining -= len as u64;
+
+			self.buffer = self
+				.stream
+				.decrypt(
+					self.position as u32,
+					self.position + 1 == self.chunks,
+					sealed.as_slice(),
+				)
+				.map_err(|_| {
+					invalid_data("Content blob failed to open, it's corrupt or sealed with another key")
+				})?;
+			self.position += 1;
+			self.offset = 0;
+		}
+
+		let len = buf.len().min(self.buffer.len() - self.offset);
+		buf[..len].copy_from_slice(&self.buffer[self.offset..self.offset + len]);
+		self.offset += len;
+		Ok(len)
+	}
+}
+
+fn locked() -> std::io::Error {
+	std::io::Error::new(
+		std::io::ErrorKind::PermissionDenied,
+		"Content blob is sealed and encryption is locked",
+	)
+}
+
+/// the content of a blob whether it's sealed or not, sealed blobs need the key
+pub fn open_file(key: Option<&Key>, path: &Path) -> std::io::Result<Box<dyn Read + Send>> {
+	if is_sealed(path)?.not() {
+		return Ok(Box::new(File::open(path)?));
+	}
+
+	match key {
+		Some(key) => Ok(Box::new(SealedReader::open(key, path)?)),
+		None => Err(locked()),
+	}
+}
+
+/// `len` bytes of a blob's content from `start`, whether it's sealed or not. only the chunks
+/// covering them are opened for a sealed blob
+pub fn read_range(
+	key: Option<&Key>,
+	path: &Path,
+	start: u64,
+	len: u64,
+) -> std::io::Result<Vec<u8>> {
+	let mut file = File::open(path)?;
+	if is_sealed(path)?.not() {
+		let mut body = vec![0; len as usize];
+		file.seek(SeekFrom::Start(start))?;
+		file.read_exact(&mut body)?;
+		return Ok(body);
+	}
+
+	let key = key.ok_or_else(locked)?;
+	let mut body = Vec::with_capacity(len as usize);
+	if len == 0 {
+		return Ok(body);
+	}
+
+	let mut header = [0; BLOB_HEADER_LEN as usize];
+	file.read_exact(&mut header)?;
+	let stream = stream(key, &header[BLOB_MAGIC.len()..]);
+	let sealed_chunk_len = (CHUNK_LEN + TAG_LEN) as u64;
+	let sealed_len = file.metadata()?.len() - BLOB_HEADER_LEN;
+	let chunks = sealed_len.div_ceil(sealed_chunk_len);
+	let first = start / CHUNK_LEN as u64;
+	let last = (start + len - 1) / CHUNK_LEN as u64;
+	if last >= chunks {
+		return Err(std::io::ErrorKind::UnexpectedEof.into());
+	}
+
+	file.seek(SeekFrom::Start(BLOB_HEADER_LEN + first * sealed_chunk_len))?;
+	let mut sealed = vec![0; sealed_chunk_len as usize];
+	for position in first..=last {
+		let chunk_len = (sealed_len - position * sealed_chunk_len).min(sealed_chunk_len) as usize;
+		file.read_exact(&mut sealed[..chunk_len])?;
+		let chunk = stream
+			.decrypt(
+				position as u32,
+				position + 1 == chunks,
+				&sealed[..chunk_len],
+			)
+			.map_err(|_| {
+				invalid_data("Content blob failed to open, it's corrupt or sealed with another key")
+			})?;
+
+		let from = if position == first {
+			(start % CHUNK_LEN as u64) as usize
+		} else {
+			0
+		};
+		let to = chunk.len().min(from + (len as usize - body.len()));
+		if from > to {
+			return Err(std::io::ErrorKind::UnexpectedEof.into());
+		}
+		body.extend_from_slice(&chunk[from..to]);
+	}
+
+	if body.len() as u64 != len {
+		return Err(std::io::ErrorKind::UnexpectedEof.into());
+	}
+
+	Ok(body)
+}
+
+// in-place conversions go through a sibling file, so an interrupted one leaves the blob as it was
+fn staging_path(path: &Path) -> PathBuf {
+	let mut staging = path.as_os_str().to_owned();
+	staging.push(STAGING_SUFFIX);
+	PathBuf::from(staging)
+}
+
+// left behind by a conversion the app didn't get to finish
+pub fn is_staging(path: &Path) -> bool {
+	path
+		.file_name()
+		.is_some_and(|name| name.to_string_lossy().ends_with(STAGING_SUFFIX))
+}
+
+pub fn seal_in_place(key: &Key, path: &Path) -> std::io::Result<bool> {
+	if is_sealed(path)? {
+		return Ok(false);
+	}
+
+	let staging = staging_path(path);
+	seal_file(key, path, &staging)?;
+	std::fs::rename(&staging, path)?;
+	Ok(true)
+}
+
+pub fn open_in_place(key: &Key, path: &Path) -> std::io::Result<bool> {
+	if is_sealed(path)?.not() {
+		return Ok(false);
+	}
+
+	let staging = staging_path(path);
+	let mut reader = SealedReader::open(key, path)?;
+	let mut output = File::create(&staging)?;
+	std::io::copy(&mut reader, &mut output)?;
+	output.sync_all()?;
+	std::fs::rename(&staging, path)?;
+	Ok(true)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn get_encryption_status(app: AppHandle) -> Result<EncryptionStatus, String> {
+	Ok(EncryptionStatus {
+		key_source: key_source(&app),
+		locked: is_locked(&app),
+		available: cfg!(feature = "encryption"),
+	})
+}
+
+/// unlocks encryption and starts everything that was waiting on the database. resolves to
+/// whether there's a signed in account, so the ui knows where to go from here
+#[tauri::command]
+#[specta::specta]
+pub async fn unlock_encryption(app: AppHandle, source: KeySource) -> Result<bool, String> {
+	if is_locked(&app) {
+		let unlock_app = app.clone();
+		// argon2 is slow on purpose
+		let unlocked = tokio::task::spawn_blocking(move || unlock_with(&unlock_app, source))
+			.await
+			.map_err(|e| e.to_string())?
+			.map_err(|e| e.to_string())?;
+		if unlocked {
+			crate::start_services(app.clone()).await?;
+		}
+	}
+
+	Ok(account::is_signed_in(&app).await)
+}
+
+/// encrypts the database and every stored blob, then restarts the app so the
+/// encrypted database is the one opened. a key file is generated at the given path when
+/// there's nothing there, which can't be in the app data directory
+#[tauri::command]
+#[specta::specta]
+pub async fn enable_encryption(app: AppHandle, source: KeySource) -> Result<(), String> {
+	enable(&app, source).await.map_err(|e| format!("{:#}", e))?;
+	app.restart()
+}
+
+async fn enable(app: &AppHandle, source: KeySource) -> anyhow::Result<()> {
+	if cfg!(not(feature = "encryption")) {
+		bail!("This build doesn't support encryption");
+	}
+
+	if key_source(app).is_some() {
+		bail!("Encryption is already enabled");
+	}
+
+	let mut salt = [0; SALT_LEN];
+	rand::rng().fill(&mut salt);
+	let key_file = match &source {
+		KeySource::KeyFile(Some(path)) => {
+			let path = PathBuf::from(path);
+			if path.is_absolute().not() {
+				bail!("The key file has to be given as an absolute path");
+			}
+
+			if is_in_app_data(app, &path) {
+				bail!("The key file has to be kept outside of the app data directory");
+			}
+
+			if path.exists().not() {
+				create_key_file(&path)?;
+			}
+			Some(path)
+		}
+		KeySource::KeyFile(None) => bail!("Choose where the key file is kept"),
+		KeySource::Passphrase(_) => None,
+	};
+
+	let derive_source = source.clone();
+	let derive_key_file = key_file.clone();
+	let key = tokio::task::spawn_blocking(move || {
+		derive_key(&derive_source, &salt, derive_key_file.as_deref())
+	})
+	.await??;
+
+	// anything written from here on would be lost with the exported copy swapped in
+	scheduler::pause_sync_scheduler(app.clone()).await.ok();
+	sync_task::cancel_all(app).await;
+
+	// everything the key is derived from is saved before anything is sealed with it
+	let store = app.store("store.json")?;
+	store.set(
+		encryption_keys::KEY_SOURCE,
+		serde_json::to_value(source.kind())?,
+	);
+	store.set(encryption_keys::VERIFIER, seal_secret(&key, VERIFIER_TEXT));
+	match key_file {
+		Some(key_file) => store.set(
+			encryption_keys::KEY_FILE,
+			key_file.to_string_lossy().to_string(),
+		),
+		None => store.set(
+			encryption_keys::SALT,
+			base64::prelude::BASE64_STANDARD.encode(salt),
+		),
+	}
+	store.set(
+		encryption_keys::PENDING,
+		serde_json::to_value(PendingSwitch::Enable)?,
+	);
+	store.save()?;
+
+	let switched = async {
+		database::export(app, Some(&key)).await?;
+		let sealed = blob_store::convert_all(app, key.clone(), true).await?;
+		log::info!("Sealed {} content blobs", sealed);
+		anyhow::Ok(())
+	}
+	.await;
+	if let Err(e) = switched {
+		// whatever was sealed so far is opened again, a crash in between is picked up on the
+		// next start like any other
+		blob_store::convert_all(app, key, false)
+			.await
+			.with_context(|| format!("Failed to roll back after: {:#}", e))?;
+		database::discard_export(app, true)?;
+		clear_store_keys(app)?;
+		return Err(e);
+	}
+
+	store.delete(encryption_keys::PENDING);
+	store.save()?;
+
+	log::info!("Encryption has been enabled");
+	Ok(())
+}
+
+/// decrypts everything again and restarts the app, encryption has to be unlocked first
+#[tauri::command]
+#[specta::specta]
+pub async fn disable_encryption(app: AppHandle) -> Result<(), String> {
+	disable(&app).await.map_err(|e| format!("{:#}", e))?;
+	app.restart()
+}
+
+async fn disable(app: &AppHandle) -> anyhow::Result<()> {
+	if key_source(app).is_none() {
+		bail!("Encryption isn't enabled");
+	}
+
+	let key = key(app).with_context(|| "Encryption has to be unlocked first")?;
+	scheduler::pause_sync_scheduler(app.clone()).await.ok();
+	sync_task::cancel_all(app).await;
+
+	// the key stays in the store until every blob has been opened, anything opened by a
+	// switch that doesn't finish is sealed again
+	let store = app.store("store.json")?;
+	store.set(
+		encryption_keys::PENDING,
+		serde_json::to_value(PendingSwitch::Disable)?,
+	);
+	store.save()?;
+
+	let switched = async {
+		database::export(app, None).await?;
+		let opened = blob_store::convert_all(app, key.clone(), false).await?;
+		log::info!("Opened {} content blobs", opened);
+		anyhow::Ok(())
+	}
+	.await;
+	if let Err(e) = switched {
+		blob_store::convert_all(app, key, true)
+			.await
+			.with_context(|| format!("Failed to roll back after: {:#}", e))?;
+		database::discard_export(app, false)?;
+		store.delete(encryption_keys::PENDING);
+		store.save()?;
+		return Err(e);
+	}
+
+	let key_file = store
+		.get(encryption_keys::KEY_FILE)
+		.and_then(|path| path.as_str().map(PathBuf::from));
+	clear_store_keys(app)?;
+
+	// earlier versions generated one in the app data directory, which is no use to anyone
+	// once it's decrypted. one kept elsewhere is left to the user
+	if let Some(key_file) = key_file
+		&& is_in_app_data(app, &key_file)
+	{
+		std::fs::remove_file(key_file).ok();
+	}
+
+	log::info!("Encryption has been disabled");
+	Ok(())
+}
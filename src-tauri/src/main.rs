@@ -11,25 +11,50 @@ use specta_typescript::{BigIntExportBehavior, Typescript};
 use tauri::async_runtime::Mutex;
 use tauri::{AppHandle, Manager, RunEvent, WindowEvent};
 use tauri_plugin_fs::FsExt;
-use tauri_plugin_store::StoreExt;
 use tauri_plugin_updater::UpdaterExt;
 use tauri_specta::{Builder, Event, collect_commands, collect_events};
 
+use crate::account::{list_accounts, switch_account};
 use crate::auth::{
-	AuthState, AuthStatus, MoodleAuthEvent, auth_keys, get_host, get_user_name, open_login_window,
+	AuthState, AuthStatus, MoodleAuthEvent, ReauthRequiredEvent, get_host, get_reauth_required,
+	get_user_name, login_with_credentials, login_with_qr, logout, open_login_window, reauthenticate,
+};
+use crate::connectivity::{
+	ConnectivityEvent, ConnectivityState, check_connectivity, get_connectivity,
+};
+use crate::content_history::{diff_module_versions, list_module_versions};
+use crate::database::reset_database;
+use crate::encryption::{
+	EncryptionState, disable_encryption, enable_encryption, get_encryption_status, unlock_encryption,
 };
 use crate::request::course::{
 	CourseSectionWithModules, CourseWithSections, SUPPORTED_MODULE_TYPES, SUPPORTED_RESOURCE_TYPES,
-	get_content_blobs, get_course, get_module_content, get_user_courses,
+	download_course_for_offline, get_content_blobs, get_course, get_module_content, get_user_courses,
+};
+use crate::scheduler::{
+	SchedulerState, pause_sync_scheduler, resume_sync_scheduler, set_sync_interval,
+};
+use crate::storage::{
+	get_storage_usage, prune_storage, set_course_pinned, set_module_pinned, set_storage_cap,
+	set_verify_storage_on_startup, verify_storage,
+};
+use crate::sync_task::{
+	ContentUpdatedEvent, SyncErrorEvent, SyncState, cancel_sync, get_sync_status, watch_sync_progress,
 };
-use crate::sync_task::{SyncErrorEvent, SyncState};
 
 const MIN_WINDOW_WIDTH: f64 = 300.0;
 const MIN_WINDOW_HEIGHT: f64 = 300.0;
 
+mod account;
 mod auth;
+mod blob_store;
+mod connectivity;
+mod content_history;
 mod database;
+mod encryption;
 mod request;
+mod scheduler;
+mod storage;
 mod sync_task;
 
 pub fn main() {
@@ -43,9 +68,45 @@ pub fn main() {
 			get_host,
 			get_course,
 			get_module_content,
-			get_content_blobs
+			get_content_blobs,
+			get_sync_status,
+			pause_sync_scheduler,
+			resume_sync_scheduler,
+			set_sync_interval,
+			watch_sync_progress,
+			cancel_sync,
+			get_connectivity,
+			check_connectivity,
+			download_course_for_offline,
+			prune_storage,
+			get_storage_usage,
+			set_storage_cap,
+			set_module_pinned,
+			set_course_pinned,
+			verify_storage,
+			set_verify_storage_on_startup,
+			list_module_versions,
+			diff_module_versions,
+			reset_database,
+			get_encryption_status,
+			unlock_encryption,
+			enable_encryption,
+			disable_encryption,
+			get_reauth_required,
+			reauthenticate,
+			logout,
+			list_accounts,
+			switch_account,
+			login_with_credentials,
+			login_with_qr
+		])
+		.events(collect_events![
+			MoodleAuthEvent,
+			SyncErrorEvent,
+			ContentUpdatedEvent,
+			ConnectivityEvent,
+			ReauthRequiredEvent
 		])
-		.events(collect_events![MoodleAuthEvent, SyncErrorEvent])
 		.typ::<Course>()
 		.typ::<CourseSection>()
 		.typ::<CourseSectionItem>()
@@ -83,6 +144,12 @@ pub fn main() {
 				.build(),
 		)
 		.plugin(tauri_plugin_updater::Builder::new().build())
+		.register_asynchronous_uri_scheme_protocol(blob_store::PROTOCOL, |ctx, request, responder| {
+			let app_handle = ctx.app_handle().clone();
+			tauri::async_runtime::spawn_blocking(move || {
+				responder.respond(blob_store::respond(&app_handle, &request));
+			});
+		})
 		.setup(move |app| {
 			builder.mount_events(app);
 			let app_handle = app.handle().clone();
@@ -98,20 +165,26 @@ pub fn main() {
 
 			app_handle.manage(Mutex::new(SyncState::default()));
 			app_handle.manage(Mutex::new(AuthState::default()));
+			app_handle.manage(SchedulerState::default());
+			app_handle.manage(ConnectivityState::default());
+			app_handle.manage(EncryptionState::default());
 
 			// #[cfg(debug_assertions)]
 			// console_subscriber::init();
 
-			let window_url = match app_handle.store("store.json") {
-				Ok(store) => {
-					if store.has(auth_keys::USER_ID) && store.has(auth_keys::WS_TOKEN) {
-						tauri::WebviewUrl::App("/home".into())
-					} else {
-						tauri::WebviewUrl::App("/".into())
-					}
-				}
-				Err(e) => {
-					log::error!("Failed to open store, forced to setup: {}", e);
+			// nothing that needs the database starts until encryption is unlocked, which is also
+			// where accounts are kept
+			let locked = encryption::unlock_on_startup(&app_handle);
+			let window_url = if locked {
+				tauri::WebviewUrl::App("/unlock".into())
+			} else {
+				tauri::async_runtime::block_on(start_services(app_handle.clone()))
+					.expect("failed to connect to database");
+				// a token moodle turned down still leaves everything synced with it readable, so
+				// that's kept on /home while the ui asks to sign in again
+				if tauri::async_runtime::block_on(account::is_signed_in(&app_handle)) {
+					tauri::WebviewUrl::App("/home".into())
+				} else {
 					tauri::WebviewUrl::App("/".into())
 				}
 			};
@@ -138,13 +211,6 @@ pub fn main() {
 			}
 
 			win_builder.build().unwrap();
-			tauri::async_runtime::block_on(async move {
-				let database = database::Database::new(&app_handle)
-					.await
-					.expect("failed to connect to database");
-				app_handle.manage(database::DatabaseState(database.connection));
-			});
-
 			Ok(())
 		})
 		.build(tauri::generate_context!())
@@ -184,6 +250,26 @@ pub fn main() {
 		})
 }
 
+// everything that needs the database, which (when encrypted) can't be opened until unlocked
+pub(crate) async fn start_services(app_handle: AppHandle) -> Result<(), String> {
+	let key = encryption::key(&app_handle);
+	let database = database::Database::new(&app_handle, key.as_ref()).await?;
+	app_handle.manage(database::DatabaseState(database.connection));
+	if let Err(e) = encryption::finish_pending(&app_handle).await {
+		log::error!("Failed to finish encryption switch: {:#}", e);
+	}
+
+	// before anything syncs, so it doesn't start out without an account to sync for
+	if let Err(e) = account::adopt_legacy(&app_handle).await {
+		log::error!("Failed to adopt legacy account: {:#}", e);
+	}
+
+	connectivity::start(app_handle.clone());
+	storage::verify_on_startup(app_handle.clone());
+	scheduler::start(app_handle);
+	Ok(())
+}
+
 async fn update(app: AppHandle) -> tauri_plugin_updater::Result<()> {
 	let update = app.updater()?.check().await?;
 	if update.is_none() {
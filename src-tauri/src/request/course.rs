@@ -1,5 +1,5 @@
 use std::{
-	collections::HashMap,
+	collections::{HashMap, HashSet},
 	ops::Not,
 	sync::{Arc, Mutex, OnceLock},
 	vec,
@@ -7,19 +7,20 @@ use std::{
 
 use anyhow::{Context, anyhow};
 use entity::section_module::SectionModuleType;
+use futures::StreamExt;
 use katex::{KatexContext, Settings as KatexSettings, render_to_string};
 use lol_html::{HtmlRewriter, Settings, element};
 use migration::Expr;
 use regex::Regex;
 use sea_orm::{
-	ActiveValue, ColumnTrait, Condition, EntityTrait, QueryFilter, TransactionTrait, sea_query,
+	ActiveValue, ColumnTrait, Condition, EntityTrait, QueryFilter, QueryOrder, TransactionTrait,
+	sea_query,
 };
 use serde::{Deserialize, Serialize};
 use specta::Type;
 use sqlx::types::chrono::Utc;
-use tauri::{AppHandle, Manager};
+use tauri::{AppHandle, Manager, ipc::Channel};
 use tauri_plugin_http::reqwest;
-use tauri_plugin_store::StoreExt;
 
 use entity::content_blob::Model as ContentBlob;
 use entity::course::Model as Course;
@@ -28,10 +29,11 @@ use entity::module_content::Model as ModuleContent;
 use entity::section_module::Model as SectionModule;
 
 use crate::{
-	auth::auth_keys,
+	account, blob_store, connectivity,
 	database::DatabaseState,
-	request::rest::{self, RestCourse, RestCourseSection},
-	sync_task::{SyncError, SyncTask},
+	request::rest::{self, RestCourse, RestCourseSection, RestCourseSectionModule},
+	storage::{self, Pruner},
+	sync_task::{self, ContentUpdatedEvent, SyncContext, SyncError, SyncProgress, SyncTask},
 };
 
 static KATEX_CONTEXT: OnceLock<KatexContext> = OnceLock::new();
@@ -62,17 +64,190 @@ pub const SUPPORTED_MODULE_TYPES: [SectionModuleType; 3] = [
 // supported mime types relevant to actual module content and not embedded content like images
 pub const SUPPORTED_RESOURCE_TYPES: [&str; 1] = ["application/pdf"];
 
+// resources are only supported when their (first) mime type is one we can display
+pub(crate) fn is_supported_module(module: &SectionModule) -> bool {
+	if module.module_type == SectionModuleType::Resource {
+		let mime_types: Vec<String> =
+			serde_json::from_value(module.mime_types.clone().unwrap_or_default()).unwrap_or_default();
+		return SUPPORTED_RESOURCE_TYPES
+			.contains(&mime_types.first().unwrap_or(&"".to_string()).as_str());
+	}
+
+	SUPPORTED_MODULE_TYPES.contains(&module.module_type)
+}
+
+// the commands and background syncing share these, so they also share sync ids (and with
+// that throttling and in-flight syncs). each account syncs under its own sync ids
+fn user_courses_sync_task<T: Send + 'static>(app: AppHandle, account_id: i32) -> SyncTask<T> {
+	SyncTask::new(app, account_id, "get_user_courses".to_string()).on_update(ContentUpdatedEvent {
+		course_id: None,
+		module_id: None,
+	})
+}
+
+pub(crate) fn course_sync_id(course_id: i32) -> String {
+	format!("get_course_{}", course_id)
+}
+
+fn course_sync_task<T: Send + 'static>(
+	app: AppHandle,
+	account_id: i32,
+	course_id: i32,
+) -> SyncTask<T> {
+	SyncTask::new(app, account_id, course_sync_id(course_id)).on_update(ContentUpdatedEvent {
+		course_id: Some(course_id),
+		module_id: None,
+	})
+}
+
+pub(crate) fn module_content_sync_id(module_id: i32) -> String {
+	format!("get_module_content_{}", module_id)
+}
+
+fn module_content_sync_task<T: Send + 'static>(
+	app: AppHandle,
+	account_id: i32,
+	course_id: i32,
+	module_id: i32,
+) -> SyncTask<T> {
+	SyncTask::new(app, account_id, module_content_sync_id(module_id)).on_update(ContentUpdatedEvent {
+		course_id: Some(course_id),
+		module_id: Some(module_id),
+	})
+}
+
+pub(crate) async fn revalidate_user_courses(
+	app: AppHandle,
+	account_id: i32,
+) -> Result<bool, SyncError> {
+	user_courses_sync_task::<()>(app, account_id)
+		.sync(|ctx| Box::pin(sync_user_courses(ctx.app_handle, ctx.account_id)))
+		.await
+}
+
+pub(crate) async fn revalidate_course(
+	app: AppHandle,
+	account_id: i32,
+	course_id: i32,
+) -> Result<bool, SyncError> {
+	course_sync_task::<()>(app, account_id, course_id)
+		.sync(move |ctx| Box::pin(sync_course(ctx.app_handle, ctx.account_id, course_id)))
+		.await
+}
+
+/// asks moodle which of the given modules changed since each last synced successfully, so only
+/// those need their content fetched again. modules that have never synced are always included
+pub(crate) async fn get_changed_modules(
+	app_handle: &AppHandle,
+	account_id: i32,
+	course_id: i32,
+	module_ids: &[i32],
+) -> Result<HashSet<i32>, SyncError> {
+	let mut changed = HashSet::new();
+	let mut to_check = Vec::new();
+	for &module_id in module_ids {
+		match sync_task::last_success_at(app_handle, account_id, &module_content_sync_id(module_id))
+			.await
+		{
+			Some(since) => to_check.push((module_id, since)),
+			None => {
+				changed.insert(module_id);
+			}
+		}
+	}
+
+	if to_check.is_empty() {
+		return Ok(changed);
+	}
+
+	let credentials = account::credentials(app_handle, account_id).await?;
+	let client = reqwest::Client::new();
+	let request = rest::check_updates_request(
+		&client,
+		&credentials.host,
+		&credentials.token,
+		course_id,
+		&to_check,
+	)
+	.map_err(SyncError::request)?;
+
+	let response = client
+		.execute(request)
+		.await
+		.with_context(|| "Failed to execute request for course updates")?;
+	if response.status().is_success().not() {
+		return Err(SyncError::network(format!(
+			"Failed to check updates for course with id: {course_id}"
+		)));
+	}
+
+	let body = response
+		.text()
+		.await
+		.with_context(|| "Failed to read response body")?;
+	if body.contains("errorcode") {
+		let error_body: rest::RestErrorBody =
+			serde_json::from_str(&body).with_context(|| "Failed to parse error body")?;
+
+		return Err(SyncError::moodle(error_body.error_code, error_body.message));
+	}
+
+	let updates: rest::RestCourseUpdates =
+		serde_json::from_str(&body).with_context(|| "Failed to parse course updates")?;
+	for instance in updates.instances {
+		if instance.context_level != "module"
+			|| instance
+				.updates
+				.iter()
+				.any(|update| rest::is_content_update(&update.name))
+				.not()
+		{
+			continue;
+		}
+
+		log::debug!(
+			"Module {} has updates: {}",
+			instance.id,
+			instance
+				.updates
+				.iter()
+				.map(|update| update.name.as_str())
+				.collect::<Vec<_>>()
+				.join(", ")
+		);
+		changed.insert(instance.id);
+	}
+
+	// moodle warns about modules it couldn't check (i.e. hidden or removed), we sync those
+	// anyway so whatever went wrong surfaces as a sync error for the module
+	for warning in updates.warnings.unwrap_or_default() {
+		if let Some(module_id) = warning.item_id
+			&& module_ids.contains(&module_id)
+		{
+			log::debug!(
+				"Could not check updates for module {} ({}): {}",
+				module_id,
+				warning.warning_code,
+				warning.message
+			);
+			changed.insert(module_id);
+		}
+	}
+
+	Ok(changed)
+}
+
 // waiting for rust equivalent of convertFileSrc()
 // https://github.com/tauri-apps/tauri/issues/12022
-fn asset_uri(file_path: &str) -> String {
+fn blob_uri(file_path: &str) -> String {
 	let encoded = urlencoding::encode(file_path);
 	#[cfg(any(target_os = "windows", target_os = "android"))]
 	{
-		format!("http://asset.localhost/{}", encoded)
+		format!("http://{}.localhost/{}", blob_store::PROTOCOL, encoded)
 	}
 	#[cfg(not(any(target_os = "windows", target_os = "android")))]
 	{
-		format!("asset://localhost/{}", encoded)
+		format!("{}://localhost/{}", blob_store::PROTOCOL, encoded)
 	}
 }
 
@@ -143,12 +318,15 @@ fn render_katex_in_html(html: &str) -> anyhow::Result<String> {
 #[tauri::command]
 #[specta::specta]
 pub async fn get_course(app: AppHandle, course_id: i32) -> Result<CourseWithSections, String> {
-	SyncTask::new(app, format!("get_course_{}", course_id))
+	let account_id = account::require_active(&app)?;
+	course_sync_task(app, account_id, course_id)
 		.return_state(move |state| {
 			let db = state.0.clone();
 			Box::pin(async move {
-				let (course, sections) = entity::Course::find_by_id(course_id)
+				let (course, sections) = entity::Course::find_by_id((account_id, course_id))
 					.find_with_related(entity::CourseSection)
+					.order_by_asc(entity::course_section::Column::Rank)
+					.order_by_asc(entity::course_section::Column::Id)
 					.all(&db)
 					.await?
 					.into_iter()
@@ -160,25 +338,18 @@ pub async fn get_course(app: AppHandle, course_id: i32) -> Result<CourseWithSect
 					let modules = entity::SectionModule::find()
 						.filter(
 							Condition::all()
+								.add(entity::section_module::Column::AccountId.eq(account_id))
 								.add(entity::section_module::Column::SectionId.eq(section.id))
 								.add(entity::section_module::Column::ModuleType.is_in(SUPPORTED_MODULE_TYPES)),
 						)
+						.order_by_asc(entity::section_module::Column::Rank)
+						.order_by_asc(entity::section_module::Column::Id)
 						.all(&db)
 						.await?;
 
 					let supported_modules = modules
 						.into_iter()
-						.filter(|module| {
-							if module.module_type == SectionModuleType::Resource {
-								let mime_types: Vec<String> =
-									serde_json::from_value(module.mime_types.clone().unwrap_or_default())
-										.unwrap_or_default();
-								return SUPPORTED_RESOURCE_TYPES
-									.contains(&mime_types.first().unwrap_or(&"".to_string()).as_str());
-							}
-
-							true
-						})
+						.filter(is_supported_module)
 						.collect::<Vec<_>>();
 
 					if supported_modules.is_empty().not() {
@@ -195,141 +366,219 @@ pub async fn get_course(app: AppHandle, course_id: i32) -> Result<CourseWithSect
 				})
 			})
 		})
-		.sync_state(move |app_handle| {
-			Box::pin(async move {
-				let store = app_handle.store("store.json").unwrap();
-				let host = store.get(auth_keys::MOODLE_HOST).unwrap();
-				let client = reqwest::Client::new();
-				let ws_token = store.get(auth_keys::WS_TOKEN).unwrap();
-				let request = rest::get_course_sections_request(
-					&client,
-					host.as_str().unwrap(),
-					ws_token.as_str().unwrap(),
-					course_id,
-				)
-				.map_err(|e| anyhow!("Failed to create request: {}", e))?;
+		.sync_state(move |ctx| Box::pin(sync_course(ctx.app_handle, ctx.account_id, course_id)))
+		.await
+		.map_err(|e| e.to_string())
+}
 
-				let response = client
-					.execute(request)
-					.await
-					.map_err(|e| anyhow!("Failed to execute request for course sections: {}", e))?;
-				if response.status().is_success().not() {
-					return Err(SyncError::from(anyhow!(
-						"Failed to fetch course sections with id: {course_id}"
-					)));
-				}
+pub(crate) async fn sync_course(
+	app_handle: AppHandle,
+	account_id: i32,
+	course_id: i32,
+) -> Result<bool, SyncError> {
+	let credentials = account::credentials(&app_handle, account_id).await?;
+	let client = reqwest::Client::new();
+	let request =
+		rest::get_course_sections_request(&client, &credentials.host, &credentials.token, course_id)
+			.map_err(SyncError::request)?;
+
+	let response = client
+		.execute(request)
+		.await
+		.with_context(|| "Failed to execute request for course sections")?;
+	if response.status().is_success().not() {
+		return Err(SyncError::network(format!(
+			"Failed to fetch course sections with id: {course_id}"
+		)));
+	}
 
-				let body = response
-					.text()
-					.await
-					.map_err(|e| anyhow!("Failed to read response body: {}", e))?;
-				if body.contains("errorcode") {
-					let error_body: rest::RestErrorBody =
-						serde_json::from_str(&body).with_context(|| "Failed to parse error body")?;
-
-					return Err(SyncError {
-						code: Some(error_body.error_code),
-						module_id: None,
-						message: error_body.message,
-					});
+	let body = response
+		.text()
+		.await
+		.with_context(|| "Failed to read response body")?;
+	if body.contains("errorcode") {
+		let error_body: rest::RestErrorBody =
+			serde_json::from_str(&body).with_context(|| "Failed to parse error body")?;
+
+		return Err(SyncError::moodle(error_body.error_code, error_body.message));
+	}
+
+	let sections_data: Vec<RestCourseSection> =
+		serde_json::from_str(&body).with_context(|| "Failed to parse sections data")?;
+	let state = app_handle.state::<DatabaseState>();
+	let db = &state.0;
+
+	// compare what we have stored against the response so we only notify the ui when
+	// sections or modules were actually added, renamed or moved
+	let (stored_sections, stored_modules): (HashSet<_>, HashSet<_>) = entity::CourseSection::find()
+		.filter(entity::course_section::Column::AccountId.eq(account_id))
+		.filter(entity::course_section::Column::CourseId.eq(course_id))
+		.find_with_related(entity::SectionModule)
+		.all(db)
+		.await
+		.with_context(|| "Failed to query stored course sections")?
+		.into_iter()
+		.fold(
+			(HashSet::new(), HashSet::new()),
+			|(mut sections, mut modules), (section, section_modules)| {
+				for module in section_modules {
+					modules.insert((module.id, module.section_id, module.name, module.rank));
 				}
+				sections.insert((section.id, section.name, section.rank));
+				(sections, modules)
+			},
+		);
+
+	let mut synced_sections = HashSet::new();
+	let mut synced_modules = HashSet::new();
+	let txn = db
+		.begin()
+		.await
+		.with_context(|| "Failed to begin transaction for course sections")?;
+
+	entity::Course::update_many()
+		// module_count helps us keep track of the number of modules in this course
+		// so we can be more transparent about any that are omitted when we wanna display them
+		.col_expr(
+			entity::course::Column::ModuleCount,
+			Expr::value(
+				sections_data
+					.iter()
+					.map(|section| section.modules.len() as i32)
+					.sum::<i32>(),
+			),
+		)
+		.filter(entity::course::Column::AccountId.eq(account_id))
+		.filter(entity::course::Column::Id.eq(course_id))
+		.exec(&txn)
+		.await
+		.with_context(|| "Failed to update course module count")?;
+
+	for (section_rank, section) in sections_data.into_iter().enumerate() {
+		let section_name = html_escape::decode_html_entities(&section.name).to_string();
+		let section_rank = section_rank as i32;
+		synced_sections.insert((section.id, section_name.clone(), section_rank));
+		let section_entity = entity::course_section::ActiveModel {
+			account_id: ActiveValue::Set(account_id),
+			id: ActiveValue::Set(section.id),
+			name: ActiveValue::Set(section_name),
+			course_id: ActiveValue::Set(course_id),
+			rank: ActiveValue::Set(section_rank),
+		};
 
-				let sections_data: Vec<RestCourseSection> =
-					serde_json::from_str(&body).with_context(|| "Failed to parse sections data")?;
-				let state = app_handle.state::<DatabaseState>();
-				let db = &state.0;
-				let txn = db
-					.begin()
-					.await
-					.map_err(|e| anyhow!("Failed to begin transaction for course sections: {}", e))?;
-
-				entity::Course::update_many()
-					// module_count helps us keep track of the number of modules in this course
-					// so we can be more transparent about any that are omitted when we wanna display them
-					.col_expr(
-						entity::course::Column::ModuleCount,
-						Expr::value(
-							sections_data
-								.iter()
-								.map(|section| section.modules.len() as i32)
-								.sum::<i32>(),
-						),
-					)
-					.filter(entity::course::Column::Id.eq(course_id))
-					.exec(&txn)
-					.await
-					.map_err(|e| anyhow!("Failed to update course module count: {}", e))?;
-
-				for section in sections_data {
-					let section_entity = entity::course_section::ActiveModel {
-						id: ActiveValue::Set(section.id),
-						name: ActiveValue::Set(html_escape::decode_html_entities(&section.name).to_string()),
-						course_id: ActiveValue::Set(course_id),
-					};
-
-					entity::CourseSection::insert(section_entity)
-						.on_conflict(
-							sea_query::OnConflict::column(entity::course_section::Column::Id)
-								.update_columns([
-									entity::course_section::Column::Name,
-									entity::course_section::Column::CourseId,
-								])
-								.to_owned(),
-						)
-						.exec(&txn)
-						.await
-						.map_err(|e| anyhow!("Failed to insert course section {}: {}", section.id, e))?;
+		entity::CourseSection::insert(section_entity)
+			.on_conflict(
+				sea_query::OnConflict::columns([
+					entity::course_section::Column::AccountId,
+					entity::course_section::Column::Id,
+				])
+				.update_columns([
+					entity::course_section::Column::Name,
+					entity::course_section::Column::CourseId,
+					entity::course_section::Column::Rank,
+				])
+				.to_owned(),
+			)
+			.exec(&txn)
+			.await
+			.with_context(|| format!("Failed to insert course section {}", section.id))?;
+
+		for (module_rank, module) in section.modules.into_iter().enumerate() {
+			if !SUPPORTED_MODULE_TYPES.contains(&module.module_type) {
+				continue;
+			}
 
-					for module in section.modules {
-						if !SUPPORTED_MODULE_TYPES.contains(&module.module_type) {
-							continue;
-						}
+			let module_name = html_escape::decode_html_entities(&module.name).to_string();
+			let module_rank = module_rank as i32;
+			synced_modules.insert((module.id, section.id, module_name.clone(), module_rank));
+			let section_item = entity::section_module::ActiveModel {
+				account_id: ActiveValue::Set(account_id),
+				id: ActiveValue::Set(module.id),
+				name: ActiveValue::Set(module_name),
+				section_id: ActiveValue::Set(section.id),
+				module_type: ActiveValue::Set(module.module_type),
+				mime_types: match module.contents_info {
+					Some(contents_info) => ActiveValue::Set(Some(
+						serde_json::to_value(contents_info.mime_types).with_context(|| {
+							format!("Failed to serialize mime types for module {}", module.id)
+						})?,
+					)),
+					None => ActiveValue::NotSet,
+				},
+				updated_at: ActiveValue::Set(Utc::now().timestamp()),
+				pinned: ActiveValue::NotSet,
+				last_opened_at: ActiveValue::NotSet,
+				rank: ActiveValue::Set(module_rank),
+			};
+
+			entity::SectionModule::insert(section_item)
+				.on_conflict(
+					sea_query::OnConflict::columns([
+						entity::section_module::Column::AccountId,
+						entity::section_module::Column::Id,
+					])
+					.update_columns([
+						entity::section_module::Column::Name,
+						entity::section_module::Column::SectionId,
+						entity::section_module::Column::UpdatedAt,
+						entity::section_module::Column::Rank,
+					])
+					.to_owned(),
+				)
+				.exec(&txn)
+				.await
+				.with_context(|| format!("Failed to insert section module {}", module.id))?;
+		}
+	}
 
-						let section_item = entity::section_module::ActiveModel {
-							id: ActiveValue::Set(module.id),
-							name: ActiveValue::Set(html_escape::decode_html_entities(&module.name).to_string()),
-							section_id: ActiveValue::Set(section.id),
-							module_type: ActiveValue::Set(module.module_type),
-							mime_types: match module.contents_info {
-								Some(contents_info) => ActiveValue::Set(Some(
-									serde_json::to_value(contents_info.mime_types).map_err(|e| {
-										anyhow!(
-											"Failed to serialize mime types for module {}: {}",
-											module.id,
-											e
-										)
-									})?,
-								)),
-								None => ActiveValue::NotSet,
-							},
-							updated_at: ActiveValue::Set(Utc::now().timestamp()),
-						};
-
-						entity::SectionModule::insert(section_item)
-							.on_conflict(
-								sea_query::OnConflict::column(entity::section_module::Column::Id)
-									.update_columns([
-										entity::section_module::Column::Name,
-										entity::section_module::Column::SectionId,
-										entity::section_module::Column::UpdatedAt,
-									])
-									.to_owned(),
-							)
-							.exec(&txn)
-							.await
-							.map_err(|e| anyhow!("Failed to insert section module {}: {}", module.id, e))?;
-					}
-				}
+	// anything moodle no longer lists for this course has been removed (or hidden from us)
+	let synced_section_ids = synced_sections
+		.iter()
+		.map(|(id, _, _)| *id)
+		.collect::<HashSet<_>>();
+	let synced_module_ids = synced_modules
+		.iter()
+		.map(|(id, _, _, _)| *id)
+		.collect::<HashSet<_>>();
+	let mut pruner = Pruner::new(account_id);
+	pruner
+		.delete_modules(
+			&txn,
+			stored_modules
+				.iter()
+				.map(|(id, _, _, _)| *id)
+				.filter(|id| synced_module_ids.contains(id).not())
+				.collect(),
+		)
+		.await
+		.with_context(|| "Failed to delete stale section modules")?;
+	pruner
+		.delete_sections(
+			&txn,
+			stored_sections
+				.iter()
+				.map(|(id, _, _)| *id)
+				.filter(|id| synced_section_ids.contains(id).not())
+				.collect(),
+		)
+		.await
+		.with_context(|| "Failed to delete stale course sections")?;
 
-				txn
-					.commit()
-					.await
-					.map_err(|e| anyhow!("Failed to commit transaction for course sections: {}", e))?;
-				Ok(())
-			})
-		})
+	txn
+		.commit()
 		.await
-		.map_err(|e| e.to_string())
+		.with_context(|| "Failed to commit transaction for course sections")?;
+
+	let pruned = pruner.finish(db).await;
+	if pruned.is_empty().not() {
+		log::info!(
+			"Removed stale content of course {}: {:?}",
+			course_id,
+			pruned
+		);
+	}
+
+	Ok(synced_sections != stored_sections || synced_modules != stored_modules)
 }
 
 #[tauri::command]
@@ -339,12 +588,15 @@ pub async fn get_module_content(
 	course_id: i32,
 	module_id: i32,
 ) -> Result<(SectionModule, Vec<ModuleContent>), String> {
-	SyncTask::new(app, format!("get_module_content_{}", module_id))
+	let account_id = account::require_active(&app)?;
+	storage::mark_opened(&app, account_id, module_id).await;
+	module_content_sync_task(app, account_id, course_id, module_id)
 		.return_state(move |state| {
 			let db = state.0.clone();
 			Box::pin(async move {
-				let module_with_content = entity::SectionModule::find_by_id(module_id)
+				let module_with_content = entity::SectionModule::find_by_id((account_id, module_id))
 					.find_with_related(entity::ModuleContent)
+					.order_by_asc(entity::module_content::Column::Rank)
 					.all(&db)
 					.await
 					.map_err(|error| error.to_string())?;
@@ -354,17 +606,21 @@ pub async fn get_module_content(
 				}
 
 				let blobs = entity::ContentBlob::find()
-					.filter(Condition::all().add(entity::content_blob::Column::ModuleId.eq(module_id)))
+					.filter(
+						Condition::all()
+							.add(entity::content_blob::Column::AccountId.eq(account_id))
+							.add(entity::content_blob::Column::ModuleId.eq(module_id)),
+					)
 					.all(&db)
 					.await
-					.map_err(|e| anyhow!("Failed to query existing content blob: {}", e))?;
+					.with_context(|| "Failed to query existing content blob")?;
 
 				// get content blocks -> rewrite each block (rewriter.write) -> pass rewritten blocks back(?)
 				// maintain id/content fields
 
 				let blob_map = blobs
 					.iter()
-					.map(|b| (b.name.clone(), b.path.clone()))
+					.map(|b| ((b.file_path.clone(), b.name.clone()), b.path.clone()))
 					.collect::<HashMap<_, _>>();
 
 				let (module, contents) = module_with_content
@@ -384,13 +640,21 @@ pub async fn get_module_content(
 					tokio::task::spawn_blocking(move || -> anyhow::Result<Vec<ModuleContent>> {
 						let mut rewritten = Vec::with_capacity(contents.len());
 						for mut block in contents {
+							// files are looked up in the block's own folder first, then the module's root folder
+							let folder = format!("/{}/", block.id);
+							let find_blob = |file_name: &str| {
+								blob_map
+									.get(&(folder.clone(), file_name.to_string()))
+									.or_else(|| blob_map.get(&("/".to_string(), file_name.to_string())))
+							};
+
 							let out = Arc::new(Mutex::new(Vec::new()));
 							let out_sink = Arc::clone(&out);
 							let mut rewriter = HtmlRewriter::new(
 								Settings {
 									element_content_handlers: vec![
 										// 1. set "src" attributes to appropriate local path, when pointing to local assets
-										// "image.png" gets translated to asset://localhost/path/to/image.png
+										// "image.png" gets translated to content-blob://localhost/path/to/image.png
 										element!("img[src]", |el| {
 											if let Some(src) = el.get_attribute("src") {
 												if src.contains("://") || src.starts_with("data:") {
@@ -401,9 +665,9 @@ pub async fn get_module_content(
 													.unwrap_or_else(|_| std::borrow::Cow::Owned(src.to_string()));
 												let file_name = decoded.split("?").next().unwrap_or("");
 
-												if let Some(local_path) = blob_map.get(file_name) {
-													let asset_src = asset_uri(local_path);
-													el.set_attribute("src", &asset_src)?;
+												if let Some(local_path) = find_blob(file_name) {
+													let blob_src = blob_uri(local_path);
+													el.set_attribute("src", &blob_src)?;
 												}
 											}
 
@@ -458,492 +722,906 @@ pub async fn get_module_content(
 				Ok((module, rewritten_contents))
 			})
 		})
-		.sync_state(move |app_handle| {
-			Box::pin(async move {
-				let store = app_handle.store("store.json").unwrap();
-				let client = reqwest::Client::new();
-				let token = store.get(auth_keys::WS_TOKEN).unwrap();
-				let token = token.as_str().unwrap();
-				let request = rest::get_sections_with_model_content(
-					&client,
-					store.get(auth_keys::MOODLE_HOST).unwrap().as_str().unwrap(),
-					token,
-					course_id,
-					module_id,
-				)
-				.map_err(|e| anyhow!("Failed to create request: {}", e))?;
+		.sync_state(move |ctx| Box::pin(sync_module_content(ctx, course_id, module_id)))
+		.await
+		.map_err(|e| e.to_string())
+}
 
-				let response = client
-					.execute(request)
-					.await
-					.map_err(|e| anyhow!("Failed to execute request for module content: {}", e))?;
-				if response.status().is_success().not() {
-					return Err(SyncError::from(anyhow!(
-						"Failed to fetch module content with id: {module_id}"
-					)));
-				}
+pub(crate) async fn sync_module_content(
+	ctx: SyncContext,
+	course_id: i32,
+	module_id: i32,
+) -> Result<bool, SyncError> {
+	let app_handle = &ctx.app_handle;
 
-				let body = response
-					.text()
-					.await
-					.with_context(|| "Failed to read response body")?;
-				if body.contains("errorcode") {
-					let error_body: rest::RestErrorBody =
-						serde_json::from_str(&body).with_context(|| "Failed to parse error body")?;
-
-					println!(
-						"course id {course_id}, module id {module_id}, error: {:?}",
-						error_body
-					);
-					return Err(SyncError {
-						code: Some(error_body.error_code),
-						module_id: Some(module_id),
-						message: error_body.message,
-					});
-				}
+	// fetching module content means fetching the contents of the whole course, so we check
+	// with moodle first and keep what's stored when nothing changed
+	if get_changed_modules(app_handle, ctx.account_id, course_id, &[module_id])
+		.await
+		.map_err(|e| e.for_module(module_id))?
+		.contains(&module_id)
+		.not()
+	{
+		return Ok(false);
+	}
 
-				let sections_data: Vec<RestCourseSection> = serde_json::from_str(&body)
-					.map_err(|e| anyhow!("Failed to parse sections data: {}", e))?;
-				let module = sections_data
-					.into_iter()
-					.flat_map(|section| section.modules)
-					.find(|module| module.id == module_id)
-					.with_context(|| format!("Module with id {} not found", module_id))?;
-
-				if SUPPORTED_MODULE_TYPES.contains(&module.module_type).not() {
-					return Err(SyncError::from(anyhow!(
-						"Module type {} is not supported",
-						module.module_type
-					)));
-				}
+	let module = fetch_module(app_handle, ctx.account_id, course_id, module_id).await?;
+	store_module_content(&ctx, module_id, &module).await
+}
 
-				let module_contents = module.contents.unwrap_or_default();
-				let state = app_handle.state::<DatabaseState>();
-				let db = &state.0;
-				let txn = db
-					.begin()
-					.await
-					.map_err(|e| anyhow!("Failed to begin transaction for module content: {}", e))?;
-
-				for (i, content) in module_contents.iter().enumerate() {
-					// ids of the content blocks stored in file path as "/id/"
-					// media content also uses this to refer to the relevant content block.
-					// the "root" content block seems to always have a path of "/", which is usually
-					// included if it doesn't contain any other content
-
-					// "books" have an additional structure content object that contains the hierarchy of the contents,
-					// not sure how i wanna handle books, but storing content blocks as they appear in the response is fine for now
-					let content_id = if content.file_path == "/" && content.file_name != "structure" {
-						1
-					} else {
-						if module.module_type == SectionModuleType::Book && content.file_name == "structure" {
-							0
-						} else {
-							content.file_path[1..content.file_path.len() - 1]
-								.parse::<i32>()
-								.map_err(|e| {
-									anyhow!(
-										"Failed to parse content id from file path {}: {}",
-										content.file_path,
-										e
-									)
-								})?
-						}
-					};
-
-					// written content is usually in an index.html file. we generally wanna store text content directly, blobs being
-					// stored on the filesystem, with paths stored in the database.
-					if content.file_name == "index.html" {
-						let existing_content = entity::ModuleContent::find()
-							.filter(
-								Condition::all()
-									.add(entity::module_content::Column::ModuleId.eq(module_id))
-									.add(entity::module_content::Column::Id.eq(content_id)),
-							)
-							.one(&txn)
-							.await
-							.map_err(|e| {
-								anyhow!(
-									"Failed to query existing module content {}: {}",
-									content.file_name,
-									e
-								)
-							})?;
-
-						if let Some(module_content) = existing_content
-							&& content.time_modified as i64 > module_content.updated_at
-						{
-							continue;
-						}
+async fn fetch_module(
+	app_handle: &AppHandle,
+	account_id: i32,
+	course_id: i32,
+	module_id: i32,
+) -> Result<RestCourseSectionModule, SyncError> {
+	let credentials = account::credentials(app_handle, account_id).await?;
+	let client = reqwest::Client::new();
+	let request = rest::get_sections_with_model_content(
+		&client,
+		&credentials.host,
+		&credentials.token,
+		course_id,
+		module_id,
+	)
+	.map_err(SyncError::request)?;
 
-						let file_url = content.file_url.as_ref().with_context(|| {
-							format!("Content with id {} does not have a file URL", content_id)
-						})?;
-						let file_url = format!("{}?forcedownload=1&token={}", file_url, token);
-						let content_response = reqwest::get(file_url).await.map_err(|e| {
-							anyhow!(
-								"Failed to fetch content for content id {}: {}",
-								content_id,
-								e
-							)
-						})?;
-						if content_response.status().is_success().not() {
-							return Err(SyncError::from(anyhow!(
-								"Failed to fetch content for content id: {}",
-								content_id
-							)));
-						}
+	let response = client
+		.execute(request)
+		.await
+		.with_context(|| "Failed to execute request for module content")?;
+	if response.status().is_success().not() {
+		return Err(
+			SyncError::network(format!(
+				"Failed to fetch module content with id: {module_id}"
+			))
+			.for_module(module_id),
+		);
+	}
 
-						// todo: remove any scripts and stylesheets that are not needed
-						// html content is usually also pretty ugly with empty tags, etc.
-						let content_text = content_response.text().await.map_err(|e| {
-							anyhow!(
-								"Failed to read content response for content id {}: {}",
-								content_id,
-								e
-							)
-						})?;
-						let module_content = entity::module_content::ActiveModel {
-							id: ActiveValue::Set(content_id),
-							module_id: ActiveValue::Set(module_id),
-							content: ActiveValue::Set(content_text),
-							rank: ActiveValue::Set(i as i32),
-							updated_at: ActiveValue::Set(Utc::now().timestamp()),
-						};
-
-						entity::ModuleContent::insert(module_content)
-							.on_conflict(
-								sea_query::OnConflict::columns([
-									entity::module_content::Column::Id,
-									entity::module_content::Column::ModuleId,
-								])
-								.update_columns([
-									entity::module_content::Column::Content,
-									entity::module_content::Column::Rank,
-									entity::module_content::Column::UpdatedAt,
-								])
-								.to_owned(),
-							)
-							.exec(&txn)
-							.await
-							.map_err(|e| anyhow!("Failed to insert module content: {}", e))?;
-					}
+	let body = response
+		.text()
+		.await
+		.with_context(|| "Failed to read response body")?;
+	if body.contains("errorcode") {
+		let error_body: rest::RestErrorBody =
+			serde_json::from_str(&body).with_context(|| "Failed to parse error body")?;
+
+		println!(
+			"course id {course_id}, module id {module_id}, error: {:?}",
+			error_body
+		);
+		return Err(SyncError::moodle(error_body.error_code, error_body.message).for_module(module_id));
+	}
 
-					if let Some(mime_type) = &content.mime_type {
-						let app_dir = app_handle
-							.path()
-							.app_local_data_dir()
-							.expect("failed to get app data dir");
-						let path = app_dir
-							.join("content_blobs")
-							.join(module_id.to_string())
-							.join(&content.file_name);
-
-						let file_exists = std::fs::exists(&path).map_err(|e| {
-							anyhow!(
-								"Failed to check if content blob file exists {}: {}",
-								path.to_str().unwrap_or_default(),
-								e
-							)
-						})?;
-
-						let existing_blob = entity::ContentBlob::find()
-							.filter(
-								Condition::all()
-									.add(entity::content_blob::Column::ModuleId.eq(module_id))
-									.add(entity::content_blob::Column::Name.eq(&content.file_name)),
-							)
-							.one(&txn)
-							.await
-							.map_err(|e| {
-								anyhow!(
-									"Failed to query existing content blob {}: {}",
-									content.file_name,
-									e
-								)
-							})?;
-
-						// if the file exists on disk and hasn't been updated, we shouldn't need to download it again
-						if file_exists
-							&& let Some(blob) = existing_blob
-							&& content.time_modified as i64 > blob.updated_at
-						{
-							continue;
-						}
+	find_module(&body, module_id)
+}
 
-						let file_url = content.file_url.as_ref().with_context(|| {
-							format!("Content with id {} does not have a file URL", content_id)
-						})?;
-						let file_url = format!("{}?forcedownload=1&token={}", file_url, token);
-						let content_response = reqwest::get(file_url).await.map_err(|e| {
-							anyhow!(
-								"Failed to fetch content blob for content id {}: {}",
-								content_id,
-								e
-							)
-						})?;
-						if content_response.status().is_success().not() {
-							return Err(SyncError::from(anyhow!(
-								"Failed to fetch content blob for content id: {}",
-								content_id
-							)));
-						}
+// module content responses are the course's sections, filtered down to the one module
+fn find_module(body: &str, module_id: i32) -> Result<RestCourseSectionModule, SyncError> {
+	let sections_data: Vec<RestCourseSection> =
+		serde_json::from_str(body).with_context(|| "Failed to parse sections data")?;
+	let module = sections_data
+		.into_iter()
+		.flat_map(|section| section.modules)
+		.find(|module| module.id == module_id)
+		.with_context(|| format!("Module with id {} not found", module_id))?;
+
+	Ok(module)
+}
 
-						let blob = content_response.bytes().await.map_err(|e| {
-							anyhow!(
-								"Failed to read content blob response for content id {}: {}",
-								content_id,
-								e
-							)
-						})?;
-						std::fs::create_dir_all(app_dir.join("content_blobs").join(module_id.to_string()))
-							.map_err(|e| {
-								anyhow!(
-									"Failed to create content blob directory {}: {}",
-									path.to_str().unwrap_or_default(),
-									e
-								)
-							})?;
-						std::fs::write(&path, blob).map_err(|e| {
-							anyhow!(
-								"Failed to write content blob to file {}: {}",
-								path.to_str().unwrap_or_default(),
-								e
-							)
-						})?;
-
-						let content_blob = entity::content_blob::ActiveModel {
-							name: ActiveValue::Set(content.file_name.clone()),
-							module_id: ActiveValue::Set(module_id),
-							// updated_at: ActiveValue::Set(Utc::now().timestamp()),
-							updated_at: ActiveValue::Set(
-								content
-									.time_modified
-									.try_into()
-									.unwrap_or(Utc::now().timestamp()),
-							),
-							mime_type: ActiveValue::Set(mime_type.to_string()),
-							path: ActiveValue::Set(path.to_str().unwrap().to_string()),
-						};
-
-						entity::ContentBlob::insert(content_blob)
-							.on_conflict(
-								sea_query::OnConflict::columns([
-									entity::content_blob::Column::Name,
-									entity::content_blob::Column::ModuleId,
-								])
-								.update_columns([
-									entity::content_blob::Column::ModuleId,
-									entity::content_blob::Column::UpdatedAt,
-									entity::content_blob::Column::MimeType,
-									entity::content_blob::Column::Path,
-								])
-								.to_owned(),
-							)
-							.exec(&txn)
-							.await
-							.map_err(|e| anyhow!("Failed to insert content blob: {}", e))?;
-
-						// modules with the resource type usually (from what i've seen) only consist of a single content blob (pdf)
-						// and so we set the module content to the content blob path
-						if module.module_type == SectionModuleType::Resource
-							&& SUPPORTED_RESOURCE_TYPES.contains(&mime_type.as_str())
-						{
-							let module_content = entity::module_content::ActiveModel {
-								id: ActiveValue::Set(content_id),
-								module_id: ActiveValue::Set(module_id),
-								content: ActiveValue::Set(content.file_name.to_string()),
-								rank: ActiveValue::Set(i as i32),
-								updated_at: ActiveValue::Set(Utc::now().timestamp()),
-							};
+/// fetches the content of many modules with a single batched request. modules whose call
+/// failed are left out, so they can fall back to being fetched on their own
+async fn fetch_modules(
+	app_handle: &AppHandle,
+	account_id: i32,
+	course_id: i32,
+	module_ids: &[i32],
+) -> Result<HashMap<i32, RestCourseSectionModule>, SyncError> {
+	let credentials = account::credentials(app_handle, account_id).await?;
+	let client = reqwest::Client::new();
+	let calls = module_ids
+		.iter()
+		.map(|&module_id| rest::RestCall::module_contents(course_id, module_id))
+		.collect::<Vec<_>>();
+	let request = rest::batch_request(&client, &credentials.host, &credentials.token, &calls)
+		.map_err(SyncError::request)?;
+
+	let response = client
+		.execute(request)
+		.await
+		.with_context(|| "Failed to execute batched request for module content")?;
+	if response.status().is_success().not() {
+		return Err(SyncError::network(format!(
+			"Failed to fetch module content for course with id: {course_id}"
+		)));
+	}
 
-							entity::ModuleContent::insert(module_content)
-								.on_conflict(
-									sea_query::OnConflict::columns([
-										entity::module_content::Column::Id,
-										entity::module_content::Column::ModuleId,
-									])
-									.update_columns([
-										entity::module_content::Column::Content,
-										entity::module_content::Column::Rank,
-										entity::module_content::Column::UpdatedAt,
-									])
-									.to_owned(),
-								)
-								.exec(&txn)
-								.await
-								.map_err(|e| anyhow!("Failed to insert module content: {}", e))?;
-						}
-					}
+	let body = response
+		.text()
+		.await
+		.with_context(|| "Failed to read response body")?;
+	if body.contains("\"responses\"").not() && body.contains("errorcode") {
+		let error_body: rest::RestErrorBody =
+			serde_json::from_str(&body).with_context(|| "Failed to parse error body")?;
+
+		return Err(SyncError::moodle(error_body.error_code, error_body.message));
+	}
+
+	let batch: rest::RestBatchResponse =
+		serde_json::from_str(&body).with_context(|| "Failed to parse batched response")?;
+	let mut modules = HashMap::new();
+	for (&module_id, item) in module_ids.iter().zip(batch.responses) {
+		match item.into_result() {
+			Ok(data) => match find_module(&data, module_id) {
+				Ok(module) => {
+					modules.insert(module_id, module);
 				}
+				Err(e) => log::debug!("Batched content for module {}: {}", module_id, e.message),
+			},
+			Err(error_body) => log::debug!(
+				"Batched call for module {} failed ({}): {}",
+				module_id,
+				error_body.error_code,
+				error_body.message
+			),
+		}
+	}
 
-				// match module.module_type {
-				// 	RestCourseSectionModuleType::Book => {
-				// 		let structure_content = module_contents.iter().find(|content| {
-				// 			content.content_type == RestCourseSectionModuleContentType::Content
-				// 				&& content.file_name == "structure"
-				// 		});
-
-				// 		if let Some(structure_content) = structure_content {
-				// 			let structure: Vec<rest::RestCourseSectionModuleStructureItem> =
-				// 				serde_json::from_str(&structure_content.content.as_ref().unwrap())
-				// 					?;
-
-				// 			for item in structure
-				// 				.iter()
-				// 				.flat_map(|item| item.sub_items.iter().flatten())
-				// 			{
-
-				// 			}
-				// 		}
-				// 	}
-				// }
-
-				txn
-					.commit()
-					.await
-					.map_err(|e| anyhow!("Failed to commit transaction: {}", e))?;
-				Ok(())
+	Ok(modules)
+}
+
+/// syncs the content of many modules in a course, fetching them in batches rather than a
+/// request per module. each module still syncs under its own sync task
+pub(crate) async fn revalidate_modules_content(
+	app: AppHandle,
+	account_id: i32,
+	course_id: i32,
+	module_ids: &[i32],
+) -> Vec<(i32, Result<bool, SyncError>)> {
+	let mut results = Vec::with_capacity(module_ids.len());
+	for chunk in module_ids.chunks(rest::MAX_BATCH_SIZE) {
+		let mut modules = fetch_modules(&app, account_id, course_id, chunk)
+			.await
+			.unwrap_or_else(|e| {
+				log::warn!(
+					"Failed to batch fetch modules for course {}, fetching individually: {}",
+					course_id,
+					e.message
+				);
+				HashMap::new()
+			});
+
+		for &module_id in chunk {
+			let result = sync_prefetched_module(
+				app.clone(),
+				account_id,
+				course_id,
+				module_id,
+				modules.remove(&module_id),
+			)
+			.await;
+			results.push((module_id, result));
+		}
+	}
+
+	results
+}
+
+// syncs a module under its own sync task using content we've already fetched
+async fn sync_prefetched_module(
+	app: AppHandle,
+	account_id: i32,
+	course_id: i32,
+	module_id: i32,
+	module: Option<RestCourseSectionModule>,
+) -> Result<bool, SyncError> {
+	let module = module.map(Arc::new);
+	module_content_sync_task::<()>(app, account_id, course_id, module_id)
+		.sync(move |ctx| {
+			let module = module.clone();
+			Box::pin(async move {
+				match module {
+					Some(module) => store_module_content(&ctx, module_id, &module).await,
+					// modules we couldn't prefetch are fetched on their own
+					None => sync_module_content(ctx, course_id, module_id).await,
+				}
 			})
 		})
 		.await
-		.map_err(|e| e.to_string())
 }
 
+// every module in the course along with its contents, in a single request
+async fn fetch_course_modules(
+	app_handle: &AppHandle,
+	account_id: i32,
+	course_id: i32,
+) -> Result<Vec<RestCourseSectionModule>, SyncError> {
+	let credentials = account::credentials(app_handle, account_id).await?;
+	let client = reqwest::Client::new();
+	let request =
+		rest::get_course_contents_request(&client, &credentials.host, &credentials.token, course_id)
+			.map_err(SyncError::request)?;
+
+	let response = client
+		.execute(request)
+		.await
+		.with_context(|| "Failed to execute request for course contents")?;
+	if response.status().is_success().not() {
+		return Err(SyncError::network(format!(
+			"Failed to fetch course contents with id: {course_id}"
+		)));
+	}
+
+	let body = response
+		.text()
+		.await
+		.with_context(|| "Failed to read response body")?;
+	if body.contains("errorcode") {
+		let error_body: rest::RestErrorBody =
+			serde_json::from_str(&body).with_context(|| "Failed to parse error body")?;
+
+		return Err(SyncError::moodle(error_body.error_code, error_body.message));
+	}
+
+	let sections_data: Vec<RestCourseSection> =
+		serde_json::from_str(&body).with_context(|| "Failed to parse sections data")?;
+	Ok(
+		sections_data
+			.into_iter()
+			.flat_map(|section| section.modules)
+			.collect(),
+	)
+}
+
+// moodle reports the total size of a module's files in contentsinfo, which isn't always
+// included, so fall back to adding up the size of each file
+fn estimated_module_size(module: &RestCourseSectionModule) -> u64 {
+	module
+		.contents_info
+		.as_ref()
+		.and_then(|contents_info| contents_info.files_size)
+		.unwrap_or_else(|| {
+			module
+				.contents
+				.iter()
+				.flatten()
+				.filter_map(|content| content.file_size)
+				.sum()
+		})
+}
+
+#[derive(Serialize, Deserialize, Type, Debug, Clone, Default)]
+pub struct CourseDownloadProgress {
+	pub estimated_bytes: u64,
+	pub modules_done: u32,
+	pub modules_total: u32,
+	pub failed_modules: Vec<i32>,
+}
+
+// how many modules are downloaded at the same time
+const OFFLINE_DOWNLOAD_CONCURRENCY: usize = 4;
+
+/// syncs every supported module in a course, along with all of their content blobs. the
+/// estimated download size is reported before anything is downloaded. the course is pinned,
+/// so what's downloaded isn't evicted again to stay under the storage cap
 #[tauri::command]
 #[specta::specta]
-pub async fn get_content_blobs(
+pub async fn download_course_for_offline(
 	app: AppHandle,
 	course_id: i32,
+	on_progress: Channel<CourseDownloadProgress>,
+) -> Result<CourseDownloadProgress, String> {
+	if connectivity::is_online(&app).not() {
+		return Err("Can't download courses while offline".to_string());
+	}
+
+	let account_id = account::require_active(&app)?;
+	// makes sure we have every module stored before downloading their content
+	revalidate_course(app.clone(), account_id, course_id)
+		.await
+		.map_err(|e| e.message)?;
+
+	let db_state = app.state::<DatabaseState>();
+	let supported_modules = entity::SectionModule::find()
+		.inner_join(entity::CourseSection)
+		.filter(entity::course_section::Column::AccountId.eq(account_id))
+		.filter(entity::course_section::Column::CourseId.eq(course_id))
+		.all(&db_state.0)
+		.await
+		.map_err(|e| e.to_string())?
+		.into_iter()
+		.filter(is_supported_module)
+		.map(|module| module.id)
+		.collect::<HashSet<_>>();
+
+	let modules = fetch_course_modules(&app, account_id, course_id)
+		.await
+		.map_err(|e| e.message)?
+		.into_iter()
+		.filter(|module| supported_modules.contains(&module.id))
+		.collect::<Vec<_>>();
+
+	// blobs evicted to stay under the cap are only downloaded again for modules opened since,
+	// which a download asked for counts as
+	entity::SectionModule::update_many()
+		.col_expr(
+			entity::section_module::Column::LastOpenedAt,
+			Expr::value(Utc::now().timestamp()),
+		)
+		.filter(entity::section_module::Column::AccountId.eq(account_id))
+		.filter(entity::section_module::Column::Id.is_in(modules.iter().map(|module| module.id)))
+		.exec(&db_state.0)
+		.await
+		.map_err(|e| e.to_string())?;
+	entity::Course::update_many()
+		.col_expr(entity::course::Column::Pinned, Expr::value(true))
+		.filter(entity::course::Column::AccountId.eq(account_id))
+		.filter(entity::course::Column::Id.eq(course_id))
+		.exec(&db_state.0)
+		.await
+		.map_err(|e| e.to_string())?;
+
+	let mut progress = CourseDownloadProgress {
+		estimated_bytes: modules.iter().map(estimated_module_size).sum(),
+		modules_total: modules.len() as u32,
+		..Default::default()
+	};
+	on_progress.send(progress.clone()).ok();
+
+	let mut downloads = futures::stream::iter(modules.into_iter().map(|module| {
+		let app = app.clone();
+		async move {
+			let module_id = module.id;
+			let result =
+				sync_prefetched_module(app, account_id, course_id, module_id, Some(module)).await;
+			(module_id, result)
+		}
+	}))
+	.buffer_unordered(OFFLINE_DOWNLOAD_CONCURRENCY);
+
+	while let Some((module_id, result)) = downloads.next().await {
+		progress.modules_done += 1;
+		if let Err(e) = result {
+			log::warn!(
+				"Failed to download module {} for offline use: {}",
+				module_id,
+				e.message
+			);
+			progress.failed_modules.push(module_id);
+		}
+
+		on_progress.send(progress.clone()).ok();
+	}
+
+	Ok(progress)
+}
+
+async fn store_module_content(
+	ctx: &SyncContext,
 	module_id: i32,
-) -> Result<Vec<ContentBlob>, String> {
-	SyncTask::new(
-		app,
-		format!("get_content_blobs_{}_{}", course_id, module_id),
-	)
-	.return_state(move |state| {
-		let db = state.0.clone();
-		Box::pin(async move {
-			let blobs = entity::ContentBlob::find()
-				.filter(Condition::all().add(entity::content_blob::Column::ModuleId.eq(module_id)))
-				.all(&db)
-				.await?;
-
-			if blobs.is_empty() {
-				return Err(format!("No content blobs found for module id: {}", module_id).into());
+	module: &RestCourseSectionModule,
+) -> Result<bool, SyncError> {
+	let app_handle = &ctx.app_handle;
+	if SUPPORTED_MODULE_TYPES.contains(&module.module_type).not() {
+		return Err(
+			SyncError::unsupported(format!(
+				"Module type {} is not supported",
+				module.module_type
+			))
+			.for_module(module_id),
+		);
+	}
+
+	let account_id = ctx.account_id;
+	let credentials = account::credentials(app_handle, account_id).await?;
+	let token = credentials.token.as_str();
+	let module_contents = module.contents.as_deref().unwrap_or_default();
+	let client = reqwest::Client::new();
+	let state = app_handle.state::<DatabaseState>();
+	let db = &state.0;
+	let mut changed = false;
+	// files that blobs used to point at, removed after commit if nothing else does
+	let mut released_paths = Vec::new();
+	let mut seen_blobs = HashSet::new();
+	let mut progress = SyncProgress {
+		items_total: module_contents.len() as u32,
+		..Default::default()
+	};
+	let last_opened_at = entity::SectionModule::find_by_id((account_id, module_id))
+		.one(db)
+		.await
+		.with_context(|| "Failed to query stored module")?
+		.and_then(|module| module.last_opened_at);
+	// everything is fetched before any of it is written, so the write transaction below
+	// doesn't hold the database's only writer lock across downloads
+	let mut contents = Vec::new();
+	let mut versions = Vec::new();
+	let mut blobs = Vec::new();
+
+	for (i, content) in module_contents.iter().enumerate() {
+		// nothing is stored until every download is done, so cancelling leaves rows untouched
+		ctx.check_cancelled()?;
+		seen_blobs.insert((content.file_path.clone(), content.file_name.clone()));
+		progress.items_done = i as u32;
+		progress.current_file = Some(content.file_name.clone());
+		ctx.report(&progress).await;
+
+		// ids of the content blocks stored in file path as "/id/"
+		// media content also uses this to refer to the relevant content block.
+		// the "root" content block seems to always have a path of "/", which is usually
+		// included if it doesn't contain any other content
+
+		// "books" have an additional structure content object that contains the hierarchy of the contents,
+		// not sure how i wanna handle books, but storing content blocks as they appear in the response is fine for now
+		let content_id = if content.file_path == "/" && content.file_name != "structure" {
+			1
+		} else {
+			if module.module_type == SectionModuleType::Book && content.file_name == "structure" {
+				0
+			} else {
+				content.file_path[1..content.file_path.len() - 1]
+					.parse::<i32>()
+					.with_context(|| {
+						format!(
+							"Failed to parse content id from file path {}",
+							content.file_path
+						)
+					})?
+			}
+		};
+
+		// written content is usually in an index.html file. we generally wanna store text content directly, blobs being
+		// stored on the filesystem, with paths stored in the database.
+		if content.file_name == "index.html" {
+			let existing_content = entity::ModuleContent::find()
+				.filter(
+					Condition::all()
+						.add(entity::module_content::Column::AccountId.eq(account_id))
+						.add(entity::module_content::Column::ModuleId.eq(module_id))
+						.add(entity::module_content::Column::Id.eq(content_id)),
+				)
+				.one(db)
+				.await
+				.with_context(|| {
+					format!(
+						"Failed to query existing module content {}",
+						content.file_name
+					)
+				})?;
+
+			if let Some(module_content) = &existing_content
+				&& content.time_modified as i64 <= module_content.updated_at
+			{
+				continue;
+			}
+
+			let file_url = content
+				.file_url
+				.as_ref()
+				.with_context(|| format!("Content with id {} does not have a file URL", content_id))?;
+			let file_url = format!("{}?forcedownload=1&token={}", file_url, token);
+			let content_response = reqwest::get(file_url)
+				.await
+				.with_context(|| format!("Failed to fetch content for content id {}", content_id))?;
+			if content_response.status().is_success().not() {
+				return Err(
+					SyncError::network(format!(
+						"Failed to fetch content for content id: {}",
+						content_id
+					))
+					.for_module(module_id),
+				);
+			}
+
+			// todo: remove any scripts and stylesheets that are not needed
+			// html content is usually also pretty ugly with empty tags, etc.
+			let content_text = content_response.text().await.with_context(|| {
+				format!(
+					"Failed to read content response for content id {}",
+					content_id
+				)
+			})?;
+			progress.bytes_downloaded += content_text.len() as u64;
+			// the content is overwritten below, so anything new is kept as a version first
+			if existing_content.is_none_or(|existing| existing.content != content_text) {
+				changed = true;
+				versions.push(entity::module_content_version::ActiveModel {
+					id: ActiveValue::NotSet,
+					account_id: ActiveValue::Set(account_id),
+					module_id: ActiveValue::Set(module_id),
+					content_id: ActiveValue::Set(content_id),
+					content: ActiveValue::Set(content_text.clone()),
+					created_at: ActiveValue::Set(Utc::now().timestamp()),
+				});
 			}
 
-			Ok(blobs)
+			contents.push(entity::module_content::ActiveModel {
+				account_id: ActiveValue::Set(account_id),
+				id: ActiveValue::Set(content_id),
+				module_id: ActiveValue::Set(module_id),
+				content: ActiveValue::Set(content_text),
+				rank: ActiveValue::Set(i as i32),
+				updated_at: ActiveValue::Set(Utc::now().timestamp()),
+			});
+		}
+
+		if let Some(mime_type) = &content.mime_type {
+			let existing_blob = entity::ContentBlob::find()
+				.filter(
+					Condition::all()
+						.add(entity::content_blob::Column::AccountId.eq(account_id))
+						.add(entity::content_blob::Column::ModuleId.eq(module_id))
+						.add(entity::content_blob::Column::FilePath.eq(&content.file_path))
+						.add(entity::content_blob::Column::Name.eq(&content.file_name)),
+				)
+				.one(db)
+				.await
+				.with_context(|| {
+					format!(
+						"Failed to query existing content blob {}",
+						content.file_name
+					)
+				})?;
+
+			// if the file exists on disk and hasn't been updated, we shouldn't need to download it again.
+			// blobs without a hash were missing when moved to the shared store, so are always downloaded
+			if let Some(blob) = &existing_blob
+				&& blob.hash.is_some()
+				&& tokio::fs::try_exists(&blob.path).await.unwrap_or(false)
+				&& content.time_modified as i64 <= blob.updated_at
+			{
+				continue;
+			}
+
+			// evicted to stay under the storage cap, it's only wanted again once the module
+			// has been opened since
+			if let Some(blob) = &existing_blob
+				&& let Some(evicted_at) = blob.evicted_at
+				&& last_opened_at.is_none_or(|opened_at| opened_at < evicted_at)
+			{
+				continue;
+			}
+
+			let file_url = content
+				.file_url
+				.as_ref()
+				.with_context(|| format!("Content with id {} does not have a file URL", content_id))?;
+			let file_url = format!("{}?forcedownload=1&token={}", file_url, token);
+			let blob = blob_store::download_blob(
+				ctx,
+				&mut progress,
+				&client,
+				&file_url,
+				&content.file_name,
+				content.time_modified,
+			)
+			.await
+			.map_err(|e| e.for_module(module_id))?;
+			let hash = blob.hash;
+			let path = blob.path.to_str().unwrap().to_string();
+			if let Some(existing_blob) = &existing_blob
+				&& existing_blob.path != path
+			{
+				released_paths.push(existing_blob.path.clone());
+			}
+
+			blobs.push(entity::content_blob::ActiveModel {
+				account_id: ActiveValue::Set(account_id),
+				name: ActiveValue::Set(content.file_name.clone()),
+				module_id: ActiveValue::Set(module_id),
+				file_path: ActiveValue::Set(content.file_path.clone()),
+				// updated_at: ActiveValue::Set(Utc::now().timestamp()),
+				updated_at: ActiveValue::Set(
+					content
+						.time_modified
+						.try_into()
+						.unwrap_or(Utc::now().timestamp()),
+				),
+				mime_type: ActiveValue::Set(mime_type.to_string()),
+				path: ActiveValue::Set(path),
+				hash: ActiveValue::Set(Some(hash.clone())),
+				evicted_at: ActiveValue::Set(None),
+				size: ActiveValue::Set(Some(blob.size as i64)),
+			});
+			// a blob downloaded again after eviction is back on disk, so counts as a change too
+			changed |= existing_blob
+				.is_none_or(|existing| existing.hash != Some(hash) || existing.evicted_at.is_some());
+
+			// modules with the resource type usually (from what i've seen) only consist of a single content blob (pdf)
+			// and so we set the module content to the content blob path
+			if module.module_type == SectionModuleType::Resource
+				&& SUPPORTED_RESOURCE_TYPES.contains(&mime_type.as_str())
+			{
+				contents.push(entity::module_content::ActiveModel {
+					account_id: ActiveValue::Set(account_id),
+					id: ActiveValue::Set(content_id),
+					module_id: ActiveValue::Set(module_id),
+					content: ActiveValue::Set(content.file_name.to_string()),
+					rank: ActiveValue::Set(i as i32),
+					updated_at: ActiveValue::Set(Utc::now().timestamp()),
+				});
+			}
+		}
+	}
+
+	let txn = db
+		.begin()
+		.await
+		.with_context(|| "Failed to begin transaction for module content")?;
+	for version in versions {
+		entity::ModuleContentVersion::insert(version)
+			.exec(&txn)
+			.await
+			.with_context(|| "Failed to insert module content version")?;
+	}
+
+	for module_content in contents {
+		entity::ModuleContent::insert(module_content)
+			.on_conflict(
+				sea_query::OnConflict::columns([
+					entity::module_content::Column::AccountId,
+					entity::module_content::Column::Id,
+					entity::module_content::Column::ModuleId,
+				])
+				.update_columns([
+					entity::module_content::Column::Content,
+					entity::module_content::Column::Rank,
+					entity::module_content::Column::UpdatedAt,
+				])
+				.to_owned(),
+			)
+			.exec(&txn)
+			.await
+			.with_context(|| "Failed to insert module content")?;
+	}
+
+	for content_blob in blobs {
+		entity::ContentBlob::insert(content_blob)
+			.on_conflict(
+				sea_query::OnConflict::columns([
+					entity::content_blob::Column::AccountId,
+					entity::content_blob::Column::Name,
+					entity::content_blob::Column::ModuleId,
+					entity::content_blob::Column::FilePath,
+				])
+				.update_columns([
+					entity::content_blob::Column::UpdatedAt,
+					entity::content_blob::Column::MimeType,
+					entity::content_blob::Column::Path,
+					entity::content_blob::Column::Hash,
+					entity::content_blob::Column::EvictedAt,
+					entity::content_blob::Column::Size,
+				])
+				.to_owned(),
+			)
+			.exec(&txn)
+			.await
+			.with_context(|| "Failed to insert content blob")?;
+	}
+
+	// match module.module_type {
+	// 	RestCourseSectionModuleType::Book => {
+	// 		let structure_content = module_contents.iter().find(|content| {
+	// 			content.content_type == RestCourseSectionModuleContentType::Content
+	// 				&& content.file_name == "structure"
+	// 		});
+
+	// 		if let Some(structure_content) = structure_content {
+	// 			let structure: Vec<rest::RestCourseSectionModuleStructureItem> =
+	// 				serde_json::from_str(&structure_content.content.as_ref().unwrap())
+	// 					?;
+
+	// 			for item in structure
+	// 				.iter()
+	// 				.flat_map(|item| item.sub_items.iter().flatten())
+	// 			{
+
+	// 			}
+	// 		}
+	// 	}
+	// }
+
+	// blobs moodle no longer lists for this module, including any stored before we kept
+	// track of which folder they're in
+	let stale_blobs = entity::ContentBlob::find()
+		.filter(entity::content_blob::Column::AccountId.eq(account_id))
+		.filter(entity::content_blob::Column::ModuleId.eq(module_id))
+		.all(&txn)
+		.await
+		.with_context(|| "Failed to query stored content blobs")?
+		.into_iter()
+		.filter(|blob| {
+			seen_blobs
+				.contains(&(blob.file_path.clone(), blob.name.clone()))
+				.not()
 		})
-	})
-	.sync_state(|_| Box::pin(async { Ok(()) }))
-	.await
-	.map_err(|e| e.to_string())
+		.collect::<Vec<_>>();
+	for blob in stale_blobs {
+		entity::ContentBlob::delete_by_id((blob.account_id, blob.name, blob.module_id, blob.file_path))
+			.exec(&txn)
+			.await
+			.with_context(|| "Failed to delete stale content blob")?;
+		released_paths.push(blob.path);
+		changed = true;
+	}
+
+	txn
+		.commit()
+		.await
+		.with_context(|| "Failed to commit transaction")?;
+	blob_store::release_unreferenced(db, released_paths).await;
+
+	progress.items_done = progress.items_total;
+	progress.current_file = None;
+	ctx.report(&progress).await;
+	Ok(changed)
+}
+
+/// only reads what's stored, the module's own sync is what downloads its blobs
+#[tauri::command]
+#[specta::specta]
+pub async fn get_content_blobs(app: AppHandle, module_id: i32) -> Result<Vec<ContentBlob>, String> {
+	let account_id = account::require_active(&app)?;
+	let db_state = app.state::<DatabaseState>();
+	let blobs = entity::ContentBlob::find()
+		.filter(
+			Condition::all()
+				.add(entity::content_blob::Column::AccountId.eq(account_id))
+				.add(entity::content_blob::Column::ModuleId.eq(module_id)),
+		)
+		.all(&db_state.0)
+		.await
+		.map_err(|e| e.to_string())?;
+
+	if blobs.is_empty() {
+		return Err(format!(
+			"No content blobs found for module id: {}",
+			module_id
+		));
+	}
+
+	Ok(blobs)
 }
 
 #[tauri::command]
 #[specta::specta]
 pub async fn get_user_courses(app: AppHandle) -> Result<Vec<Course>, String> {
-	SyncTask::new(app, "get_user_courses".to_string())
+	let account_id = account::require_active(&app)?;
+	user_courses_sync_task(app, account_id)
 		.return_state(move |state| {
 			let db = state.0.clone();
 			Box::pin(async move {
-				let courses = entity::Course::find().all(&db).await?;
+				let courses = entity::Course::find()
+					.filter(entity::course::Column::AccountId.eq(account_id))
+					.all(&db)
+					.await?;
 				Ok(courses)
 			})
 		})
-		.sync_state(|app_handle| {
-			Box::pin(async move {
-				{
-					let store = app_handle.store("store.json").unwrap();
-					let client = reqwest::Client::new();
-					let user_id = store
-						.get(auth_keys::USER_ID)
-						.and_then(|id| id.as_str().and_then(|s| s.parse::<u32>().ok()))
-						.with_context(|| "Failed to retrieve user id from store")
-						.map_err(|e| SyncError::from(anyhow!("Failed to get user id: {}", e)))?;
-
-					let host = store.get(auth_keys::MOODLE_HOST).unwrap();
-					let ws_token = store.get(auth_keys::WS_TOKEN).unwrap();
-					let request = rest::get_user_courses_request(
-						&client,
-						ws_token.as_str().unwrap(),
-						host.as_str().unwrap(),
-						user_id,
-					)
-					.map_err(|e| SyncError::from(anyhow!("Failed to create request: {}", e)))?;
-
-					let response = client
-						.execute(request)
-						.await
-						.map_err(|e| SyncError::from(anyhow!("Failed to execute request: {}", e)))?;
-					if response.status().is_success().not() {
-						return Err(SyncError::from(anyhow!(
-							"Could not get user courses: {}",
-							response.text().await.unwrap_or_default()
-						)));
-					}
+		.sync_state(|ctx| Box::pin(sync_user_courses(ctx.app_handle, ctx.account_id)))
+		.await
+		.map_err(|e| e.to_string())
+}
 
-					let body = response
-						.text()
-						.await
-						.with_context(|| "Failed to read response body")?;
-					if body.contains("errorcode") {
-						let error_body: rest::RestErrorBody =
-							serde_json::from_str(&body).with_context(|| "Failed to parse error body")?;
-
-						return Err(SyncError {
-							code: Some(error_body.error_code),
-							module_id: None,
-							message: error_body.message,
-						});
-					}
+pub(crate) async fn sync_user_courses(
+	app_handle: AppHandle,
+	account_id: i32,
+) -> Result<bool, SyncError> {
+	let credentials = account::credentials(&app_handle, account_id).await?;
+	let client = reqwest::Client::new();
+	let request = rest::get_user_courses_request(
+		&client,
+		&credentials.token,
+		&credentials.host,
+		credentials.user_id as u32,
+	)
+	.map_err(SyncError::request)?;
 
-					let course_data = serde_json::from_str::<Vec<RestCourse>>(&body)
-						.with_context(|| "Failed to parse course data")?;
-					let courses = course_data
-						.into_iter()
-						.map(|course| entity::course::ActiveModel {
-							id: ActiveValue::Set(course.id),
-							name: ActiveValue::Set(course.full_name),
-							colour: ActiveValue::Set(Some("brown".to_string())),
-							module_count: ActiveValue::Set(0),
-							icon: ActiveValue::Set(None),
-						})
-						.collect::<Vec<_>>();
+	let response = client
+		.execute(request)
+		.await
+		.with_context(|| "Failed to execute request")?;
+	if response.status().is_success().not() {
+		return Err(SyncError::network(format!(
+			"Could not get user courses: {}",
+			response.text().await.unwrap_or_default()
+		)));
+	}
 
-					let state = app_handle.state::<DatabaseState>();
-					let db = &state.0;
-					let txn = db
-						.begin()
-						.await
-						.map_err(|e| SyncError::from(anyhow!("Failed to begin transaction: {}", e)))?;
-
-					for course in courses {
-						entity::Course::insert(course)
-							.on_conflict(
-								sea_query::OnConflict::column(entity::course::Column::Id)
-									.update_columns([
-										entity::course::Column::Name,
-										entity::course::Column::Colour,
-										entity::course::Column::Icon,
-										// don't update module count here
-									])
-									.to_owned(),
-							)
-							.exec(&txn)
-							.await
-							.map_err(|e| SyncError::from(anyhow!("Failed to insert course: {}", e)))?;
-					}
+	let body = response
+		.text()
+		.await
+		.with_context(|| "Failed to read response body")?;
+	if body.contains("errorcode") {
+		let error_body: rest::RestErrorBody =
+			serde_json::from_str(&body).with_context(|| "Failed to parse error body")?;
 
-					txn
-						.commit()
-						.await
-						.map_err(|e| SyncError::from(anyhow!("Failed to commit transaction: {}", e)))?;
-					Ok(())
-				}
-			})
+		return Err(SyncError::moodle(error_body.error_code, error_body.message));
+	}
+
+	let course_data = serde_json::from_str::<Vec<RestCourse>>(&body)
+		.with_context(|| "Failed to parse course data")?;
+	let synced_courses = course_data
+		.iter()
+		.map(|course| (course.id, course.full_name.clone()))
+		.collect::<HashSet<_>>();
+	let courses = course_data
+		.into_iter()
+		.map(|course| entity::course::ActiveModel {
+			account_id: ActiveValue::Set(account_id),
+			id: ActiveValue::Set(course.id),
+			name: ActiveValue::Set(course.full_name),
+			colour: ActiveValue::Set(Some("brown".to_string())),
+			module_count: ActiveValue::Set(0),
+			icon: ActiveValue::Set(None),
+			pinned: ActiveValue::NotSet,
 		})
+		.collect::<Vec<_>>();
+
+	let state = app_handle.state::<DatabaseState>();
+	let db = &state.0;
+	let stored_courses = entity::Course::find()
+		.filter(entity::course::Column::AccountId.eq(account_id))
+		.all(db)
 		.await
-		.map_err(|e| e.to_string())
+		.with_context(|| "Failed to query stored courses")?
+		.into_iter()
+		.map(|course| (course.id, course.name))
+		.collect::<HashSet<_>>();
+
+	let txn = db
+		.begin()
+		.await
+		.with_context(|| "Failed to begin transaction")?;
+
+	for course in courses {
+		entity::Course::insert(course)
+			.on_conflict(
+				sea_query::OnConflict::columns([
+					entity::course::Column::AccountId,
+					entity::course::Column::Id,
+				])
+				.update_columns([
+					entity::course::Column::Name,
+					entity::course::Column::Colour,
+					entity::course::Column::Icon,
+					// don't update module count here
+				])
+				.to_owned(),
+			)
+			.exec(&txn)
+			.await
+			.with_context(|| "Failed to insert course")?;
+	}
+
+	// courses we're no longer enrolled in, along with everything we stored for them
+	let synced_course_ids = synced_courses
+		.iter()
+		.map(|(id, _)| *id)
+		.collect::<HashSet<_>>();
+	let mut pruner = Pruner::new(account_id);
+	pruner
+		.delete_courses(
+			&txn,
+			stored_courses
+				.iter()
+				.map(|(id, _)| *id)
+				.filter(|id| synced_course_ids.contains(id).not())
+				.collect(),
+		)
+		.await
+		.with_context(|| "Failed to delete stale courses")?;
+
+	txn
+		.commit()
+		.await
+		.with_context(|| "Failed to commit transaction")?;
+
+	let pruned = pruner.finish(db).await;
+	if pruned.is_empty().not() {
+		log::info!("Removed stale courses: {:?}", pruned);
+	}
+
+	Ok(synced_courses != stored_courses)
 }
@@ -1,3 +1,5 @@
+use std::ops::Not;
+
 use ::serde::Deserialize;
 use entity::section_module::SectionModuleType;
 use tauri::http::{HeaderMap, HeaderValue};
@@ -7,10 +9,29 @@ pub mod rest_functions {
 	pub const GET_USER_COURSES: &str = "core_enrol_get_users_courses";
 	pub const GET_COURSE_CONTENT: &str = "core_course_get_contents";
 	pub const GET_USERS_BY_FIELD: &str = "core_user_get_users_by_field";
+	pub const CHECK_UPDATES: &str = "core_course_check_updates";
+	pub const CALL_EXTERNAL_FUNCTIONS: &str = "tool_mobile_call_external_functions";
+	pub const GET_SITE_INFO: &str = "core_webservice_get_site_info";
+	pub const GET_TOKENS_FOR_QR_LOGIN: &str = "tool_mobile_get_tokens_for_qr_login";
+}
+
+// the web service tokens are issued for, the same one the mobile app uses
+pub const MOBILE_SERVICE: &str = "moodle_mobile_app";
+
+// calls bundled into a single batched request, anything more is split across requests
+pub const MAX_BATCH_SIZE: usize = 20;
+
+// updates moodle reports that affect stored module content, anything else (completion,
+// grades, etc.) doesn't warrant a re-sync. file areas are reported by their own names, i.e.
+// "introfiles" or "contentfiles"
+pub fn is_content_update(name: &str) -> bool {
+	name == "configuration" || name == "entries" || name.ends_with("files")
 }
 
 #[derive(Debug, Deserialize)]
 pub struct RestErrorBody {
+	// not included in errors of batched calls
+	#[serde(default)]
 	pub exception: String,
 	#[serde(rename = "errorcode")]
 	pub error_code: String,
@@ -64,12 +85,16 @@ pub struct RestCourseSectionModuleContent {
 	pub content_type: RestCourseSectionModuleContentType,
 	#[serde(rename = "content")]
 	pub content: Option<String>,
+	#[serde(rename = "filesize")]
+	pub file_size: Option<u64>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct RestCourseSectionModuleContentInfo {
 	#[serde(rename = "filescount")]
 	pub files_count: u32,
+	#[serde(rename = "filessize")]
+	pub files_size: Option<u64>,
 	#[serde(rename = "mimetypes")]
 	pub mime_types: Option<Vec<String>>,
 }
@@ -89,6 +114,122 @@ pub struct RestCourse {
 	pub full_name: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct RestCourseUpdates {
+	pub instances: Vec<RestUpdatedInstance>,
+	pub warnings: Option<Vec<RestWarning>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RestUpdatedInstance {
+	#[serde(rename = "contextlevel")]
+	pub context_level: String,
+	pub id: i32,
+	pub updates: Vec<RestInstanceUpdate>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RestInstanceUpdate {
+	pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RestWarning {
+	#[serde(rename = "itemid")]
+	pub item_id: Option<i32>,
+	#[serde(rename = "warningcode")]
+	pub warning_code: String,
+	pub message: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RestBatchResponse {
+	pub responses: Vec<RestBatchItem>,
+}
+
+// data and exception are json encoded strings of what the call would've returned on its own
+#[derive(Debug, Deserialize)]
+pub struct RestBatchItem {
+	pub error: bool,
+	pub data: Option<String>,
+	pub exception: Option<String>,
+}
+
+impl RestBatchItem {
+	pub fn into_result(self) -> Result<String, RestErrorBody> {
+		if self.error.not() {
+			return Ok(self.data.unwrap_or_default());
+		}
+
+		Err(
+			self
+				.exception
+				.and_then(|exception| serde_json::from_str(&exception).ok())
+				.unwrap_or_else(|| RestErrorBody {
+					exception: String::new(),
+					error_code: "unknown".to_string(),
+					message: "Batched call failed without an exception".to_string(),
+				}),
+		)
+	}
+}
+
+/// a single web service call, which can be bundled with others through [`batch_request`]
+pub struct RestCall {
+	pub function: &'static str,
+	pub arguments: serde_json::Value,
+}
+
+impl RestCall {
+	/// same as [`get_sections_with_model_content`]
+	pub fn module_contents(course_id: i32, module_id: i32) -> Self {
+		Self {
+			function: rest_functions::GET_COURSE_CONTENT,
+			arguments: serde_json::json!({
+				"courseid": course_id,
+				"options": [
+					{ "name": "includestealthmodules", "value": "1" },
+					{ "name": "cmid", "value": module_id.to_string() },
+				],
+			}),
+		}
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RestSiteInfo {
+	#[serde(rename = "userid")]
+	pub user_id: u32,
+	#[serde(rename = "fullname")]
+	pub full_name: String,
+}
+
+// login/token.php answers with either a token or an error, neither in the shape the web
+// service uses
+#[derive(Debug, Deserialize)]
+pub struct RestTokenResponse {
+	pub token: Option<String>,
+	pub error: Option<String>,
+	#[serde(rename = "errorcode")]
+	pub error_code: Option<String>,
+}
+
+// a response to a call made through lib/ajax/service.php, which is answered in a list with
+// one of these per call
+#[derive(Debug, Deserialize)]
+pub struct RestAjaxResponse {
+	pub error: bool,
+	#[serde(default)]
+	pub data: Option<serde_json::Value>,
+	#[serde(default)]
+	pub exception: Option<RestErrorBody>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RestQrLoginTokens {
+	pub token: String,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct RestUser {
 	pub id: u32,
@@ -96,13 +237,7 @@ pub struct RestUser {
 	pub full_name: String,
 }
 
-fn build_rest_request(
-	client: &reqwest::Client,
-	host: &str,
-	ws_token: &str,
-	form: &mut std::collections::HashMap<String, String>,
-) -> Result<reqwest::Request, Box<dyn std::error::Error>> {
-	let endpoint = format!("{host}/webservice/rest/server.php");
+fn mobile_headers() -> HeaderMap {
 	let mut headers = HeaderMap::new();
 	headers.insert("Accept", HeaderValue::from_static("application/json"));
 	headers.insert("User-Agent", HeaderValue::from_static("Mozilla/5.0 (iPhone; CPU iPhone OS 19_0 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Mobile/15E148 MoodleMobile 5.0.0 (50003)"));
@@ -110,12 +245,90 @@ fn build_rest_request(
 		"Origin",
 		HeaderValue::from_static("moodleappfs://localhost"),
 	);
+	headers
+}
 
+fn build_rest_request(
+	client: &reqwest::Client,
+	host: &str,
+	ws_token: &str,
+	form: &mut std::collections::HashMap<String, String>,
+) -> Result<reqwest::Request, Box<dyn std::error::Error>> {
+	let endpoint = format!("{host}/webservice/rest/server.php");
 	form.insert("moodlewsrestformat".to_string(), "json".to_string());
 	form.insert("moodlewssettinglang".to_string(), "en".to_string());
 	form.insert("wstoken".to_string(), ws_token.to_string());
 
-	Ok(client.post(endpoint).headers(headers).form(&form).build()?)
+	Ok(
+		client
+			.post(endpoint)
+			.headers(mobile_headers())
+			.form(&form)
+			.build()?,
+	)
+}
+
+/// asks the site for a token directly with the user's credentials, which only works on sites
+/// that allow the mobile app to sign in without the browser
+pub fn get_token_request(
+	client: &reqwest::Client,
+	host: &str,
+	username: &str,
+	password: &str,
+) -> Result<reqwest::Request, Box<dyn std::error::Error>> {
+	let form = [
+		("username", username),
+		("password", password),
+		("service", MOBILE_SERVICE),
+	];
+
+	Ok(
+		client
+			.post(format!("{host}/login/token.php"))
+			.headers(mobile_headers())
+			.form(&form)
+			.build()?,
+	)
+}
+
+/// exchanges the key from a qr code shown on the user's profile for a token. moodle only
+/// accepts this from the mobile app, and usually only from the same network the code was
+/// shown on
+pub fn get_tokens_for_qr_login_request(
+	client: &reqwest::Client,
+	host: &str,
+	qr_login_key: &str,
+	user_id: u32,
+) -> Result<reqwest::Request, Box<dyn std::error::Error>> {
+	let body = serde_json::json!([{
+		"index": 0,
+		"methodname": rest_functions::GET_TOKENS_FOR_QR_LOGIN,
+		"args": { "qrloginkey": qr_login_key, "userid": user_id },
+	}]);
+
+	Ok(
+		client
+			.post(format!("{host}/lib/ajax/service.php"))
+			.query(&[("info", rest_functions::GET_TOKENS_FOR_QR_LOGIN)])
+			.headers(mobile_headers())
+			.header("Content-Type", "application/json")
+			.body(body.to_string())
+			.build()?,
+	)
+}
+
+pub fn get_site_info_request(
+	client: &reqwest::Client,
+	host: &str,
+	ws_token: &str,
+) -> Result<reqwest::Request, Box<dyn std::error::Error>> {
+	let form = &mut std::collections::HashMap::new();
+	form.insert(
+		"wsfunction".to_string(),
+		rest_functions::GET_SITE_INFO.to_string(),
+	);
+
+	build_rest_request(client, host, ws_token, form)
 }
 
 pub fn get_user_courses_request(
@@ -155,6 +368,23 @@ pub fn get_course_sections_request(
 	build_rest_request(&client, host, ws_token, form)
 }
 
+/// same as [`get_course_sections_request`], but with the contents of every module included
+pub fn get_course_contents_request(
+	client: &reqwest::Client,
+	host: &str,
+	ws_token: &str,
+	course_id: i32,
+) -> Result<reqwest::Request, Box<dyn std::error::Error>> {
+	let form = &mut std::collections::HashMap::new();
+	form.insert(
+		"wsfunction".to_string(),
+		rest_functions::GET_COURSE_CONTENT.to_string(),
+	);
+	form.insert("courseid".to_string(), course_id.to_string());
+
+	build_rest_request(&client, host, ws_token, form)
+}
+
 /// this endpoint returns data similar to the course content endpoint (used for sections and modules),
 /// but it also includes only the specified module's content
 pub fn get_sections_with_model_content(
@@ -199,3 +429,57 @@ pub fn get_users_by_id(
 
 	build_rest_request(client, host, ws_token, form)
 }
+
+/// checks which of the given modules have been updated since their paired timestamp. every
+/// area is checked, the filter only takes area keys ("fileareas", etc.) rather than the names
+/// updates are reported with, so [`is_content_update`] picks out the ones that matter
+pub fn check_updates_request(
+	client: &reqwest::Client,
+	host: &str,
+	ws_token: &str,
+	course_id: i32,
+	modules: &[(i32, i64)],
+) -> Result<reqwest::Request, Box<dyn std::error::Error>> {
+	let form = &mut std::collections::HashMap::new();
+	form.insert(
+		"wsfunction".to_string(),
+		rest_functions::CHECK_UPDATES.to_string(),
+	);
+	form.insert("courseid".to_string(), course_id.to_string());
+	for (i, (module_id, since)) in modules.iter().enumerate() {
+		form.insert(format!("tocheck[{i}][contextlevel]"), "module".to_string());
+		form.insert(format!("tocheck[{i}][id]"), module_id.to_string());
+		form.insert(format!("tocheck[{i}][since]"), since.to_string());
+	}
+	build_rest_request(client, host, ws_token, form)
+}
+
+/// bundles many web service calls into one request, the responses are returned in the same
+/// order as the calls
+pub fn batch_request(
+	client: &reqwest::Client,
+	host: &str,
+	ws_token: &str,
+	calls: &[RestCall],
+) -> Result<reqwest::Request, Box<dyn std::error::Error>> {
+	let form = &mut std::collections::HashMap::new();
+	form.insert(
+		"wsfunction".to_string(),
+		rest_functions::CALL_EXTERNAL_FUNCTIONS.to_string(),
+	);
+	for (i, call) in calls.iter().enumerate() {
+		form.insert(
+			format!("requests[{i}][function]"),
+			call.function.to_string(),
+		);
+		form.insert(
+			format!("requests[{i}][arguments]"),
+			call.arguments.to_string(),
+		);
+		// keep text and file urls as they'd be returned by the call on its own
+		form.insert(format!("requests[{i}][settingfilter]"), "0".to_string());
+		form.insert(format!("requests[{i}][settingfileurl]"), "1".to_string());
+	}
+
+	build_rest_request(client, host, ws_token, form)
+}
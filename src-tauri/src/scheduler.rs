@@ -0,0 +1,278 @@
+use std::{
+	ops::Not,
+	sync::atomic::{AtomicBool, Ordering},
+	time::Duration,
+};
+
+use rand::Rng;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QuerySelect};
+use tauri::{AppHandle, Manager};
+use tauri_plugin_store::StoreExt;
+use tokio::sync::Notify;
+
+use crate::{
+	account, connectivity,
+	database::DatabaseState,
+	request::course::{
+		get_changed_modules, is_supported_module, revalidate_course, revalidate_modules_content,
+		revalidate_user_courses,
+	},
+	request::rest::MAX_BATCH_SIZE,
+	storage,
+	sync_task::{SyncError, SyncErrorKind},
+};
+
+pub mod scheduler_keys {
+	pub const INTERVAL: &str = "sync_interval";
+	pub const JITTER: &str = "sync_jitter";
+}
+
+// 30 minutes
+const DEFAULT_INTERVAL: u64 = 60 * 30;
+// 5 minutes
+const DEFAULT_JITTER: u64 = 60 * 5;
+// 6 hours, failed rounds back off exponentially up to this
+const MAX_BACKOFF: u64 = 60 * 60 * 6;
+// the first round runs shortly after startup rather than a whole interval later
+const STARTUP_DELAY: u64 = 10;
+
+// the scheduler periodically walks each account (courses -> sections -> supported modules)
+// through the same sync tasks the commands use, so anything it syncs is throttled and
+// deduplicated against what the ui is requesting at the same time
+#[derive(Default)]
+pub struct SchedulerState {
+	pub paused: AtomicBool,
+	// wakes the scheduler early, i.e. when resumed or the interval changes
+	pub wake: Notify,
+}
+
+struct SchedulerConfig {
+	interval: u64,
+	jitter: u64,
+}
+
+impl SchedulerConfig {
+	fn load(app: &AppHandle) -> Self {
+		let store = app.store("store.json").ok();
+		let get = |key: &str| {
+			store
+				.as_ref()
+				.and_then(|store| store.get(key))
+				.and_then(|value| value.as_u64())
+		};
+
+		Self {
+			interval: get(scheduler_keys::INTERVAL).unwrap_or(DEFAULT_INTERVAL),
+			jitter: get(scheduler_keys::JITTER).unwrap_or(DEFAULT_JITTER),
+		}
+	}
+
+	fn next_delay(&self, failed_rounds: u32) -> Duration {
+		let backoff = self
+			.interval
+			.saturating_mul(2u64.saturating_pow(failed_rounds))
+			.min(MAX_BACKOFF.max(self.interval));
+		let jitter = rand::rng().random_range(0..=self.jitter);
+		Duration::from_secs(backoff + jitter)
+	}
+}
+
+pub fn start(app: AppHandle) {
+	tauri::async_runtime::spawn(async move {
+		let mut failed_rounds = 0;
+		let mut delay = Duration::from_secs(STARTUP_DELAY);
+		loop {
+			let scheduler = app.state::<SchedulerState>();
+			tokio::select! {
+				_ = tokio::time::sleep(delay) => {}
+				_ = scheduler.wake.notified() => {}
+			}
+
+			let config = SchedulerConfig::load(&app);
+			delay = config.next_delay(failed_rounds);
+			// reconnecting wakes the scheduler, so offline rounds can just be skipped
+			if scheduler.paused.load(Ordering::Relaxed) || connectivity::is_online(&app).not() {
+				continue;
+			}
+
+			// a token moodle has turned down is as good as none, so those accounts are left out
+			let account_ids = match account::syncable_ids(&app).await {
+				Ok(account_ids) => account_ids,
+				Err(e) => {
+					log::error!("Failed to query accounts to sync: {:#}", e);
+					continue;
+				}
+			};
+
+			if account_ids.is_empty() {
+				continue;
+			}
+
+			// one account failing doesn't hold back the others, the round only counts as failed
+			// for backoff once the last of them has been tried
+			let mut last_error = None;
+			for account_id in account_ids {
+				if is_paused(&app) {
+					break;
+				}
+
+				if let Err(e) = sync_account(&app, account_id).await {
+					log::warn!(
+						"Scheduled sync of account {} failed: {}",
+						account_id,
+						e.message
+					);
+					last_error = Some(e);
+				}
+			}
+
+			match last_error {
+				None => failed_rounds = 0,
+				Some(_) => {
+					failed_rounds += 1;
+					delay = config.next_delay(failed_rounds);
+					log::warn!(
+						"Scheduled sync failed ({} in a row), next attempt in {}s",
+						failed_rounds,
+						delay.as_secs()
+					);
+				}
+			}
+
+			// whatever the round downloaded might have put storage over its cap
+			if let Err(e) = storage::enforce_storage_cap(&app).await {
+				log::error!("Failed to enforce storage cap: {}", e);
+			}
+		}
+	});
+}
+
+fn is_paused(app: &AppHandle) -> bool {
+	app.state::<SchedulerState>().paused.load(Ordering::Relaxed)
+}
+
+// no point carrying on with a token moodle won't accept, or without a host to talk to
+fn should_stop(error: &SyncError) -> bool {
+	matches!(error.kind, SyncErrorKind::Auth | SyncErrorKind::Offline)
+}
+
+// walks every course and supported module, carrying on past individual failures so one
+// broken module doesn't hold back the rest. the last error is returned for backoff
+async fn sync_account(app: &AppHandle, account_id: i32) -> Result<(), SyncError> {
+	revalidate_user_courses(app.clone(), account_id).await?;
+
+	let db_state = app.state::<DatabaseState>();
+	let course_ids = entity::Course::find()
+		.select_only()
+		.column(entity::course::Column::Id)
+		.filter(entity::course::Column::AccountId.eq(account_id))
+		.into_tuple::<i32>()
+		.all(&db_state.0)
+		.await
+		.map_err(|e| SyncError::storage(format!("Failed to query courses: {}", e)))?;
+
+	let mut last_error = None;
+	for course_id in course_ids {
+		if is_paused(app) {
+			break;
+		}
+
+		match revalidate_course(app.clone(), account_id, course_id).await {
+			Err(e) if should_stop(&e) => return Err(e),
+			Err(e) => {
+				last_error = Some(e);
+				continue;
+			}
+			Ok(_) => {}
+		}
+
+		let modules = entity::SectionModule::find()
+			.inner_join(entity::CourseSection)
+			.filter(entity::course_section::Column::AccountId.eq(account_id))
+			.filter(entity::course_section::Column::CourseId.eq(course_id))
+			.all(&db_state.0)
+			.await
+			.map_err(|e| SyncError::storage(format!("Failed to query course modules: {}", e)))?;
+
+		let module_ids = modules
+			.iter()
+			.filter(|module| is_supported_module(module))
+			.map(|module| module.id)
+			.collect::<Vec<_>>();
+
+		// one update check per course instead of fetching every module, falling back to
+		// syncing all of them when moodle can't tell us what changed
+		let module_ids = match get_changed_modules(app, account_id, course_id, &module_ids).await {
+			Ok(changed) => module_ids
+				.into_iter()
+				.filter(|module_id| changed.contains(module_id))
+				.collect(),
+			Err(e) if should_stop(&e) => return Err(e),
+			Err(e) => {
+				log::warn!(
+					"Failed to check updates for course {}, syncing all modules: {}",
+					course_id,
+					e.message
+				);
+				module_ids
+			}
+		};
+
+		for chunk in module_ids.chunks(MAX_BATCH_SIZE) {
+			if is_paused(app) {
+				break;
+			}
+
+			for (_, result) in revalidate_modules_content(app.clone(), account_id, course_id, chunk).await
+			{
+				match result {
+					Err(e) if should_stop(&e) => return Err(e),
+					Err(e) => last_error = Some(e),
+					Ok(_) => {}
+				}
+			}
+		}
+	}
+
+	match last_error {
+		Some(e) => Err(e),
+		None => Ok(()),
+	}
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn pause_sync_scheduler(app: AppHandle) -> Result<(), String> {
+	app
+		.state::<SchedulerState>()
+		.paused
+		.store(true, Ordering::Relaxed);
+	Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn resume_sync_scheduler(app: AppHandle) -> Result<(), String> {
+	let scheduler = app.state::<SchedulerState>();
+	scheduler.paused.store(false, Ordering::Relaxed);
+	scheduler.wake.notify_one();
+	Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn set_sync_interval(
+	app: AppHandle,
+	interval_secs: u32,
+	jitter_secs: u32,
+) -> Result<(), String> {
+	if interval_secs == 0 {
+		return Err("sync interval must be greater than zero".to_string());
+	}
+
+	let store = app.store("store.json").map_err(|e| e.to_string())?;
+	store.set(scheduler_keys::INTERVAL, interval_secs);
+	store.set(scheduler_keys::JITTER, jitter_secs);
+	app.state::<SchedulerState>().wake.notify_one();
+	Ok(())
+}
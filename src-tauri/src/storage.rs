@@ -0,0 +1,1043 @@
+use std::{
+	collections::{HashMap, HashSet},
+	ops::Not,
+};
+
+use anyhow::Context;
+use sea_orm::{
+	ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait, PaginatorTrait,
+	QueryFilter, QuerySelect, TransactionTrait,
+	sea_query::{Expr, Query},
+};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use sqlx::types::chrono::Utc;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_store::StoreExt;
+
+use entity::content_blob::Model as ContentBlob;
+
+use crate::{
+	account, blob_store,
+	database::DatabaseState,
+	encryption::{self, Key},
+	request::course::{course_sync_id, module_content_sync_id, revalidate_modules_content},
+	sync_task,
+};
+
+pub mod storage_keys {
+	pub const CAP: &str = "storage_cap";
+	pub const VERIFY_ON_STARTUP: &str = "verify_storage_on_startup";
+	pub const VERIFY_CHECKSUM_ON_STARTUP: &str = "verify_storage_checksum_on_startup";
+}
+
+/// what was removed from storage, by a sync that found things moodle no longer lists
+/// or by pruning
+#[derive(Serialize, Deserialize, Type, Debug, Clone, Default)]
+pub struct PruneReport {
+	pub courses: u32,
+	pub sections: u32,
+	pub modules: u32,
+	pub module_contents: u32,
+	pub module_content_versions: u32,
+	pub content_blobs: u32,
+	pub files: u32,
+	pub bytes_freed: u64,
+}
+
+impl PruneReport {
+	pub fn is_empty(&self) -> bool {
+		self.courses == 0
+			&& self.sections == 0
+			&& self.modules == 0
+			&& self.module_contents == 0
+			&& self.module_content_versions == 0
+			&& self.content_blobs == 0
+			&& self.files == 0
+	}
+
+	fn add(&mut self, other: PruneReport) {
+		self.courses += other.courses;
+		self.sections += other.sections;
+		self.modules += other.modules;
+		self.module_contents += other.module_contents;
+		self.module_content_versions += other.module_content_versions;
+		self.content_blobs += other.content_blobs;
+		self.files += other.files;
+		self.bytes_freed += other.bytes_freed;
+	}
+}
+
+// everything below a row is deleted explicitly rather than left to cascade, so what was
+// removed can be counted and the files it pointed at released. rows are deleted within the
+// caller's transaction while the files are only released once it's been committed. ids
+// are only unique within an account, so a pruner only ever deletes from the one
+pub(crate) struct Pruner {
+	account_id: i32,
+	report: PruneReport,
+	released_paths: Vec<String>,
+}
+
+impl Pruner {
+	pub fn new(account_id: i32) -> Self {
+		Self {
+			account_id,
+			report: PruneReport::default(),
+			released_paths: Vec::new(),
+		}
+	}
+
+	pub async fn delete_courses<C: ConnectionTrait>(
+		&mut self,
+		db: &C,
+		course_ids: Vec<i32>,
+	) -> Result<(), DbErr> {
+		if course_ids.is_empty() {
+			return Ok(());
+		}
+
+		let section_ids = entity::CourseSection::find()
+			.select_only()
+			.column(entity::course_section::Column::Id)
+			.filter(entity::course_section::Column::AccountId.eq(self.account_id))
+			.filter(entity::course_section::Column::CourseId.is_in(course_ids.clone()))
+			.into_tuple::<i32>()
+			.all(db)
+			.await?;
+		self.delete_sections(db, section_ids).await?;
+
+		delete_sync_logs(
+			db,
+			self.account_id,
+			course_ids.iter().map(|id| course_sync_id(*id)),
+		)
+		.await?;
+		self.report.courses += entity::Course::delete_many()
+			.filter(entity::course::Column::AccountId.eq(self.account_id))
+			.filter(entity::course::Column::Id.is_in(course_ids))
+			.exec(db)
+			.await?
+			.rows_affected as u32;
+
+		Ok(())
+	}
+
+	pub async fn delete_sections<C: ConnectionTrait>(
+		&mut self,
+		db: &C,
+		section_ids: Vec<i32>,
+	) -> Result<(), DbErr> {
+		if section_ids.is_empty() {
+			return Ok(());
+		}
+
+		let module_ids = entity::SectionModule::find()
+			.select_only()
+			.column(entity::section_module::Column::Id)
+			.filter(entity::section_module::Column::AccountId.eq(self.account_id))
+			.filter(entity::section_module::Column::SectionId.is_in(section_ids.clone()))
+			.into_tuple::<i32>()
+			.all(db)
+			.await?;
+		self.delete_modules(db, module_ids).await?;
+
+		self.report.sections += entity::CourseSection::delete_many()
+			.filter(entity::course_section::Column::AccountId.eq(self.account_id))
+			.filter(entity::course_section::Column::Id.is_in(section_ids))
+			.exec(db)
+			.await?
+			.rows_affected as u32;
+
+		Ok(())
+	}
+
+	pub async fn delete_modules<C: ConnectionTrait>(
+		&mut self,
+		db: &C,
+		module_ids: Vec<i32>,
+	) -> Result<(), DbErr> {
+		if module_ids.is_empty() {
+			return Ok(());
+		}
+
+		self.delete_module_data(db, module_ids.clone()).await?;
+		delete_sync_logs(
+			db,
+			self.account_id,
+			module_ids.iter().map(|id| module_content_sync_id(*id)),
+		)
+		.await?;
+		self.report.modules += entity::SectionModule::delete_many()
+			.filter(entity::section_module::Column::AccountId.eq(self.account_id))
+			.filter(entity::section_module::Column::Id.is_in(module_ids))
+			.exec(db)
+			.await?
+			.rows_affected as u32;
+
+		Ok(())
+	}
+
+	// content, version and blob rows, which can outlive their module
+	async fn delete_module_data<C: ConnectionTrait>(
+		&mut self,
+		db: &C,
+		module_ids: Vec<i32>,
+	) -> Result<(), DbErr> {
+		if module_ids.is_empty() {
+			return Ok(());
+		}
+
+		let blob_paths = entity::ContentBlob::find()
+			.select_only()
+			.column(entity::content_blob::Column::Path)
+			.filter(entity::content_blob::Column::AccountId.eq(self.account_id))
+			.filter(entity::content_blob::Column::ModuleId.is_in(module_ids.clone()))
+			.into_tuple::<String>()
+			.all(db)
+			.await?;
+		self.released_paths.extend(blob_paths);
+
+		self.report.content_blobs += entity::ContentBlob::delete_many()
+			.filter(entity::content_blob::Column::AccountId.eq(self.account_id))
+			.filter(entity::content_blob::Column::ModuleId.is_in(module_ids.clone()))
+			.exec(db)
+			.await?
+			.rows_affected as u32;
+		self.report.module_contents += entity::ModuleContent::delete_many()
+			.filter(entity::module_content::Column::AccountId.eq(self.account_id))
+			.filter(entity::module_content::Column::ModuleId.is_in(module_ids.clone()))
+			.exec(db)
+			.await?
+			.rows_affected as u32;
+		self.report.module_content_versions += entity::ModuleContentVersion::delete_many()
+			.filter(entity::module_content_version::Column::AccountId.eq(self.account_id))
+			.filter(entity::module_content_version::Column::ModuleId.is_in(module_ids))
+			.exec(db)
+			.await?
+			.rows_affected as u32;
+
+		Ok(())
+	}
+
+	/// releases the files of everything deleted, call this once the deletes have been committed
+	pub async fn finish(mut self, db: &DatabaseConnection) -> PruneReport {
+		let (files, bytes_freed) = blob_store::release_unreferenced(db, self.released_paths).await;
+		self.report.files += files;
+		self.report.bytes_freed += bytes_freed;
+		self.report
+	}
+}
+
+async fn delete_sync_logs<C: ConnectionTrait>(
+	db: &C,
+	account_id: i32,
+	sync_ids: impl Iterator<Item = String>,
+) -> Result<(), DbErr> {
+	entity::SyncLog::delete_many()
+		.filter(
+			entity::sync_log::Column::Id
+				.is_in(sync_ids.map(|id| sync_task::normalise_sync_id(account_id, &id))),
+		)
+		.exec(db)
+		.await?;
+
+	Ok(())
+}
+
+/// removes anything left behind in storage: rows whose parent is gone, files no content
+/// blob points at and abandoned partial downloads. stale courses and modules are already
+/// removed as part of syncing, so this mostly cleans up after crashes and older versions
+#[tauri::command]
+#[specta::specta]
+pub async fn prune_storage(app: AppHandle) -> Result<PruneReport, String> {
+	prune(&app).await.map_err(|e| e.to_string())
+}
+
+async fn prune(app: &AppHandle) -> anyhow::Result<PruneReport> {
+	let state = app.state::<DatabaseState>();
+	let db = &state.0;
+	// signed out accounts included, what's kept of them can be left behind all the same
+	let account_ids = entity::Account::find()
+		.select_only()
+		.column(entity::account::Column::Id)
+		.into_tuple::<i32>()
+		.all(db)
+		.await
+		.with_context(|| "Failed to query accounts")?;
+
+	let mut report = PruneReport::default();
+	for account_id in account_ids {
+		report.add(
+			prune_account(db, account_id)
+				.await
+				.with_context(|| format!("Failed to prune account {}", account_id))?,
+		);
+	}
+
+	let referenced = entity::ContentBlob::find()
+		.select_only()
+		.column(entity::content_blob::Column::Path)
+		.into_tuple::<String>()
+		.all(db)
+		.await
+		.with_context(|| "Failed to query content blob paths")?
+		.into_iter()
+		.collect::<HashSet<_>>();
+	let (files, bytes_freed) = blob_store::remove_untracked(app, referenced).await;
+	report.files += files;
+	report.bytes_freed += bytes_freed;
+
+	log::info!("Pruned storage: {:?}", report);
+	Ok(report)
+}
+
+async fn prune_account(db: &DatabaseConnection, account_id: i32) -> anyhow::Result<PruneReport> {
+	let txn = db
+		.begin()
+		.await
+		.with_context(|| "Failed to begin transaction")?;
+
+	let mut pruner = Pruner::new(account_id);
+	// courses are only ever removed by syncing, which takes everything below them along,
+	// but sections and modules could be left over from before it did
+	let orphaned_sections = entity::CourseSection::find()
+		.select_only()
+		.column(entity::course_section::Column::Id)
+		.filter(entity::course_section::Column::AccountId.eq(account_id))
+		.filter(
+			entity::course_section::Column::CourseId.not_in_subquery(
+				Query::select()
+					.column(entity::course::Column::Id)
+					.from(entity::Course)
+					.and_where(entity::course::Column::AccountId.eq(account_id))
+					.to_owned(),
+			),
+		)
+		.into_tuple::<i32>()
+		.all(&txn)
+		.await
+		.with_context(|| "Failed to query orphaned sections")?;
+	pruner
+		.delete_sections(&txn, orphaned_sections)
+		.await
+		.with_context(|| "Failed to delete orphaned sections")?;
+
+	let orphaned_modules = entity::SectionModule::find()
+		.select_only()
+		.column(entity::section_module::Column::Id)
+		.filter(entity::section_module::Column::AccountId.eq(account_id))
+		.filter(
+			entity::section_module::Column::SectionId.not_in_subquery(
+				Query::select()
+					.column(entity::course_section::Column::Id)
+					.from(entity::CourseSection)
+					.and_where(entity::course_section::Column::AccountId.eq(account_id))
+					.to_owned(),
+			),
+		)
+		.into_tuple::<i32>()
+		.all(&txn)
+		.await
+		.with_context(|| "Failed to query orphaned modules")?;
+	pruner
+		.delete_modules(&txn, orphaned_modules)
+		.await
+		.with_context(|| "Failed to delete orphaned modules")?;
+
+	let module_ids = Query::select()
+		.column(entity::section_module::Column::Id)
+		.from(entity::SectionModule)
+		.and_where(entity::section_module::Column::AccountId.eq(account_id))
+		.to_owned();
+	let orphaned_content = entity::ModuleContent::find()
+		.select_only()
+		.column(entity::module_content::Column::ModuleId)
+		.filter(entity::module_content::Column::AccountId.eq(account_id))
+		.filter(entity::module_content::Column::ModuleId.not_in_subquery(module_ids.clone()))
+		.into_tuple::<i32>()
+		.all(&txn)
+		.await
+		.with_context(|| "Failed to query orphaned module content")?;
+	let orphaned_versions = entity::ModuleContentVersion::find()
+		.select_only()
+		.column(entity::module_content_version::Column::ModuleId)
+		.filter(entity::module_content_version::Column::AccountId.eq(account_id))
+		.filter(entity::module_content_version::Column::ModuleId.not_in_subquery(module_ids.clone()))
+		.into_tuple::<i32>()
+		.all(&txn)
+		.await
+		.with_context(|| "Failed to query orphaned module content versions")?;
+	let orphaned_blobs = entity::ContentBlob::find()
+		.select_only()
+		.column(entity::content_blob::Column::ModuleId)
+		.filter(entity::content_blob::Column::AccountId.eq(account_id))
+		.filter(entity::content_blob::Column::ModuleId.not_in_subquery(module_ids))
+		.into_tuple::<i32>()
+		.all(&txn)
+		.await
+		.with_context(|| "Failed to query orphaned content blobs")?;
+	pruner
+		.delete_module_data(
+			&txn,
+			orphaned_content
+				.into_iter()
+				.chain(orphaned_versions)
+				.chain(orphaned_blobs)
+				.collect::<HashSet<_>>()
+				.into_iter()
+				.collect(),
+		)
+		.await
+		.with_context(|| "Failed to delete orphaned module content")?;
+
+	txn
+		.commit()
+		.await
+		.with_context(|| "Failed to commit transaction")?;
+	Ok(pruner.finish(db).await)
+}
+
+// usage of the active account, while the cap covers every account together
+#[derive(Serialize, Deserialize, Type, Debug, Clone)]
+pub struct StorageUsage {
+	pub cap_bytes: Option<u64>,
+	// files shared between modules (or courses) are only counted once here
+	pub blob_bytes: u64,
+	pub content_bytes: u64,
+	pub courses: Vec<CourseStorageUsage>,
+}
+
+#[derive(Serialize, Deserialize, Type, Debug, Clone)]
+pub struct CourseStorageUsage {
+	pub course_id: i32,
+	pub pinned: bool,
+	pub blob_bytes: u64,
+	pub content_bytes: u64,
+	pub modules: Vec<ModuleStorageUsage>,
+}
+
+#[derive(Serialize, Deserialize, Type, Debug, Clone)]
+pub struct ModuleStorageUsage {
+	pub module_id: i32,
+	pub pinned: bool,
+	pub last_opened_at: Option<i64>,
+	pub blob_bytes: u64,
+	pub content_bytes: u64,
+	// blobs removed to stay under the cap, downloaded again when the module is opened
+	pub evicted_blobs: u32,
+}
+
+fn storage_cap(app: &AppHandle) -> Option<u64> {
+	app
+		.store("store.json")
+		.ok()
+		.and_then(|store| store.get(storage_keys::CAP))
+		.and_then(|cap| cap.as_u64())
+}
+
+struct StoredModule {
+	account_id: i32,
+	course_id: i32,
+	pinned: bool,
+	last_opened_at: Option<i64>,
+}
+
+// modules by account and module id, those whose section or course is gone are left out
+// as pruning takes care of them
+async fn stored_modules(
+	db: &DatabaseConnection,
+) -> Result<HashMap<(i32, i32), StoredModule>, DbErr> {
+	let pinned_courses = entity::Course::find()
+		.select_only()
+		.column(entity::course::Column::AccountId)
+		.column(entity::course::Column::Id)
+		.filter(entity::course::Column::Pinned.eq(true))
+		.into_tuple::<(i32, i32)>()
+		.all(db)
+		.await?
+		.into_iter()
+		.collect::<HashSet<_>>();
+
+	Ok(
+		entity::SectionModule::find()
+			.inner_join(entity::CourseSection)
+			.select_only()
+			.column(entity::section_module::Column::AccountId)
+			.column(entity::section_module::Column::Id)
+			.column(entity::course_section::Column::CourseId)
+			.column(entity::section_module::Column::Pinned)
+			.column(entity::section_module::Column::LastOpenedAt)
+			.into_tuple::<(i32, i32, i32, bool, Option<i64>)>()
+			.all(db)
+			.await?
+			.into_iter()
+			.map(|(account_id, id, course_id, pinned, last_opened_at)| {
+				let module = StoredModule {
+					account_id,
+					course_id,
+					// pinning a course pins everything in it
+					pinned: pinned || pinned_courses.contains(&(account_id, course_id)),
+					last_opened_at,
+				};
+				((account_id, id), module)
+			})
+			.collect(),
+	)
+}
+
+// files that have gone missing are left out
+async fn file_sizes<'a>(paths: impl Iterator<Item = &'a String>) -> HashMap<String, u64> {
+	let mut sizes = HashMap::new();
+	for path in paths {
+		if let Ok(metadata) = tokio::fs::metadata(path).await {
+			sizes.insert(path.clone(), metadata.len());
+		}
+	}
+
+	sizes
+}
+
+/// records that the module was opened, which keeps its blobs from being evicted before
+/// modules that haven't been opened in a while. blobs already evicted are downloaded again
+/// by the sync opening the module starts
+pub(crate) async fn mark_opened(app: &AppHandle, account_id: i32, module_id: i32) {
+	let state = app.state::<DatabaseState>();
+	let db = &state.0;
+	let opened = entity::SectionModule::update_many()
+		.col_expr(
+			entity::section_module::Column::LastOpenedAt,
+			Expr::value(Utc::now().timestamp()),
+		)
+		.filter(entity::section_module::Column::AccountId.eq(account_id))
+		.filter(entity::section_module::Column::Id.eq(module_id))
+		.exec(db)
+		.await;
+	if let Err(e) = opened {
+		log::error!("Failed to mark module {} as opened: {}", module_id, e);
+		return;
+	}
+
+	let evicted = entity::ContentBlob::find()
+		.filter(entity::content_blob::Column::AccountId.eq(account_id))
+		.filter(entity::content_blob::Column::ModuleId.eq(module_id))
+		.filter(entity::content_blob::Column::EvictedAt.is_not_null())
+		.count(db)
+		.await
+		.unwrap_or_else(|e| {
+			log::error!(
+				"Failed to count evicted blobs of module {}: {}",
+				module_id,
+				e
+			);
+			0
+		});
+
+	// otherwise a recent sync would have the one opening the module throttled
+	if evicted > 0 {
+		sync_task::invalidate(app, account_id, &module_content_sync_id(module_id)).await;
+	}
+}
+
+/// evicts the blobs of the least recently opened modules until storage is back under the
+/// cap, skipping pinned modules and courses. a file shared between modules is as recent as
+/// the most recently opened of them. every account counts towards the same cap. resolves to
+/// how many files were evicted and how many bytes that freed
+pub(crate) async fn enforce_storage_cap(app: &AppHandle) -> anyhow::Result<(u32, u64)> {
+	let Some(cap) = storage_cap(app) else {
+		return Ok((0, 0));
+	};
+
+	let state = app.state::<DatabaseState>();
+	let db = &state.0;
+	let modules = stored_modules(db)
+		.await
+		.with_context(|| "Failed to query stored modules")?;
+	let blobs = entity::ContentBlob::find()
+		.select_only()
+		.column(entity::content_blob::Column::Path)
+		.column(entity::content_blob::Column::AccountId)
+		.column(entity::content_blob::Column::ModuleId)
+		.filter(entity::content_blob::Column::EvictedAt.is_null())
+		.into_tuple::<(String, i32, i32)>()
+		.all(db)
+		.await
+		.with_context(|| "Failed to query content blobs")?;
+
+	let mut files = HashMap::<String, (Option<i64>, bool)>::new();
+	for (path, account_id, module_id) in blobs {
+		let (last_opened_at, pinned) = files.entry(path).or_default();
+		if let Some(module) = modules.get(&(account_id, module_id)) {
+			*last_opened_at = (*last_opened_at).max(module.last_opened_at);
+			*pinned |= module.pinned;
+		}
+	}
+
+	let sizes = file_sizes(files.keys()).await;
+	let mut used = sizes.values().sum::<u64>();
+	if used <= cap {
+		return Ok((0, 0));
+	}
+
+	// modules that were never opened go first
+	let mut candidates = files
+		.into_iter()
+		.filter(|(_, (_, pinned))| pinned.not())
+		.map(|(path, (last_opened_at, _))| (last_opened_at, path))
+		.collect::<Vec<_>>();
+	candidates.sort();
+
+	let now = Utc::now().timestamp();
+	let mut evicted = (0, 0);
+	for (_, path) in candidates {
+		if used <= cap {
+			break;
+		}
+
+		let Some(&size) = sizes.get(&path) else {
+			continue;
+		};
+
+		// the file goes first, a row left behind without it is just downloaded again
+		if let Err(e) = tokio::fs::remove_file(&path).await {
+			log::warn!("Failed to evict content blob {}: {}", path, e);
+			continue;
+		}
+
+		entity::ContentBlob::update_many()
+			.col_expr(entity::content_blob::Column::EvictedAt, Expr::value(now))
+			.filter(entity::content_blob::Column::Path.eq(&path))
+			.exec(db)
+			.await
+			.with_context(|| format!("Failed to mark content blob {} as evicted", path))?;
+
+		used -= size;
+		evicted.0 += 1;
+		evicted.1 += size;
+	}
+
+	if used > cap {
+		log::warn!(
+			"Storage is still over its cap of {} bytes ({} bytes used), everything left is pinned",
+			cap,
+			used
+		);
+	}
+
+	log::info!(
+		"Evicted {} content blobs ({} bytes) to stay under the storage cap",
+		evicted.0,
+		evicted.1
+	);
+	Ok(evicted)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn get_storage_usage(app: AppHandle) -> Result<StorageUsage, String> {
+	storage_usage(&app).await.map_err(|e| e.to_string())
+}
+
+async fn storage_usage(app: &AppHandle) -> anyhow::Result<StorageUsage> {
+	let account_id = account::require_active(app).map_err(anyhow::Error::msg)?;
+	let state = app.state::<DatabaseState>();
+	let db = &state.0;
+	let modules = stored_modules(db)
+		.await
+		.with_context(|| "Failed to query stored modules")?
+		.into_iter()
+		.filter(|((module_account_id, _), _)| *module_account_id == account_id)
+		.map(|((_, module_id), module)| (module_id, module))
+		.collect::<HashMap<_, _>>();
+	let pinned_courses = entity::Course::find()
+		.select_only()
+		.column(entity::course::Column::Id)
+		.column(entity::course::Column::Pinned)
+		.filter(entity::course::Column::AccountId.eq(account_id))
+		.into_tuple::<(i32, bool)>()
+		.all(db)
+		.await
+		.with_context(|| "Failed to query stored courses")?;
+
+	// in bytes rather than characters, counting the versions kept of each module's content
+	let mut content_bytes = HashMap::<i32, i64>::new();
+	let current = entity::ModuleContent::find()
+		.select_only()
+		.column(entity::module_content::Column::ModuleId)
+		.column_as(
+			Expr::cust("COALESCE(SUM(LENGTH(CAST(content AS BLOB))), 0)"),
+			"bytes",
+		)
+		.filter(entity::module_content::Column::AccountId.eq(account_id))
+		.group_by(entity::module_content::Column::ModuleId)
+		.into_tuple::<(i32, i64)>()
+		.all(db)
+		.await
+		.with_context(|| "Failed to query module content size")?;
+	let versions = entity::ModuleContentVersion::find()
+		.select_only()
+		.column(entity::module_content_version::Column::ModuleId)
+		.column_as(
+			Expr::cust("COALESCE(SUM(LENGTH(CAST(content AS BLOB))), 0)"),
+			"bytes",
+		)
+		.filter(entity::module_content_version::Column::AccountId.eq(account_id))
+		.group_by(entity::module_content_version::Column::ModuleId)
+		.into_tuple::<(i32, i64)>()
+		.all(db)
+		.await
+		.with_context(|| "Failed to query module content version size")?;
+	for (module_id, bytes) in current.into_iter().chain(versions) {
+		*content_bytes.entry(module_id).or_default() += bytes;
+	}
+
+	let blobs = entity::ContentBlob::find()
+		.select_only()
+		.column(entity::content_blob::Column::ModuleId)
+		.column(entity::content_blob::Column::Path)
+		.column(entity::content_blob::Column::EvictedAt)
+		.filter(entity::content_blob::Column::AccountId.eq(account_id))
+		.into_tuple::<(i32, String, Option<i64>)>()
+		.all(db)
+		.await
+		.with_context(|| "Failed to query content blobs")?;
+	let sizes = file_sizes(
+		blobs
+			.iter()
+			.filter(|(_, _, evicted_at)| evicted_at.is_none())
+			.map(|(_, path, _)| path),
+	)
+	.await;
+
+	let mut module_usage = HashMap::<i32, (ModuleStorageUsage, HashSet<&String>)>::new();
+	for (&module_id, module) in &modules {
+		let usage = ModuleStorageUsage {
+			module_id,
+			pinned: module.pinned,
+			last_opened_at: module.last_opened_at,
+			blob_bytes: 0,
+			content_bytes: content_bytes.get(&module_id).copied().unwrap_or(0) as u64,
+			evicted_blobs: 0,
+		};
+		module_usage.insert(module_id, (usage, HashSet::new()));
+	}
+
+	for (module_id, path, evicted_at) in &blobs {
+		let Some((usage, paths)) = module_usage.get_mut(module_id) else {
+			continue;
+		};
+
+		if evicted_at.is_some() {
+			usage.evicted_blobs += 1;
+		} else if paths.insert(path) {
+			usage.blob_bytes += sizes.get(path).copied().unwrap_or(0);
+		}
+	}
+
+	let mut courses = pinned_courses
+		.into_iter()
+		.map(|(course_id, pinned)| {
+			(
+				course_id,
+				(
+					CourseStorageUsage {
+						course_id,
+						pinned,
+						blob_bytes: 0,
+						content_bytes: 0,
+						modules: Vec::new(),
+					},
+					HashSet::new(),
+				),
+			)
+		})
+		.collect::<HashMap<_, _>>();
+	for (module_id, (usage, paths)) in module_usage {
+		let Some((course, course_paths)) = courses.get_mut(&modules[&module_id].course_id) else {
+			continue;
+		};
+
+		course.content_bytes += usage.content_bytes;
+		for path in paths {
+			if course_paths.insert(path) {
+				course.blob_bytes += sizes.get(path).copied().unwrap_or(0);
+			}
+		}
+		course.modules.push(usage);
+	}
+
+	let mut courses = courses
+		.into_values()
+		.map(|(mut course, _)| {
+			course.modules.sort_by_key(|module| module.module_id);
+			course
+		})
+		.collect::<Vec<_>>();
+	courses.sort_by_key(|course| course.course_id);
+
+	Ok(StorageUsage {
+		cap_bytes: storage_cap(app),
+		blob_bytes: sizes.values().sum(),
+		content_bytes: courses.iter().map(|course| course.content_bytes).sum(),
+		courses,
+	})
+}
+
+/// caps how much space blobs may take up, evicting the least recently opened straight away
+/// if storage is already over it. no cap means storage is unlimited
+#[tauri::command]
+#[specta::specta]
+pub async fn set_storage_cap(app: AppHandle, cap_bytes: Option<u64>) -> Result<(), String> {
+	let store = app.store("store.json").map_err(|e| e.to_string())?;
+	match cap_bytes {
+		Some(cap_bytes) => store.set(storage_keys::CAP, cap_bytes),
+		None => {
+			store.delete(storage_keys::CAP);
+		}
+	}
+
+	enforce_storage_cap(&app).await.map_err(|e| e.to_string())?;
+	Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn set_module_pinned(app: AppHandle, module_id: i32, pinned: bool) -> Result<(), String> {
+	let account_id = account::require_active(&app)?;
+	let state = app.state::<DatabaseState>();
+	entity::SectionModule::update_many()
+		.col_expr(entity::section_module::Column::Pinned, Expr::value(pinned))
+		.filter(entity::section_module::Column::AccountId.eq(account_id))
+		.filter(entity::section_module::Column::Id.eq(module_id))
+		.exec(&state.0)
+		.await
+		.map_err(|e| e.to_string())?;
+
+	// what was being kept might put storage over the cap
+	if pinned.not() {
+		enforce_storage_cap(&app).await.map_err(|e| e.to_string())?;
+	}
+
+	Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn set_course_pinned(app: AppHandle, course_id: i32, pinned: bool) -> Result<(), String> {
+	let account_id = account::require_active(&app)?;
+	let state = app.state::<DatabaseState>();
+	entity::Course::update_many()
+		.col_expr(entity::course::Column::Pinned, Expr::value(pinned))
+		.filter(entity::course::Column::AccountId.eq(account_id))
+		.filter(entity::course::Column::Id.eq(course_id))
+		.exec(&state.0)
+		.await
+		.map_err(|e| e.to_string())?;
+
+	if pinned.not() {
+		enforce_storage_cap(&app).await.map_err(|e| e.to_string())?;
+	}
+
+	Ok(())
+}
+
+#[derive(Serialize, Deserialize, Type, Debug, Clone, Default)]
+pub struct VerifyReport {
+	pub checked: u32,
+	pub missing: u32,
+	// truncated, or the content doesn't match its hash
+	pub corrupt: u32,
+	// modules whose broken blobs were queued to be downloaded again
+	pub queued_modules: u32,
+}
+
+enum FileCheck {
+	Intact(u64),
+	Missing,
+	Corrupt,
+}
+
+fn check_file(
+	key: Option<&Key>,
+	path: &str,
+	expected_hash: &str,
+	expected_size: Option<i64>,
+	checksum: bool,
+) -> FileCheck {
+	// sizes are of the content, sealed files are a little larger
+	let Ok(size) = encryption::content_len(std::path::Path::new(path)) else {
+		return FileCheck::Missing;
+	};
+
+	if expected_size.is_some_and(|expected_size| expected_size != size as i64) {
+		return FileCheck::Corrupt;
+	}
+
+	if checksum {
+		match blob_store::hash_file(key, std::path::Path::new(path)) {
+			Ok(hash) if hash == expected_hash => {}
+			Ok(_) => return FileCheck::Corrupt,
+			// a sealed chunk that doesn't open was changed on disk
+			Err(e) if e.kind() == std::io::ErrorKind::InvalidData => return FileCheck::Corrupt,
+			Err(e) => {
+				log::warn!("Failed to hash content blob {}: {}", path, e);
+				return FileCheck::Missing;
+			}
+		}
+	}
+
+	FileCheck::Intact(size)
+}
+
+/// checks that every stored blob's file is still there and intact, i.e. not deleted from
+/// under us or truncated by an interrupted write. broken blobs lose their hash, which has
+/// them downloaded again, and their modules are queued to sync. `checksum` also hashes
+/// every file rather than just checking its size, which is a lot slower
+#[tauri::command]
+#[specta::specta]
+pub async fn verify_storage(app: AppHandle, checksum: bool) -> Result<VerifyReport, String> {
+	verify(&app, checksum).await.map_err(|e| e.to_string())
+}
+
+async fn verify(app: &AppHandle, checksum: bool) -> anyhow::Result<VerifyReport> {
+	let state = app.state::<DatabaseState>();
+	let db = &state.0;
+	// evicted blobs are meant to be missing, and those without a hash are already queued
+	let blobs = entity::ContentBlob::find()
+		.filter(entity::content_blob::Column::EvictedAt.is_null())
+		.filter(entity::content_blob::Column::Hash.is_not_null())
+		.all(db)
+		.await
+		.with_context(|| "Failed to query content blobs")?;
+
+	let mut files = HashMap::<String, Vec<ContentBlob>>::new();
+	for blob in blobs {
+		files.entry(blob.path.clone()).or_default().push(blob);
+	}
+
+	let key = encryption::key(app);
+	let mut report = VerifyReport::default();
+	let mut broken_modules = HashSet::new();
+	for (path, blobs) in files {
+		report.checked += 1;
+		let expected_hash = blobs[0].hash.clone().unwrap_or_default();
+		let expected_size = blobs.iter().find_map(|blob| blob.size);
+		let check_path = path.clone();
+		let check_key = key.clone();
+		let check = tokio::task::spawn_blocking(move || {
+			check_file(
+				check_key.as_ref(),
+				&check_path,
+				&expected_hash,
+				expected_size,
+				checksum,
+			)
+		})
+		.await
+		.with_context(|| "Failed to join verification task")?;
+
+		match check {
+			FileCheck::Intact(size) => {
+				// rows from before sizes were stored
+				if expected_size.is_none() {
+					entity::ContentBlob::update_many()
+						.col_expr(entity::content_blob::Column::Size, Expr::value(size as i64))
+						.filter(entity::content_blob::Column::Path.eq(&path))
+						.exec(db)
+						.await
+						.with_context(|| format!("Failed to update size of content blob {}", path))?;
+				}
+
+				continue;
+			}
+			FileCheck::Missing => {
+				log::warn!("Content blob {} is missing", path);
+				report.missing += 1;
+			}
+			FileCheck::Corrupt => {
+				log::warn!("Content blob {} is corrupt, removing it", path);
+				report.corrupt += 1;
+				tokio::fs::remove_file(&path).await.ok();
+			}
+		}
+
+		entity::ContentBlob::update_many()
+			.col_expr(
+				entity::content_blob::Column::Hash,
+				Expr::value(None::<String>),
+			)
+			.col_expr(entity::content_blob::Column::Size, Expr::value(None::<i64>))
+			.filter(entity::content_blob::Column::Path.eq(&path))
+			.exec(db)
+			.await
+			.with_context(|| format!("Failed to mark content blob {} as broken", path))?;
+		broken_modules.extend(blobs.iter().map(|blob| (blob.account_id, blob.module_id)));
+	}
+
+	let modules = stored_modules(db)
+		.await
+		.with_context(|| "Failed to query stored modules")?;
+	let mut courses = HashMap::<(i32, i32), Vec<i32>>::new();
+	for (account_id, module_id) in broken_modules {
+		let Some(module) = modules.get(&(account_id, module_id)) else {
+			continue;
+		};
+
+		// otherwise they'd be throttled, or skipped as unchanged
+		sync_task::invalidate(app, account_id, &module_content_sync_id(module_id)).await;
+		courses
+			.entry((module.account_id, module.course_id))
+			.or_default()
+			.push(module_id);
+		report.queued_modules += 1;
+	}
+
+	if courses.is_empty().not() {
+		let app = app.clone();
+		tauri::async_runtime::spawn(async move {
+			for ((account_id, course_id), module_ids) in courses {
+				revalidate_modules_content(app.clone(), account_id, course_id, &module_ids).await;
+			}
+		});
+	}
+
+	log::info!("Verified storage: {:?}", report);
+	Ok(report)
+}
+
+/// verifies storage in the background if enabled. only sizes are checked unless checksums
+/// were asked for too, since hashing every file can take a while on larger stores
+pub fn verify_on_startup(app: AppHandle) {
+	let Ok(store) = app.store("store.json") else {
+		return;
+	};
+	let setting = |key: &str| {
+		store
+			.get(key)
+			.and_then(|value| value.as_bool())
+			.unwrap_or(false)
+	};
+	if setting(storage_keys::VERIFY_ON_STARTUP).not() {
+		return;
+	}
+
+	let checksum = setting(storage_keys::VERIFY_CHECKSUM_ON_STARTUP);
+	tauri::async_runtime::spawn(async move {
+		if let Err(e) = verify(&app, checksum).await {
+			log::error!("Failed to verify storage on startup: {}", e);
+		}
+	});
+}
+
+/// `checksum` hashes every blob on startup as well, see `verify_storage`
+#[tauri::command]
+#[specta::specta]
+pub async fn set_verify_storage_on_startup(
+	app: AppHandle,
+	enabled: bool,
+	checksum: bool,
+) -> Result<(), String> {
+	let store = app.store("store.json").map_err(|e| e.to_string())?;
+	store.set(storage_keys::VERIFY_ON_STARTUP, enabled);
+	store.set(storage_keys::VERIFY_CHECKSUM_ON_STARTUP, checksum);
+	Ok(())
+}
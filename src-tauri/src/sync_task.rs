@@ -1,65 +1,321 @@
-use std::{collections::HashMap, future::Future, pin::Pin};
+use std::{
+	collections::HashMap,
+	future::Future,
+	ops::Not,
+	pin::Pin,
+	sync::Arc,
+	time::{Duration, Instant},
+};
 
+use entity::sync_log::Model as SyncLog;
+use futures::future::{BoxFuture, FutureExt, Shared};
+use sea_orm::{ActiveValue, ColumnTrait, EntityTrait, QueryFilter, sea_query};
 use serde::{Deserialize, Serialize};
-use serde_json::json;
 use specta::Type;
-use tauri::{AppHandle, Manager, async_runtime::Mutex};
+use sqlx::types::chrono::Utc;
+use tauri::{AppHandle, Manager, async_runtime::Mutex, ipc::Channel};
+use tauri_plugin_http::reqwest;
 use tauri_specta::Event;
+use tokio_util::sync::CancellationToken;
 
-use crate::database::DatabaseState;
+use crate::{account, auth, connectivity, database::DatabaseState};
 
+pub type SharedSync = Shared<BoxFuture<'static, Result<bool, SyncError>>>;
+// resolves once a finished sync has been recorded in the sync log
+pub type SettledSync = Shared<BoxFuture<'static, ()>>;
+// starts a sync that was deferred while the host was unreachable
+pub type DeferredSync = Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>;
+
+pub const SYNC_ID_PREFIX: &str = "sync_task_";
+
+pub struct InFlightSync {
+	pub task: SharedSync,
+	pub cancel: CancellationToken,
+	pub settled: SettledSync,
+}
+
+// the state lock is only held while looking up or registering a task, never across the
+// sync itself, so unrelated syncs are free to run in parallel. when each sync last ran
+// (and how that went) is persisted in the sync_log table rather than kept here
 #[derive(Default)]
 pub struct SyncState {
-	pub tasks: HashMap<String, serde_json::Value>,
+	// syncs currently running by sync id, callers of the same id await the one shared
+	// future instead of starting a duplicate request
+	pub in_flight: HashMap<String, InFlightSync>,
+	// progress listeners by sync id, these can be registered before the sync starts and
+	// are dropped once it finishes
+	pub progress_channels: HashMap<String, Vec<Channel<SyncProgress>>>,
+	// syncs requested while offline by sync id, started once the host is reachable again
+	pub deferred: HashMap<String, DeferredSync>,
+}
+
+#[derive(Serialize, Deserialize, Type, Debug, Clone, Default)]
+pub struct SyncProgress {
+	pub items_done: u32,
+	pub items_total: u32,
+	pub bytes_downloaded: u64,
+	pub current_file: Option<String>,
+}
+
+// handed to each sync so it can report progress and check whether it's been cancelled
+#[derive(Clone)]
+pub struct SyncContext {
+	pub app_handle: AppHandle,
+	// the account the sync is running for
+	pub account_id: i32,
+	pub sync_id: String,
+	pub cancel: CancellationToken,
+}
+
+impl SyncContext {
+	pub async fn report(&self, progress: &SyncProgress) {
+		let sync_state = self.app_handle.state::<Mutex<SyncState>>();
+		let mut sync_state = sync_state.lock().await;
+		if let Some(channels) = sync_state.progress_channels.get_mut(&self.sync_id) {
+			// channels whose webview has gone away just get dropped
+			channels.retain(|channel| channel.send(progress.clone()).is_ok());
+		}
+	}
+
+	/// errors out if the sync has been cancelled, checked between downloads
+	pub fn check_cancelled(&self) -> Result<(), SyncError> {
+		if self.cancel.is_cancelled() {
+			return Err(SyncError::with_kind(
+				SyncErrorKind::Cancelled,
+				format!("Sync task {} was cancelled", self.sync_id),
+			));
+		}
+
+		Ok(())
+	}
+}
+
+/// every sync id of the account starts with this, i.e. `sync_task_account_1_`
+pub(crate) fn account_sync_prefix(account_id: i32) -> String {
+	format!("{}account_{}_", SYNC_ID_PREFIX, account_id)
+}
+
+/// matches every sync log of the account. sea-orm's starts_with doesn't escape the prefix,
+/// where each underscore would match any character (i.e. account 1 matching account 12)
+pub(crate) fn account_sync_logs(account_id: i32) -> sea_query::SimpleExpr {
+	let prefix = account_sync_prefix(account_id).replace('_', "\\_");
+	entity::sync_log::Column::Id.like(sea_query::LikeExpr::new(format!("{}%", prefix)).escape('\\'))
+}
+
+// the same sync runs separately for each account, so sync ids are namespaced by the account.
+// commands take the sync id with or without the prefix that sync tasks add
+pub(crate) fn normalise_sync_id(account_id: i32, sync_id: &str) -> String {
+	let prefix = account_sync_prefix(account_id);
+	if sync_id.starts_with(&prefix) {
+		return sync_id.to_string();
+	}
+
+	let sync_id = sync_id.strip_prefix(SYNC_ID_PREFIX).unwrap_or(sync_id);
+	format!("{}{}", prefix, sync_id)
+}
+
+/// when the sync last succeeded (as a unix timestamp of when it started), which makes for a
+/// safe "since" when asking moodle what changed
+pub async fn last_success_at(
+	app_handle: &AppHandle,
+	account_id: i32,
+	sync_id: &str,
+) -> Option<i64> {
+	let db_state = app_handle.state::<DatabaseState>();
+	entity::SyncLog::find_by_id(normalise_sync_id(account_id, sync_id))
+		.one(&db_state.0)
+		.await
+		.unwrap_or_else(|e| {
+			log::error!("Failed to query sync log for {}: {}", sync_id, e);
+			None
+		})
+		.and_then(|log| log.last_success_at)
+}
+
+/// forgets when the sync last succeeded, so it isn't throttled next time and anything
+/// asking moodle what changed since treats it as never synced
+pub async fn invalidate(app_handle: &AppHandle, account_id: i32, sync_id: &str) {
+	let db_state = app_handle.state::<DatabaseState>();
+	let result = entity::SyncLog::update_many()
+		.col_expr(
+			entity::sync_log::Column::LastSuccessAt,
+			sea_query::Expr::value(None::<i64>),
+		)
+		.filter(entity::sync_log::Column::Id.eq(normalise_sync_id(account_id, sync_id)))
+		.exec(&db_state.0)
+		.await;
+
+	if let Err(e) = result {
+		log::error!("Failed to invalidate sync log for {}: {}", sync_id, e);
+	}
+}
+
+// moodle error codes that mean our token is no longer any good
+const AUTH_ERROR_CODES: [&str; 2] = ["invalidtoken", "accessexception"];
+
+pub fn is_auth_error_code(error_code: &str) -> bool {
+	AUTH_ERROR_CODES.contains(&error_code)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Type)]
+pub enum SyncErrorKind {
+	// couldn't reach moodle, or it responded with an unsuccessful status
+	Network,
+	Auth,
+	// moodle responded with an error body
+	Moodle { errorcode: String },
+	// moodle responded with something we didn't expect
+	Parse,
+	// database or filesystem errors
+	Storage,
+	Unsupported,
+	Cancelled,
+	// the host is unreachable, the sync has been deferred until it's back
+	Offline,
+}
+
+impl SyncErrorKind {
+	/// transient errors are retried with backoff, anything else fails straight away
+	pub fn is_transient(&self) -> bool {
+		matches!(self, SyncErrorKind::Network | SyncErrorKind::Storage)
+	}
+
+	// walks the error chain for the first error we know how to classify
+	fn classify<'a>(chain: impl Iterator<Item = &'a (dyn std::error::Error + 'static)>) -> Self {
+		for cause in chain {
+			if cause.is::<reqwest::Error>() {
+				return SyncErrorKind::Network;
+			}
+
+			if cause.is::<sea_orm::DbErr>() || cause.is::<std::io::Error>() {
+				return SyncErrorKind::Storage;
+			}
+		}
+
+		SyncErrorKind::Parse
+	}
 }
 
 // todo: sync errors probably shouldn't be exclusive to modules, but im lazy
 #[derive(Serialize, Deserialize, Debug, Clone, Type)]
 pub struct SyncError {
+	pub kind: SyncErrorKind,
 	pub code: Option<String>,
 	pub module_id: Option<i32>,
 	pub message: String,
 }
 
-impl From<anyhow::Error> for SyncError {
-	fn from(error: anyhow::Error) -> Self {
+impl SyncError {
+	fn with_kind(kind: SyncErrorKind, message: String) -> Self {
 		SyncError {
+			kind,
 			code: None,
 			module_id: None,
-			message: error.to_string(),
+			message,
 		}
 	}
-}
 
-impl From<&dyn std::error::Error> for SyncError {
-	fn from(error: &dyn std::error::Error) -> Self {
-		SyncError {
-			code: None,
-			module_id: None,
-			message: error.to_string(),
-		}
+	pub fn network(message: String) -> Self {
+		Self::with_kind(SyncErrorKind::Network, message)
+	}
+
+	pub fn storage(message: String) -> Self {
+		Self::with_kind(SyncErrorKind::Storage, message)
+	}
+
+	pub fn unsupported(message: String) -> Self {
+		Self::with_kind(SyncErrorKind::Unsupported, message)
+	}
+
+	// the token was already turned down, so there's no point asking moodle again
+	pub fn reauth_required() -> Self {
+		Self::with_kind(
+			SyncErrorKind::Auth,
+			"Moodle no longer accepts the stored token, sign in again".to_string(),
+		)
+	}
+
+	pub fn signed_out() -> Self {
+		Self::with_kind(SyncErrorKind::Auth, "Not signed in to Moodle".to_string())
+	}
+
+	pub fn offline() -> Self {
+		Self::with_kind(
+			SyncErrorKind::Offline,
+			"Moodle host is unreachable".to_string(),
+		)
 	}
-}
 
-impl From<String> for SyncError {
-	fn from(message: String) -> Self {
+	/// an error returned in a moodle response body, i.e. `{"errorcode": "...", "message": "..."}`
+	pub fn moodle(error_code: String, message: String) -> Self {
+		let kind = if is_auth_error_code(&error_code) {
+			SyncErrorKind::Auth
+		} else {
+			SyncErrorKind::Moodle {
+				errorcode: error_code.clone(),
+			}
+		};
+
 		SyncError {
-			code: None,
+			kind,
+			code: Some(error_code),
 			module_id: None,
 			message,
 		}
 	}
+
+	/// a request that couldn't be built, classified by its cause so a client that failed to
+	/// set up is retried like any other network error
+	pub fn request(error: Box<dyn std::error::Error>) -> Self {
+		let chain = std::iter::successors(Some(error.as_ref()), |&cause| cause.source());
+		Self::with_kind(
+			SyncErrorKind::classify(chain),
+			format!("Failed to create request: {}", error),
+		)
+	}
+
+	pub fn for_module(mut self, module_id: i32) -> Self {
+		self.module_id = Some(module_id);
+		self
+	}
+}
+
+impl From<anyhow::Error> for SyncError {
+	fn from(error: anyhow::Error) -> Self {
+		// alternate formatting includes the causes, i.e. "Failed to insert course: <db error>"
+		Self::with_kind(
+			SyncErrorKind::classify(error.chain()),
+			format!("{:#}", error),
+		)
+	}
 }
 
 #[derive(Serialize, Deserialize, Type, Debug, Clone, Event)]
 pub struct SyncErrorEvent(SyncError);
 
+// emitted once a background sync has actually changed stored rows, so the ui can swap
+// in the newer content without waiting on (or re-triggering) a sync itself
+#[derive(Serialize, Deserialize, Type, Debug, Clone, Event)]
+pub struct ContentUpdatedEvent {
+	pub course_id: Option<i32>,
+	pub module_id: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Type, Debug, Clone)]
+pub struct SyncStatus {
+	pub log: SyncLog,
+	// hasn't successfully synced within the sync timeout, or ever
+	pub stale: bool,
+	// the most recent attempt failed
+	pub failing: bool,
+}
+
 #[macro_export]
 macro_rules! sync_return {
 	($self:expr, $db_state:expr) => {
 		$self
 			.return_fn
-			.take()
+			.as_ref()
 			.map(|f| f($db_state))
 			.unwrap_or_else(|| panic!("Sync task {} did not return a function", $self.sync_id))
 			.await
@@ -67,18 +323,24 @@ macro_rules! sync_return {
 }
 
 // 3 minutes
-const SYNC_TIMEOUT: u64 = 60 * 3;
+const SYNC_TIMEOUT: i64 = 60 * 3;
+const MAX_RETRIES: u32 = 3;
+// doubled after each retry, so 1s, 2s, 4s
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
 
 // todo: this could probably just be a macro
 pub struct SyncTask<T> {
 	pub app_handle: AppHandle,
+	pub account_id: i32,
 	pub sync_id: String,
+	pub update_event: Option<ContentUpdatedEvent>,
 	pub return_fn: Option<
 		Box<
-			dyn FnOnce(
+			dyn Fn(
 					tauri::State<'_, DatabaseState>,
 				) -> Pin<Box<dyn Future<Output = Result<T, Box<dyn std::error::Error>>> + Send>>
 				+ Send
+				+ Sync
 				+ 'static,
 		>,
 	>,
@@ -88,65 +350,571 @@ impl<T> SyncTask<T>
 where
 	T: Send + 'static,
 {
-	pub fn new(app: AppHandle, sync_id: String) -> Self {
+	pub fn new(app: AppHandle, account_id: i32, sync_id: String) -> Self {
 		Self {
 			app_handle: app,
-			sync_id: format!("sync_task_{}", sync_id),
+			account_id,
+			sync_id: normalise_sync_id(account_id, &sync_id),
+			update_event: None,
 			return_fn: None,
 		}
 	}
 
 	pub fn return_state<F>(mut self, return_fn: F) -> Self
 	where
-		F: FnOnce(
+		F: Fn(
 				tauri::State<'_, DatabaseState>,
 			) -> Pin<Box<dyn Future<Output = Result<T, Box<dyn std::error::Error>>> + Send>>
 			+ Send
+			+ Sync
 			+ 'static,
 	{
 		self.return_fn = Some(Box::new(return_fn));
 		self
 	}
 
-	pub async fn sync_state<F>(mut self, task_fn: F) -> Result<T, Box<dyn std::error::Error>>
+	/// event emitted when the sync reports that it changed stored rows
+	pub fn on_update(mut self, event: ContentUpdatedEvent) -> Self {
+		self.update_event = Some(event);
+		self
+	}
+
+	/// returns the stored state straight away and revalidates it in the background, only
+	/// waiting on the sync when there's nothing usable stored yet (i.e. first visit)
+	pub async fn sync_state<F>(self, task_fn: F) -> Result<T, Box<dyn std::error::Error>>
 	where
-		F: FnOnce(AppHandle) -> Pin<Box<dyn Future<Output = anyhow::Result<(), SyncError>> + Send>>
+		F: Fn(SyncContext) -> Pin<Box<dyn Future<Output = anyhow::Result<bool, SyncError>> + Send>>
 			+ Send
+			+ Sync
 			+ 'static,
 	{
-		let sync_state = self.app_handle.state::<Mutex<SyncState>>();
-		let mut sync_state = sync_state.lock().await;
 		let db_state = self.app_handle.state::<DatabaseState>();
-		let sync_entry = sync_state.tasks.get(&self.sync_id);
-		let now = std::time::SystemTime::now()
-			.duration_since(std::time::SystemTime::UNIX_EPOCH)
-			.unwrap()
-			.as_secs();
-		if sync_entry.is_some() {
-			let last_sync: u64 = sync_entry.unwrap().as_u64().unwrap();
-			if now - last_sync < SYNC_TIMEOUT {
-				return sync_return!(self, db_state);
+		let stored = match sync_return!(self, db_state) {
+			Ok(state) => Some(state),
+			Err(e) => {
+				log::debug!(
+					"No stored state for sync task {}, waiting on sync: {}",
+					self.sync_id,
+					e
+				);
+				None
 			}
+		};
+
+		let revalidate = revalidate(
+			self.app_handle.clone(),
+			self.account_id,
+			self.sync_id.clone(),
+			self.update_event.clone(),
+			task_fn,
+		);
+
+		if let Some(state) = stored {
+			tauri::async_runtime::spawn(revalidate);
+			return Ok(state);
 		}
 
-		match task_fn(self.app_handle.clone()).await {
-			Ok(_) => {
-				sync_state
-					.tasks
-					.insert(self.sync_id.to_string(), json!(now));
+		revalidate.await.ok();
+		let db_state = self.app_handle.state::<DatabaseState>();
+		sync_return!(self, db_state)
+	}
+
+	/// runs the sync without reading any state back, used when syncing outside of a command.
+	/// resolves to whether the sync changed anything, throttled syncs count as unchanged
+	pub async fn sync<F>(self, task_fn: F) -> Result<bool, SyncError>
+	where
+		F: Fn(SyncContext) -> Pin<Box<dyn Future<Output = anyhow::Result<bool, SyncError>> + Send>>
+			+ Send
+			+ Sync
+			+ 'static,
+	{
+		revalidate(
+			self.app_handle,
+			self.account_id,
+			self.sync_id,
+			self.update_event,
+			task_fn,
+		)
+		.await
+	}
+}
+
+async fn revalidate<F>(
+	app_handle: AppHandle,
+	account_id: i32,
+	sync_id: String,
+	update_event: Option<ContentUpdatedEvent>,
+	task_fn: F,
+) -> Result<bool, SyncError>
+where
+	F: Fn(SyncContext) -> Pin<Box<dyn Future<Output = anyhow::Result<bool, SyncError>> + Send>>
+		+ Send
+		+ Sync
+		+ 'static,
+{
+	revalidate_shared(
+		app_handle,
+		account_id,
+		sync_id,
+		update_event,
+		Arc::new(task_fn),
+	)
+	.await
+}
+
+// the task fn is shared so the same sync can be deferred and started again later
+async fn revalidate_shared<F>(
+	app_handle: AppHandle,
+	account_id: i32,
+	sync_id: String,
+	update_event: Option<ContentUpdatedEvent>,
+	task_fn: Arc<F>,
+) -> Result<bool, SyncError>
+where
+	F: Fn(SyncContext) -> Pin<Box<dyn Future<Output = anyhow::Result<bool, SyncError>> + Send>>
+		+ Send
+		+ Sync
+		+ 'static,
+{
+	let in_flight = {
+		let sync_state = app_handle.state::<Mutex<SyncState>>();
+		let sync_state = sync_state.lock().await;
+		sync_state
+			.in_flight
+			.get(&sync_id)
+			.map(|in_flight| in_flight.task.clone())
+	};
+
+	let task = match in_flight {
+		Some(task) => Some(task),
+		None => {
+			// looked up before taking the state lock, so starting one sync never waits on
+			// another's database round trip
+			let db_state = app_handle.state::<DatabaseState>();
+			let sync_log = entity::SyncLog::find_by_id(&sync_id)
+				.one(&db_state.0)
+				.await
+				.unwrap_or_else(|e| {
+					log::error!("Failed to query sync log for {}: {}", sync_id, e);
+					None
+				});
+
+			let now = Utc::now().timestamp();
+			// syncs that don't start never settle, so nothing else would drop their listeners
+			if let Some(last_success) = sync_log.as_ref().and_then(|log| log.last_success_at)
+				&& now - last_success < SYNC_TIMEOUT
+			{
+				drop_listeners(&app_handle, &sync_id).await;
+				return Ok(false);
 			}
-			Err(e) => {
-				log::error!(
-					"Error in sync task {}: (code: {:?}) {}",
-					self.sync_id,
-					e.code.as_deref().unwrap_or("unknown"),
+
+			// what's stored is all there is while signed out, i.e. kept as an offline library
+			if let Err(e) = account::credentials(&app_handle, account_id).await {
+				drop_listeners(&app_handle, &sync_id).await;
+				return Err(e);
+			}
+
+			// no point trying while offline, it's deferred below instead
+			if connectivity::is_online(&app_handle).not() {
+				drop_listeners(&app_handle, &sync_id).await;
+				None
+			} else {
+				Some(
+					start_in_flight(
+						&app_handle,
+						account_id,
+						&sync_id,
+						update_event.clone(),
+						task_fn.clone(),
+						sync_log,
+					)
+					.await,
+				)
+			}
+		}
+	};
+
+	let result = match task {
+		Some(task) => task.await,
+		None => Err(SyncError::offline()),
+	};
+
+	if let Err(e) = &result
+		&& e.kind == SyncErrorKind::Offline
+	{
+		defer(&app_handle, account_id, sync_id, update_event, task_fn).await;
+	}
+
+	result
+}
+
+// registers the sync as in flight, unless the same sync was started while we were looking
+// up its log, in which case that one is joined instead
+async fn start_in_flight<F>(
+	app_handle: &AppHandle,
+	account_id: i32,
+	sync_id: &str,
+	update_event: Option<ContentUpdatedEvent>,
+	task_fn: Arc<F>,
+	sync_log: Option<SyncLog>,
+) -> SharedSync
+where
+	F: Fn(SyncContext) -> Pin<Box<dyn Future<Output = anyhow::Result<bool, SyncError>> + Send>>
+		+ Send
+		+ Sync
+		+ 'static,
+{
+	let sync_state = app_handle.state::<Mutex<SyncState>>();
+	let mut sync_state = sync_state.lock().await;
+	if let Some(in_flight) = sync_state.in_flight.get(sync_id) {
+		return in_flight.task.clone();
+	}
+
+	let cancel = CancellationToken::new();
+	let ctx = SyncContext {
+		app_handle: app_handle.clone(),
+		account_id,
+		sync_id: sync_id.to_string(),
+		cancel: cancel.clone(),
+	};
+	let task = run_with_retry(task_fn, ctx).boxed().shared();
+	// bookkeeping is done by a separate task so it still happens if the caller that
+	// started the sync goes away before it finishes
+	let settled = settle(
+		app_handle.clone(),
+		account_id,
+		sync_id.to_string(),
+		update_event,
+		task.clone(),
+		sync_log,
+	)
+	.boxed()
+	.shared();
+	sync_state.in_flight.insert(
+		sync_id.to_string(),
+		InFlightSync {
+			task: task.clone(),
+			cancel,
+			settled: settled.clone(),
+		},
+	);
+
+	tauri::async_runtime::spawn(settled);
+	task
+}
+
+async fn drop_listeners(app_handle: &AppHandle, sync_id: &str) {
+	let sync_state = app_handle.state::<Mutex<SyncState>>();
+	let mut sync_state = sync_state.lock().await;
+	sync_state.progress_channels.remove(sync_id);
+}
+
+// only the first deferral of a sync id is kept, later ones would run the same sync
+async fn defer<F>(
+	app_handle: &AppHandle,
+	account_id: i32,
+	sync_id: String,
+	update_event: Option<ContentUpdatedEvent>,
+	task_fn: Arc<F>,
+) where
+	F: Fn(SyncContext) -> Pin<Box<dyn Future<Output = anyhow::Result<bool, SyncError>> + Send>>
+		+ Send
+		+ Sync
+		+ 'static,
+{
+	let app = app_handle.clone();
+	let id = sync_id.clone();
+	let resume: DeferredSync = Box::new(move || {
+		revalidate_shared(app, account_id, id, update_event, task_fn)
+			.map(|_| ())
+			.boxed()
+	});
+
+	{
+		let sync_state = app_handle.state::<Mutex<SyncState>>();
+		let mut sync_state = sync_state.lock().await;
+		sync_state.deferred.entry(sync_id).or_insert(resume);
+	}
+
+	// we might have come back online between checking and deferring, in which case
+	// nothing else would pick it up until the next time we go offline
+	if connectivity::is_online(app_handle) {
+		resume_deferred(app_handle).await;
+	}
+}
+
+/// starts every sync that was deferred while offline
+pub async fn resume_deferred(app_handle: &AppHandle) {
+	let deferred = {
+		let sync_state = app_handle.state::<Mutex<SyncState>>();
+		let mut sync_state = sync_state.lock().await;
+		std::mem::take(&mut sync_state.deferred)
+	};
+
+	if deferred.is_empty() {
+		return;
+	}
+
+	log::info!("Resuming {} deferred syncs", deferred.len());
+	for resume in deferred.into_values() {
+		tauri::async_runtime::spawn(resume());
+	}
+}
+
+// transient errors are retried within the same sync, so callers awaiting it only see the
+// final outcome. anything else (auth, unsupported, etc.) fails on the first attempt
+async fn run_with_retry<F>(task_fn: Arc<F>, ctx: SyncContext) -> Result<bool, SyncError>
+where
+	F: Fn(SyncContext) -> Pin<Box<dyn Future<Output = anyhow::Result<bool, SyncError>> + Send>>
+		+ Send
+		+ Sync
+		+ 'static,
+{
+	let mut retries = 0;
+	loop {
+		match task_fn(ctx.clone()).await {
+			Err(e) if e.kind.is_transient() && retries < MAX_RETRIES => {
+				// a network error might just mean we've lost the host, in which case the
+				// sync is deferred until it's back rather than retried
+				if e.kind == SyncErrorKind::Network && connectivity::check(&ctx.app_handle).await.not() {
+					return Err(SyncError::offline());
+				}
+
+				let delay = RETRY_BASE_DELAY * 2u32.pow(retries);
+				retries += 1;
+				log::warn!(
+					"Sync task {} failed ({:?}), retrying in {}ms ({}/{}): {}",
+					ctx.sync_id,
+					e.kind,
+					delay.as_millis(),
+					retries,
+					MAX_RETRIES,
 					e.message
 				);
-				SyncErrorEvent(e).emit(&self.app_handle).unwrap();
-				return sync_return!(self, db_state);
+
+				tokio::select! {
+					_ = tokio::time::sleep(delay) => {}
+					_ = ctx.cancel.cancelled() => ctx.check_cancelled()?,
+				}
 			}
-		};
+			result => return result,
+		}
+	}
+}
 
-		sync_return!(self, db_state)
+async fn settle(
+	app_handle: AppHandle,
+	account_id: i32,
+	sync_id: String,
+	update_event: Option<ContentUpdatedEvent>,
+	task: SharedSync,
+	sync_log: Option<SyncLog>,
+) {
+	let started_at = Utc::now().timestamp();
+	let timer = Instant::now();
+	let result = task.await;
+	let duration_ms = timer.elapsed().as_millis() as i64;
+
+	let sync_state = app_handle.state::<Mutex<SyncState>>();
+	let mut sync_state = sync_state.lock().await;
+	sync_state.in_flight.remove(&sync_id);
+	sync_state.progress_channels.remove(&sync_id);
+
+	let mut sync_log = entity::sync_log::ActiveModel {
+		id: ActiveValue::Set(sync_id.clone()),
+		last_success_at: ActiveValue::Set(sync_log.as_ref().and_then(|log| log.last_success_at)),
+		last_error_at: ActiveValue::Set(sync_log.as_ref().and_then(|log| log.last_error_at)),
+		last_error_code: ActiveValue::Set(
+			sync_log
+				.as_ref()
+				.and_then(|log| log.last_error_code.clone()),
+		),
+		last_error_message: ActiveValue::Set(
+			sync_log
+				.as_ref()
+				.and_then(|log| log.last_error_message.clone()),
+		),
+		duration_ms: ActiveValue::Set(duration_ms),
+		attempt_count: ActiveValue::Set(sync_log.map(|log| log.attempt_count).unwrap_or(0) + 1),
+	};
+
+	match result {
+		Ok(changed) => {
+			sync_log.last_success_at = ActiveValue::Set(Some(started_at));
+			if changed && let Some(event) = update_event {
+				event.emit(&app_handle).ok();
+			}
+		}
+		Err(e) if e.kind == SyncErrorKind::Cancelled => {
+			log::info!("Sync task {} was cancelled", sync_id);
+		}
+		// not really a failure, the sync is picked up again once we're back online
+		Err(e) if e.kind == SyncErrorKind::Offline => {
+			log::info!("Sync task {} deferred until online", sync_id);
+		}
+		Err(e) => {
+			log::error!(
+				"Error in sync task {}: (code: {:?}) {}",
+				sync_id,
+				e.code.as_deref().unwrap_or("unknown"),
+				e.message
+			);
+			sync_log.last_error_at = ActiveValue::Set(Some(started_at));
+			sync_log.last_error_code = ActiveValue::Set(e.code.clone());
+			sync_log.last_error_message = ActiveValue::Set(Some(e.message.clone()));
+			if e.kind == SyncErrorKind::Auth {
+				auth::require_reauth(&app_handle, account_id).await;
+			}
+			SyncErrorEvent(e).emit(&app_handle).unwrap();
+		}
+	};
+
+	let db_state = app_handle.state::<DatabaseState>();
+	entity::SyncLog::insert(sync_log)
+		.on_conflict(
+			sea_query::OnConflict::column(entity::sync_log::Column::Id)
+				.update_columns([
+					entity::sync_log::Column::LastSuccessAt,
+					entity::sync_log::Column::LastErrorAt,
+					entity::sync_log::Column::LastErrorCode,
+					entity::sync_log::Column::LastErrorMessage,
+					entity::sync_log::Column::DurationMs,
+					entity::sync_log::Column::AttemptCount,
+				])
+				.to_owned(),
+		)
+		.exec(&db_state.0)
+		.await
+		.map_err(|e| log::error!("Failed to update sync log for {}: {}", sync_id, e))
+		.ok();
+}
+
+#[tauri::command]
+#[specta::specta]
+pub async fn get_sync_status(app: AppHandle) -> Result<Vec<SyncStatus>, String> {
+	let account_id = account::require_active(&app)?;
+	let db_state = app.state::<DatabaseState>();
+	let now = Utc::now().timestamp();
+	let sync_logs = entity::SyncLog::find()
+		.filter(account_sync_logs(account_id))
+		.all(&db_state.0)
+		.await
+		.map_err(|e| e.to_string())?;
+
+	Ok(
+		sync_logs
+			.into_iter()
+			.map(|log| SyncStatus {
+				stale: log
+					.last_success_at
+					.is_none_or(|last_success| now - last_success >= SYNC_TIMEOUT),
+				failing: log.last_error_at.is_some_and(|last_error| {
+					log
+						.last_success_at
+						.is_none_or(|last_success| last_error > last_success)
+				}),
+				log,
+			})
+			.collect(),
+	)
+}
+
+/// listeners are dropped once the sync settles, or as soon as it's throttled or deferred
+/// without starting, so they should be registered right before starting the sync
+#[tauri::command]
+#[specta::specta]
+pub async fn watch_sync_progress(
+	app: AppHandle,
+	sync_id: String,
+	on_progress: Channel<SyncProgress>,
+) -> Result<(), String> {
+	let account_id = account::require_active(&app)?;
+	let sync_state = app.state::<Mutex<SyncState>>();
+	let mut sync_state = sync_state.lock().await;
+	sync_state
+		.progress_channels
+		.entry(normalise_sync_id(account_id, &sync_id))
+		.or_default()
+		.push(on_progress);
+	Ok(())
+}
+
+/// cancels an in-flight sync, resolving to false if there was nothing running to cancel
+#[tauri::command]
+#[specta::specta]
+pub async fn cancel_sync(app: AppHandle, sync_id: String) -> Result<bool, String> {
+	let account_id = account::require_active(&app)?;
+	let sync_state = app.state::<Mutex<SyncState>>();
+	let sync_state = sync_state.lock().await;
+	match sync_state
+		.in_flight
+		.get(&normalise_sync_id(account_id, &sync_id))
+	{
+		Some(in_flight) => {
+			in_flight.cancel.cancel();
+			Ok(true)
+		}
+		None => Ok(false),
+	}
+}
+
+/// cancels every in-flight sync and drops deferred ones, i.e. before something that can't
+/// have syncs writing under it. resolves once the cancelled syncs have settled
+pub async fn cancel_all(app_handle: &AppHandle) {
+	cancel_matching(app_handle, |_| true).await;
+}
+
+async fn cancel_matching(app_handle: &AppHandle, is_match: impl Fn(&str) -> bool) {
+	let settled = {
+		let sync_state = app_handle.state::<Mutex<SyncState>>();
+		let mut sync_state = sync_state.lock().await;
+		sync_state
+			.deferred
+			.retain(|sync_id, _| is_match(sync_id).not());
+		sync_state
+			.in_flight
+			.iter()
+			.filter(|(sync_id, _)| is_match(sync_id))
+			.map(|(_, in_flight)| {
+				in_flight.cancel.cancel();
+				in_flight.settled.clone()
+			})
+			.collect::<Vec<_>>()
+	};
+
+	// settling takes the state lock itself, so it's only awaited once ours is released
+	futures::future::join_all(settled).await;
+}
+
+/// cancels every in-flight sync of the account and drops its deferred ones, i.e. before
+/// signing out of it. resolves once the cancelled syncs have settled, so nothing they do
+/// lands after the account is gone
+pub async fn cancel_account(app_handle: &AppHandle, account_id: i32) {
+	let prefix = account_sync_prefix(account_id);
+	cancel_matching(app_handle, |sync_id| sync_id.starts_with(&prefix)).await;
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn normalise_sync_id_adds_account_prefix() {
+		assert_eq!(
+			normalise_sync_id(1, "course_2"),
+			"sync_task_account_1_course_2"
+		);
+		assert_eq!(
+			normalise_sync_id(1, "sync_task_course_2"),
+			"sync_task_account_1_course_2"
+		);
+	}
+
+	#[test]
+	fn normalise_sync_id_keeps_namespaced_ids() {
+		assert_eq!(
+			normalise_sync_id(1, "sync_task_account_1_course_2"),
+			"sync_task_account_1_course_2"
+		);
 	}
 }
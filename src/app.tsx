@@ -9,6 +9,7 @@ import { Index } from "./pages";
 import { Announcements } from "./pages/announcements";
 import { Course } from "./pages/course";
 import { Home } from "./pages/home";
+import { Unlock } from "./pages/unlock";
 import { Dialog, DialogBodySuccess, DialogBodyFailed } from "./components/dialog";
 import { AuthStatus } from "./types";
 
@@ -43,6 +44,9 @@ export function App() {
 					<Route path="/">
 						<Index />
 					</Route>
+					<Route path="/unlock">
+						<Unlock />
+					</Route>
 					<GlobalLayout>
 						<Route path="/home">
 							<Home />
@@ -53,9 +53,249 @@ async getModuleContent(courseId: number, moduleId: number) : Promise<Result<[Sec
     else return { status: "error", error: e  as any };
 }
 },
-async getContentBlobs(courseId: number, moduleId: number) : Promise<Result<ContentBlob[], string>> {
+async getContentBlobs(moduleId: number) : Promise<Result<ContentBlob[], string>> {
     try {
-    return { status: "ok", data: await TAURI_INVOKE("get_content_blobs", { courseId, moduleId }) };
+    return { status: "ok", data: await TAURI_INVOKE("get_content_blobs", { moduleId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getSyncStatus() : Promise<Result<SyncStatus[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_sync_status") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async pauseSyncScheduler() : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("pause_sync_scheduler") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async resumeSyncScheduler() : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("resume_sync_scheduler") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async setSyncInterval(intervalSecs: number, jitterSecs: number) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_sync_interval", { intervalSecs, jitterSecs }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async watchSyncProgress(syncId: string, onProgress: TAURI_CHANNEL<SyncProgress>) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("watch_sync_progress", { syncId, onProgress }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async cancelSync(syncId: string) : Promise<Result<boolean, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("cancel_sync", { syncId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getConnectivity() : Promise<Result<boolean, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_connectivity") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async checkConnectivity() : Promise<Result<boolean, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("check_connectivity") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async downloadCourseForOffline(courseId: number, onProgress: TAURI_CHANNEL<CourseDownloadProgress>) : Promise<Result<CourseDownloadProgress, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("download_course_for_offline", { courseId, onProgress }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async pruneStorage() : Promise<Result<PruneReport, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("prune_storage") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getStorageUsage() : Promise<Result<StorageUsage, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_storage_usage") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async setStorageCap(capBytes: bigint | null) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_storage_cap", { capBytes }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async setModulePinned(moduleId: number, pinned: boolean) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_module_pinned", { moduleId, pinned }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async setCoursePinned(courseId: number, pinned: boolean) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_course_pinned", { courseId, pinned }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async verifyStorage(checksum: boolean) : Promise<Result<VerifyReport, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("verify_storage", { checksum }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async setVerifyStorageOnStartup(enabled: boolean, checksum: boolean) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("set_verify_storage_on_startup", { enabled, checksum }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async listModuleVersions(moduleId: number) : Promise<Result<ModuleContentVersionInfo[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("list_module_versions", { moduleId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async diffModuleVersions(fromVersionId: number, toVersionId: number, format: DiffFormat) : Promise<Result<string, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("diff_module_versions", { fromVersionId, toVersionId, format }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async resetDatabase() : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("reset_database") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getEncryptionStatus() : Promise<Result<EncryptionStatus, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_encryption_status") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async unlockEncryption(source: KeySource) : Promise<Result<boolean, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("unlock_encryption", { source }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async enableEncryption(source: KeySource) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("enable_encryption", { source }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async disableEncryption() : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("disable_encryption") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async getReauthRequired() : Promise<Result<boolean, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("get_reauth_required") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async reauthenticate() : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("reauthenticate") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async logout(wipeData: boolean) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("logout", { wipeData }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async listAccounts() : Promise<Result<AccountInfo[], string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("list_accounts") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async switchAccount(accountId: number) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("switch_account", { accountId }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async loginWithCredentials(host: string, username: string, password: string) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("login_with_credentials", { host, username, password }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+async loginWithQr(source: QrLoginSource) : Promise<Result<null, string>> {
+    try {
+    return { status: "ok", data: await TAURI_INVOKE("login_with_qr", { source }) };
 } catch (e) {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
@@ -68,10 +308,16 @@ async getContentBlobs(courseId: number, moduleId: number) : Promise<Result<Conte
 
 export const events = __makeEvents__<{
 moodleAuthEvent: MoodleAuthEvent,
-syncErrorEvent: SyncErrorEvent
+syncErrorEvent: SyncErrorEvent,
+contentUpdatedEvent: ContentUpdatedEvent,
+connectivityEvent: ConnectivityEvent,
+reauthRequiredEvent: ReauthRequiredEvent
 }>({
 moodleAuthEvent: "moodle-auth-event",
-syncErrorEvent: "sync-error-event"
+syncErrorEvent: "sync-error-event",
+contentUpdatedEvent: "content-updated-event",
+connectivityEvent: "connectivity-event",
+reauthRequiredEvent: "reauth-required-event"
 })
 
 /** user-defined constants **/
@@ -81,23 +327,44 @@ export const SUPPORTED_RESOURCE_TYPES = ["application/pdf"] as const;
 
 /** user-defined types **/
 
+export type AccountInfo = { id: number; host: string; user_name: string | null; signed_in: boolean; reauth_required: boolean; active: boolean }
 export type AuthStatus = "Failed" | "Success" | "Aborted" | "Pending"
-export type ContentBlob = { name: string; moduleId: number; updatedAt: bigint; mimeType: string; path: string }
-export type Course = { id: number; name: string; moduleCount: number; colour: string | null; icon: string | null }
-export type CourseSection = { id: number; courseId: number; name: string }
+export type ConnectivityEvent = { online: boolean }
+export type ContentBlob = { accountId: number; name: string; moduleId: number; filePath: string; updatedAt: bigint; mimeType: string; path: string; hash: string | null; size: bigint | null; evictedAt: bigint | null }
+export type ContentUpdatedEvent = { course_id: number | null; module_id: number | null }
+export type Course = { accountId: number; id: number; name: string; moduleCount: number; colour: string | null; icon: string | null; pinned: boolean }
+export type CourseDownloadProgress = { estimated_bytes: bigint; modules_done: number; modules_total: number; failed_modules: number[] }
+export type CourseSection = { accountId: number; id: number; courseId: number; name: string; rank: number }
 export type CourseSectionWithModules = { section: CourseSection; modules: SectionModule[] }
+export type CourseStorageUsage = { course_id: number; pinned: boolean; blob_bytes: bigint; content_bytes: bigint; modules: ModuleStorageUsage[] }
 export type CourseWithSections = { course: Course; sections: CourseSectionWithModules[] }
-export type ModuleContent = { id: number; moduleId: number; updatedAt: bigint; rank: number; content: string }
+export type DiffFormat = "Text" | "Html"
+export type EncryptionStatus = { key_source: KeySourceKind | null; locked: boolean; available: boolean }
+export type KeySource = { Passphrase: string } | { KeyFile: string | null }
+export type KeySourceKind = "Passphrase" | "KeyFile"
+export type ModuleContent = { accountId: number; id: number; moduleId: number; updatedAt: bigint; rank: number; content: string }
+export type ModuleContentVersionInfo = { id: number; content_id: number; created_at: bigint; size: bigint }
+export type ModuleStorageUsage = { module_id: number; pinned: boolean; last_opened_at: bigint | null; blob_bytes: bigint; content_bytes: bigint; evicted_blobs: number }
 export type MoodleAuthEvent = AuthStatus
-export type SectionModule = { id: number; sectionId: number; name: string; updatedAt: bigint; mimeTypes?: string[]; moduleType: SectionModuleType }
+export type PruneReport = { courses: number; sections: number; modules: number; module_contents: number; module_content_versions: number; content_blobs: number; files: number; bytes_freed: bigint }
+export type QrLoginSource = { Payload: string } | { Image: string }
+export type ReauthRequiredEvent = { host: string | null }
+export type SectionModule = { accountId: number; id: number; sectionId: number; name: string; updatedAt: bigint; mimeTypes?: string[]; moduleType: SectionModuleType; pinned: boolean; lastOpenedAt: bigint | null; rank: number }
 export type SectionModuleType = "page" | "book" | "forum" | "resource" | "url" | "Unknown"
-export type SyncError = { code: string | null; module_id: number | null; message: string }
+export type StorageUsage = { cap_bytes: bigint | null; blob_bytes: bigint; content_bytes: bigint; courses: CourseStorageUsage[] }
+export type SyncError = { kind: SyncErrorKind; code: string | null; module_id: number | null; message: string }
 export type SyncErrorEvent = SyncError
+export type SyncErrorKind = "Network" | "Auth" | { Moodle: { errorcode: string } } | "Parse" | "Storage" | "Unsupported" | "Cancelled" | "Offline"
+export type SyncLog = { id: string; lastSuccessAt: bigint | null; lastErrorAt: bigint | null; lastErrorCode: string | null; lastErrorMessage: string | null; durationMs: bigint; attemptCount: number }
+export type SyncProgress = { items_done: number; items_total: number; bytes_downloaded: bigint; current_file: string | null }
+export type SyncStatus = { log: SyncLog; stale: boolean; failing: boolean }
+export type VerifyReport = { checked: number; missing: number; corrupt: number; queued_modules: number }
 
 /** tauri-specta globals **/
 
 import {
 	invoke as TAURI_INVOKE,
+	Channel as TAURI_CHANNEL,
 } from "@tauri-apps/api/core";
 import * as TAURI_API_EVENT from "@tauri-apps/api/event";
 import type { WebviewWindow as __WebviewWindow__ } from "@tauri-apps/api/webviewWindow";
@@ -6,7 +6,7 @@ import { SidebarIcon } from "./sidebar-icon";
 
 export function Sidebar() {
 	const [location] = useLocation();
-	const courses = useCommand(commands.getUserCourses);
+	const courses = useCommand(commands.getUserCourses, {});
 
 	return (
 		<div className="flex flex-col py-2 bg-steel-700 h-full min-w-14">
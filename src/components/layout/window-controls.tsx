@@ -2,12 +2,15 @@ import { getCurrentWebviewWindow } from "@tauri-apps/api/webviewWindow";
 import { useContext, useEffect, useState } from "react";
 import IconAlertSquare from "~icons/tabler/alert-square-filled";
 import IconLayoutSidebar from "~icons/tabler/layout-sidebar-filled";
+import IconLogout from "~icons/tabler/logout";
 import IconMinus from "~icons/tabler/minus";
 import IconSquare from "~icons/tabler/square";
+import IconTrash from "~icons/tabler/trash";
 import User from "~icons/tabler/user-filled";
+import IconUserPlus from "~icons/tabler/user-plus";
 import IconX from "~icons/tabler/x";
 import type { SyncError } from "../../bindings";
-import { events } from "../../bindings";
+import { commands, events } from "../../bindings";
 import { useLoginWindow } from "../../hooks/login-window";
 import { useUser } from "../../hooks/user";
 import { Button, ButtonStyle } from "../button";
@@ -16,6 +19,7 @@ import { ModuleContext } from "./module-context";
 import { SidebarContext } from "./sidebar-context";
 import { LoginContext } from "./login-context";
 import { AuthStatus } from "../../types";
+import { useLocation } from "wouter";
 
 export function WindowControls({ children }: { children: React.ReactNode }) {
 	const [sidebarCollapsed, setSidebarCollapsed] = useState(false);
@@ -23,13 +27,17 @@ export function WindowControls({ children }: { children: React.ReactNode }) {
 	const [moduleName, setModuleName] = useState<string | undefined>(undefined);
 	const [syncError, setSyncError] = useState<SyncError | undefined>(undefined);
 	const [moduleLoading, setModuleLoading] = useState(false);
+	const [online, setOnline] = useState(true);
+	const [reauthRequired, setReauthRequired] = useState(false);
 	const loginContext = useContext(LoginContext);
-	const { openLoginWindow, loading: loginLoading } = useLoginWindow();
-	const { userName, host } = useUser();
+	const { reauthenticate, loading: loginLoading } = useLoginWindow();
+	const { userName, host, accounts } = useUser();
+	const [, navigate] = useLocation();
+	const otherAccounts = accounts.filter((account) => !account.active);
 
-	const statusColour = moduleLoading ? "bg-steel-100" : syncError != null ? "bg-crimson" : "bg-accent";
-	const shouldReauthenticate =
-		host && loginContext?.authStatus !== AuthStatus.Success && syncError?.code === "invalidtoken";
+	const statusColour =
+		moduleLoading || !online ? "bg-steel-100" : syncError != null ? "bg-crimson" : "bg-accent";
+	const shouldReauthenticate = host && loginContext?.authStatus !== AuthStatus.Success && reauthRequired;
 
 	// biome-ignore lint/correctness/useExhaustiveDependencies: <explanation>
 	useEffect(() => {
@@ -47,8 +55,25 @@ export function WindowControls({ children }: { children: React.ReactNode }) {
 			}
 		});
 
+		commands.getConnectivity().then((result) => {
+			if (result.status === "ok") setOnline(result.data);
+		});
+
+		const connectivityUnlistenPromise = events.connectivityEvent.listen((event) => {
+			setOnline(event.payload.online);
+		});
+
+		// kept across restarts, so the prompt shows up again until the user signs in
+		commands.getReauthRequired().then((result) => {
+			if (result.status === "ok") setReauthRequired(result.data);
+		});
+
+		const reauthUnlistenPromise = events.reauthRequiredEvent.listen(() => setReauthRequired(true));
+
 		return () => {
 			errorUnlistenPromise.then((unlisten) => unlisten());
+			connectivityUnlistenPromise.then((unlisten) => unlisten());
+			reauthUnlistenPromise.then((unlisten) => unlisten());
 		};
 	}, []);
 
@@ -67,6 +92,7 @@ export function WindowControls({ children }: { children: React.ReactNode }) {
 							onClick={() => {}}
 							buttonStyle={ButtonStyle.BORDERLESS}
 							className="inline my-auto text-xs px-3 w-fit"
+							title={online ? undefined : "Offline, showing saved content"}
 						>
 							<div
 								className={`w-1.5 h-1.5 mr-2 rounded-full inline-block ${statusColour} ${moduleLoading && "animate-pulse"}`}
@@ -95,13 +121,35 @@ export function WindowControls({ children }: { children: React.ReactNode }) {
 										</span>
 									</div>
 								</Dropdown.ItemContainer>
+								{otherAccounts.length > 0 && (
+									<>
+										<Dropdown.Divider />
+										<Dropdown.ItemContainer>
+											{otherAccounts.map((account) => (
+												<Dropdown.Item
+													key={account.id}
+													onClick={() => commands.switchAccount(account.id)}
+													icon={User}
+													title={account.signed_in ? account.host : "Signed out, showing saved content"}
+												>
+													<div className="flex flex-col *:text-ellipsis *:overflow-hidden">
+														{account.user_name && <span>{account.user_name}</span>}
+														<span className="text-xs opacity-50">
+															{account.host ? new URL(account.host).hostname : "Unknown site"}
+														</span>
+													</div>
+												</Dropdown.Item>
+											))}
+										</Dropdown.ItemContainer>
+									</>
+								)}
 								{shouldReauthenticate && (
 									<>
 										<Dropdown.Divider />
 										<Dropdown.ItemContainer>
 											<Dropdown.Item
 												loading={loginLoading}
-												onClick={() => openLoginWindow(host)}
+												onClick={() => reauthenticate()}
 												icon={IconAlertSquare}
 												buttonStyle={ButtonStyle.CRIMSON}
 												title="Your session has expired, you'll need to reauthenticate with Moodle"
@@ -111,6 +159,31 @@ export function WindowControls({ children }: { children: React.ReactNode }) {
 										</Dropdown.ItemContainer>
 									</>
 								)}
+								<Dropdown.Divider />
+								<Dropdown.ItemContainer>
+									<Dropdown.Item
+										onClick={() => navigate("/")}
+										icon={IconUserPlus}
+										title="Sign in to another account, or another Moodle site"
+									>
+										Add account
+									</Dropdown.Item>
+									<Dropdown.Item
+										onClick={() => commands.logout(false)}
+										icon={IconLogout}
+										title="Sign out, keeping synced courses to read offline"
+									>
+										Sign out
+									</Dropdown.Item>
+									<Dropdown.Item
+										onClick={() => commands.logout(true)}
+										icon={IconTrash}
+										buttonStyle={ButtonStyle.CRIMSON}
+										title="Sign out and remove everything synced from this device"
+									>
+										Sign out and remove data
+									</Dropdown.Item>
+								</Dropdown.ItemContainer>
 							</Dropdown.Menu>
 						</Dropdown>
 					)}
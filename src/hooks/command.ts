@@ -1,5 +1,5 @@
 import { useEffect, useState } from "react";
-import type { Result } from "../bindings";
+import { type ContentUpdatedEvent, events, type Result } from "../bindings";
 
 export interface Command<T> {
 	data?: T;
@@ -7,8 +7,23 @@ export interface Command<T> {
 	loading?: boolean;
 }
 
+// the stored content a command reads from, used to skip updates that don't touch it
+export interface UpdateScope {
+	courseId?: number;
+	moduleId?: number;
+}
+
+// null ids mean everything changed (e.g. the database was reset), a course id without a
+// module id means the whole course
+function isAffected(scope: UpdateScope, event: ContentUpdatedEvent): boolean {
+	if (event.course_id == null) return true;
+	if (event.course_id !== scope.courseId) return false;
+	return event.module_id == null || event.module_id === scope.moduleId;
+}
+
 export function useCommand<T>(
 	command: (...args: any) => Promise<Result<T, unknown>>,
+	scope: UpdateScope,
 	...args: Parameters<typeof command>
 ): Command<T> {
 	const [commandValue, setCommandValue] = useState<T | undefined>();
@@ -29,8 +44,8 @@ export function useCommand<T>(
 		};
 
 		let cancelled = false;
-		const executeCommand = async () => {
-			setLoading(true);
+		const executeCommand = async (showLoading = true) => {
+			if (showLoading) setLoading(true);
 			setError(undefined);
 
 			try {
@@ -54,10 +69,17 @@ export function useCommand<T>(
 
 		setCommandValue(getCachedData());
 		executeCommand();
+
+		// commands return stored data straight away and sync in the background, so we swap in
+		// the newer data once the backend lets us know something actually changed
+		const updateUnlistenPromise = events.contentUpdatedEvent.listen(({ payload }) => {
+			if (isAffected(scope, payload)) executeCommand(false);
+		});
 		return () => {
 			cancelled = true;
+			updateUnlistenPromise.then((unlisten) => unlisten());
 		};
-	}, [command, ...args]);
+	}, [command, scope.courseId, scope.moduleId, ...args]);
 
 	return { data: commandValue, error, loading };
 }
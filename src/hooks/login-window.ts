@@ -1,6 +1,6 @@
 import { useContext, useEffect } from "react";
 import { events, commands } from "../bindings";
-import type { AuthStatus as AuthStatusPayload } from "../bindings";
+import type { AuthStatus as AuthStatusPayload, QrLoginSource, Result } from "../bindings";
 import { LoginContext } from "../components/layout/login-context";
 import { AuthStatus } from "../types";
 import { useLocation } from "wouter";
@@ -28,8 +28,31 @@ export function useLoginWindow() {
 	const openLoginWindow = async (host: string) => {
 		if (!host[0] || loginContext?.loading) return;
 		loginContext?.setLoading(true);
-		const loginResult = await commands.openLoginWindow(host);
+		handleLoginResult(await commands.openLoginWindow(host));
+	};
+
+	// for sites that let the mobile app sign in without the browser
+	const loginWithCredentials = async (host: string, username: string, password: string) => {
+		if (!host[0] || !username[0] || !password[0] || loginContext?.loading) return;
+		loginContext?.setLoading(true);
+		handleLoginResult(await commands.loginWithCredentials(host, username, password));
+	};
+
+	// the host comes from the code itself
+	const loginWithQr = async (source: QrLoginSource) => {
+		if (loginContext?.loading) return;
+		loginContext?.setLoading(true);
+		handleLoginResult(await commands.loginWithQr(source));
+	};
+
+	// signs in again to the host we're already using, keeping everything synced so far
+	const reauthenticate = async () => {
+		if (loginContext?.loading) return;
+		loginContext?.setLoading(true);
+		handleLoginResult(await commands.reauthenticate());
+	};
 
+	const handleLoginResult = (loginResult: Result<null, string>) => {
 		if (loginResult.status === "error") {
 			loginContext?.setLoading(false);
 			loginContext?.setShowDialog(true);
@@ -56,5 +79,5 @@ export function useLoginWindow() {
 		);
 	};
 
-	return { openLoginWindow, loading: loginContext?.loading };
+	return { openLoginWindow, loginWithCredentials, loginWithQr, reauthenticate, loading: loginContext?.loading };
 }
@@ -1,20 +1,24 @@
 import { useState, useEffect } from "react";
 import { commands } from "../bindings";
+import type { AccountInfo } from "../bindings";
 
 export function useUser() {
 	const [userName, setUserName] = useState<string | null>();
 	const [host, setHost] = useState<string | null>();
+	const [accounts, setAccounts] = useState<AccountInfo[]>([]);
 
 	useEffect(() => {
 		async function fetchUserData() {
 			const fetchedUser = await commands.getUserName();
 			const fetchedHost = await commands.getHost();
+			const fetchedAccounts = await commands.listAccounts();
 			if (fetchedUser.status !== "error") setUserName(fetchedUser.data);
 			if (fetchedHost.status !== "error") setHost(fetchedHost.data);
+			if (fetchedAccounts.status !== "error") setAccounts(fetchedAccounts.data);
 		}
 
 		fetchUserData();
 	}, []);
 
-	return { userName, host };
+	return { userName, host, accounts };
 }
@@ -32,7 +32,8 @@ const pdfOptions = {
 
 export function Course() {
 	const [match, params] = useRoute("/course/:courseId/:moduleId?");
-	const { data: courseData, error: _error, loading } = useCommand(commands.getCourse, Number(params?.courseId));
+	const courseId = Number(params?.courseId);
+	const { data: courseData, error: _error, loading } = useCommand(commands.getCourse, { courseId }, courseId);
 	const moduleContext = useContext(ModuleContext);
 
 	if (!match || params.courseId == null) navigate("/home", { replace: true });
@@ -110,8 +111,13 @@ function CourseModule({
 	courseId,
 	moduleId,
 }: { selectedModuleName?: string; courseData: CourseWithSections; courseId: number; moduleId: number }) {
-	const { data, error: _, loading } = useCommand(commands.getModuleContent, courseId, moduleId);
-	const { data: contentBlobs, loading: blobsLoading } = useCommand(commands.getContentBlobs, courseId, moduleId);
+	const { data, error: _, loading } = useCommand(
+		commands.getModuleContent,
+		{ courseId, moduleId },
+		courseId,
+		moduleId,
+	);
+	const { data: contentBlobs, loading: blobsLoading } = useCommand(commands.getContentBlobs, { courseId, moduleId }, moduleId);
 	const moduleContext = useContext(ModuleContext);
 
 	const [moduleData, moduleContent] = data || [];
@@ -155,7 +161,8 @@ function ResourceContentBlock({
 	// sometimes multiple blobs can exist for a resource module, if a previous one were replaced, so we should use the
 	// the file currently referred to in the module content
 	const contentBlob = contentBlobs?.find((blob) => blob.name === moduleContent[0]?.content);
-	const localPath = convertFileSrc(contentBlob?.path ?? "");
+	// served through the blob protocol, which opens blobs sealed by at-rest encryption
+	const localPath = convertFileSrc(contentBlob?.path ?? "", "content-blob");
 
 	if (contentBlob?.mimeType === "application/pdf") {
 		return (
@@ -1,17 +1,51 @@
-import { useState } from "react";
+import { getCurrentWebview } from "@tauri-apps/api/webview";
+import { useEffect, useState } from "react";
 import IconJourney from "~icons/journey/journey";
 import IconArrowRight from "~icons/tabler/arrow-right";
 import { Button } from "../components/button";
 import { Input } from "../components/input";
 import { Link } from "../components/link";
+import { commands } from "../bindings";
 import { useLoginWindow } from "../hooks/login-window";
 import { useVersion } from "../hooks/version";
 
 export function Index() {
-	const { openLoginWindow, loading } = useLoginWindow();
+	const { openLoginWindow, loginWithCredentials, loginWithQr, loading } = useLoginWindow();
 	const [host, setHost] = useState("");
+	const [method, setMethod] = useState<"browser" | "credentials" | "qr">("browser");
+	const [qrPayload, setQrPayload] = useState("");
+	const [username, setUsername] = useState("");
+	const [password, setPassword] = useState("");
+	const [hasLibrary, setHasLibrary] = useState(false);
 	const version = useVersion();
 
+	const login = () => {
+		if (method === "credentials") return loginWithCredentials(host, username, password);
+		if (method === "qr") return loginWithQr({ Payload: qrPayload });
+		return openLoginWindow(host);
+	};
+
+	// a picture of the qr code can be dropped onto the window instead of pasting its text
+	// biome-ignore lint/correctness/useExhaustiveDependencies: <explanation>
+	useEffect(() => {
+		if (method !== "qr") return;
+		const unlistenPromise = getCurrentWebview().onDragDropEvent((event) => {
+			if (event.payload.type !== "drop" || !event.payload.paths[0]) return;
+			loginWithQr({ Image: event.payload.paths[0] });
+		});
+
+		return () => {
+			unlistenPromise.then((unlisten) => unlisten());
+		};
+	}, [method]);
+
+	// courses kept after signing out can still be read, they just aren't synced anymore
+	useEffect(() => {
+		commands.getUserCourses().then((result) => {
+			if (result.status === "ok") setHasLibrary(result.data.length > 0);
+		});
+	}, []);
+
 	return (
 		<div className="flex flex-col justify-center items-center w-full space-y-6">
 			<div className="flex flex-row space-x-10 items-center justify-center">
@@ -34,29 +68,81 @@ export function Index() {
 				</div>
 			</div>
 			<div className="flex flex-col w-full max-w-1/4">
-				<span className="text-sm">Enter the host of your Moodle instance here.</span>
-				<div className="flex flex-row space-x-2 w-full items-center">
-					<Input
-						className="w-full"
-						type="url"
-						disabled={loading}
-						onChange={(value) => {
-							if (!value[0]) return;
+				{method === "qr" ? (
+					<>
+						<span className="text-sm">
+							Paste the text of the QR code from your Moodle profile, or drop a picture of it here.
+						</span>
+						<div className="flex flex-row space-x-2 w-full items-center">
+							<Input
+								className="w-full"
+								disabled={loading}
+								onChange={setQrPayload}
+								onEnter={login}
+								placeholder="moodlemobile://https://moodle.example.com?qrlogin=..."
+							/>
+							<Button onClick={login} loading={loading} disabled={!qrPayload[0]} className="px-4">
+								{loading == false && <IconArrowRight className="w-6 h-6" />}
+							</Button>
+						</div>
+					</>
+				) : (
+					<>
+						<span className="text-sm">Enter the host of your Moodle instance here.</span>
+						<div className="flex flex-row space-x-2 w-full items-center">
+							<Input
+								className="w-full"
+								type="url"
+								disabled={loading}
+								onChange={(value) => {
+									if (!value[0]) return;
 
-							try {
-								const parsed = new URL(value);
-								setHost(`${parsed.protocol}//${parsed.host}`);
-							} catch {
-								setHost("");
-							}
-						}}
-						onEnter={() => openLoginWindow(host)}
-						placeholder="https://moodle.example.com"
-					/>
-					<Button onClick={() => openLoginWindow(host)} loading={loading} disabled={!host[0]} className="px-4">
-						{loading == false && <IconArrowRight className="w-6 h-6" />}
-					</Button>
-				</div>
+									try {
+										const parsed = new URL(value);
+										setHost(`${parsed.protocol}//${parsed.host}`);
+									} catch {
+										setHost("");
+									}
+								}}
+								onEnter={login}
+								placeholder="https://moodle.example.com"
+							/>
+							<Button
+								onClick={login}
+								loading={loading}
+								disabled={!host[0] || (method === "credentials" && (!username[0] || !password[0]))}
+								className="px-4"
+							>
+								{loading == false && <IconArrowRight className="w-6 h-6" />}
+							</Button>
+						</div>
+					</>
+				)}
+				{method === "credentials" && (
+					<div className="flex flex-col space-y-2 mt-2">
+						<Input disabled={loading} onChange={setUsername} onEnter={login} placeholder="Username" />
+						<Input type="password" disabled={loading} onChange={setPassword} onEnter={login} placeholder="Password" />
+					</div>
+				)}
+				{(["browser", "credentials", "qr"] as const)
+					.filter((other) => other !== method)
+					.map((other) => (
+						<button
+							key={other}
+							type="button"
+							onClick={() => setMethod(other)}
+							className="text-sm text-steel-100 mt-2 hover:underline text-left w-fit cursor-pointer"
+						>
+							{other === "browser" && "Sign in through Moodle instead"}
+							{other === "credentials" && "Sign in with a username and password"}
+							{other === "qr" && "Sign in with a QR code"}
+						</button>
+					))}
+				{hasLibrary && (
+					<Link href="/home" className="text-sm text-steel-100 mt-2 hover:underline">
+						Browse offline library
+					</Link>
+				)}
 			</div>
 		</div>
 	);
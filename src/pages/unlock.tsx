@@ -0,0 +1,62 @@
+import { useEffect, useState } from "react";
+import { useLocation } from "wouter";
+import IconJourney from "~icons/journey/journey";
+import IconArrowRight from "~icons/tabler/arrow-right";
+import { commands } from "../bindings";
+import type { KeySourceKind } from "../bindings";
+import { Button } from "../components/button";
+import { Input } from "../components/input";
+
+export function Unlock() {
+	const [, navigate] = useLocation();
+	const [keySource, setKeySource] = useState<KeySourceKind | null>(null);
+	const [secret, setSecret] = useState("");
+	const [error, setError] = useState<string>();
+	const [loading, setLoading] = useState(false);
+
+	useEffect(() => {
+		commands.getEncryptionStatus().then((result) => {
+			if (result.status === "ok") setKeySource(result.data.key_source);
+		});
+	}, []);
+
+	const unlock = async () => {
+		if (loading) return;
+		setLoading(true);
+		// a key file path is optional, the one chosen when encryption was enabled is used otherwise
+		const result = await commands.unlockEncryption(
+			keySource === "KeyFile" ? { KeyFile: secret[0] ? secret : null } : { Passphrase: secret },
+		);
+
+		setLoading(false);
+		if (result.status === "error") return setError(result.error);
+		navigate(result.data ? "/home" : "/");
+	};
+
+	return (
+		<div className="flex flex-col justify-center items-center w-full space-y-6">
+			<IconJourney className="w-30 h-30 text-accent" />
+			<div className="flex flex-col w-full max-w-1/4">
+				<span className="text-sm">
+					{keySource === "KeyFile"
+						? "Your key file couldn't be read, enter where it can be found."
+						: "Enter your passphrase to unlock your courses."}
+				</span>
+				<div className="flex flex-row space-x-2 w-full items-center">
+					<Input
+						className="w-full"
+						type={keySource === "KeyFile" ? "text" : "password"}
+						disabled={loading}
+						onChange={(value) => setSecret(value)}
+						onEnter={unlock}
+						placeholder={keySource === "KeyFile" ? "/path/to/journey.key" : "Passphrase"}
+					/>
+					<Button onClick={unlock} loading={loading} disabled={keySource === "Passphrase" && !secret[0]} className="px-4">
+						{loading == false && <IconArrowRight className="w-6 h-6" />}
+					</Button>
+				</div>
+				{error && <span className="text-sm text-crimson">{error}</span>}
+			</div>
+		</div>
+	);
+}